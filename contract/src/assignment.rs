@@ -0,0 +1,320 @@
+//! Assigning tasks to other accounts.
+//!
+//! Whoever may change a task can assign it to another account, which then accepts or declines
+//! the assignment. Once accepted, the assignee may change the status of the task but nothing
+//! else. Declining removes the assignment, so the task can be assigned to someone else.
+
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::{env, near_bindgen, AccountId};
+
+use crate::events::AssignmentData;
+use crate::*;
+
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, BorshDeserialize, BorshSerialize, Serialize, Deserialize,
+)]
+#[serde(crate = "near_sdk::serde", rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum AssignmentStatus {
+    /// Waiting for the assignee to accept or decline.
+    Pending,
+    Accepted,
+}
+
+#[derive(Debug, Clone, PartialEq, BorshDeserialize, BorshSerialize, Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct Assignment {
+    pub account_id: AccountId,
+    pub status: AssignmentStatus,
+}
+
+impl Task {
+    /// Whether the account accepted the assignment of this task.
+    pub(crate) fn is_accepted_by(&self, account_id: &AccountId) -> bool {
+        self.assignee.as_ref().is_some_and(|assignee| {
+            assignee.account_id == *account_id && assignee.status == AssignmentStatus::Accepted
+        })
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    // Public method - returns a page of the active tasks assigned to the given account,
+    // including assignments it hasn't accepted yet
+    pub fn get_assigned_tasks(
+        &self,
+        account_id: AccountId,
+        from_index: Option<u64>,
+        limit: Option<u64>,
+    ) -> Vec<Task> {
+        self.assigned_by_account
+            .page(&account_id, from_index, limit)
+            .into_iter()
+            .map(|t| self.tasks.get(&t).unwrap())
+            .collect()
+    }
+
+    // Public method - returns the number of active tasks assigned to the given account
+    pub fn get_assigned_task_count(&self, account_id: AccountId) -> u64 {
+        self.assigned_by_account.len(&account_id)
+    }
+
+    // Public method - assign a task to another account, replacing the current assignee
+    // The assignment is pending until the assignee accepts it. Storage is charged against
+    // the task owner's storage balance, like in `rename_task`.
+    #[payable]
+    #[handle_result]
+    pub fn assign_task(
+        &mut self,
+        task_id: TaskId,
+        account_id: AccountId,
+    ) -> Result<(), ContractError> {
+        let initial_storage_usage = env::storage_usage();
+        let mut task = self.editable_task(task_id)?;
+        if self.is_bounty_locked(&task) {
+            return Err(ContractError::BountyLocked(task_id));
+        }
+        let mut changes = Vec::new();
+        if let Some(previous) = task.assignee.take() {
+            if previous.account_id == account_id {
+                return Err(ContractError::AlreadyAssigned {
+                    task_id,
+                    account_id,
+                });
+            }
+            self.assigned_by_account
+                .remove(&previous.account_id, task_id);
+            TaskEvent::TaskUnassigned(vec![AssignmentData::new(&task, previous.account_id)]).emit();
+            changes.push(TaskChange::Unassigned);
+        }
+        task.assignee = Some(Assignment {
+            account_id: account_id.clone(),
+            status: AssignmentStatus::Pending,
+        });
+        task.touch();
+        self.tasks.insert(&task_id, &task);
+        self.assigned_by_account.insert(&account_id, task_id);
+        changes.push(TaskChange::Assigned {
+            account_id: account_id.clone(),
+        });
+        self.record_history(&task, changes);
+        TaskEvent::TaskAssigned(vec![AssignmentData::new(&task, account_id)]).emit();
+        self.settle_storage_of(&task.owner_id, initial_storage_usage);
+        Ok(())
+    }
+
+    // Public method - remove the assignee of a task
+    // Not possible while the assignee's bounty is locked, see `bounty`.
+    #[handle_result]
+    pub fn unassign_task(&mut self, task_id: TaskId) -> Result<(), ContractError> {
+        let initial_storage_usage = env::storage_usage();
+        let mut task = self.editable_task(task_id)?;
+        if self.is_bounty_locked(&task) {
+            return Err(ContractError::BountyLocked(task_id));
+        }
+        let assignee = task
+            .assignee
+            .take()
+            .ok_or(ContractError::NotAssigned(task_id))?;
+        self.remove_assignment(
+            &mut task,
+            assignee.account_id.clone(),
+            TaskChange::Unassigned,
+        );
+        TaskEvent::TaskUnassigned(vec![AssignmentData::new(&task, assignee.account_id)]).emit();
+        self.settle_storage_of(&task.owner_id, initial_storage_usage);
+        Ok(())
+    }
+
+    // Public method - accept a task assigned to the caller
+    #[handle_result]
+    pub fn accept_task(&mut self, task_id: TaskId) -> Result<(), ContractError> {
+        let initial_storage_usage = env::storage_usage();
+        let mut task = self.assigned_task(task_id)?;
+        let assignee = task.assignee.as_mut().unwrap();
+        if assignee.status == AssignmentStatus::Accepted {
+            return Err(ContractError::AlreadyAccepted(task_id));
+        }
+        assignee.status = AssignmentStatus::Accepted;
+        let account_id = assignee.account_id.clone();
+        task.touch();
+        self.tasks.insert(&task_id, &task);
+        self.record_history(&task, vec![TaskChange::AssignmentAccepted]);
+        TaskEvent::AssignmentAccepted(vec![AssignmentData::new(&task, account_id)]).emit();
+        self.settle_storage_of(&task.owner_id, initial_storage_usage);
+        Ok(())
+    }
+
+    // Public method - decline a task assigned to the caller, or give back an accepted one
+    #[handle_result]
+    pub fn decline_task(&mut self, task_id: TaskId) -> Result<(), ContractError> {
+        let initial_storage_usage = env::storage_usage();
+        let mut task = self.assigned_task(task_id)?;
+        let account_id = task.assignee.take().unwrap().account_id;
+        self.remove_assignment(
+            &mut task,
+            account_id.clone(),
+            TaskChange::AssignmentDeclined,
+        );
+        TaskEvent::AssignmentDeclined(vec![AssignmentData::new(&task, account_id)]).emit();
+        self.settle_storage_of(&task.owner_id, initial_storage_usage);
+        Ok(())
+    }
+}
+
+impl Contract {
+    /// Loads an active task assigned to the predecessor.
+    fn assigned_task(&self, task_id: TaskId) -> Result<Task, ContractError> {
+        let task = self
+            .tasks
+            .get(&task_id)
+            .ok_or(ContractError::TaskNotFound(task_id))?;
+        let account_id = env::predecessor_account_id();
+        if task
+            .assignee
+            .as_ref()
+            .is_some_and(|assignee| assignee.account_id == account_id)
+        {
+            Ok(task)
+        } else {
+            Err(ContractError::NotAssignee {
+                task_id,
+                account_id,
+            })
+        }
+    }
+
+    /// Stores the task after its assignee was taken out.
+    fn remove_assignment(&mut self, task: &mut Task, account_id: AccountId, change: TaskChange) {
+        task.touch();
+        self.tasks.insert(&task.id, task);
+        self.assigned_by_account.remove(&account_id, task.id);
+        self.record_history(task, vec![change]);
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use super::*;
+    use near_sdk::test_utils::{accounts, VMContextBuilder};
+    use near_sdk::{testing_env, ONE_NEAR};
+
+    fn set_predecessor(account_id: AccountId) {
+        let mut context = VMContextBuilder::new();
+        context
+            .predecessor_account_id(account_id)
+            .account_balance(10 * ONE_NEAR)
+            .attached_deposit(ONE_NEAR / 100);
+        testing_env!(context.build());
+    }
+
+    // alice assigns a task to bob
+    fn setup() -> (Contract, TaskId) {
+        set_predecessor(accounts(0));
+        let mut contract = Contract::default();
+        let task_id = contract.insert_task(String::from("task_a"));
+        contract.assign_task(task_id, accounts(1)).unwrap();
+        (contract, task_id)
+    }
+
+    fn assignee(contract: &Contract, task_id: TaskId) -> Option<Assignment> {
+        contract.get_task(task_id).unwrap().assignee
+    }
+
+    #[test]
+    fn assignee_accepts_and_finishes_a_task() {
+        let (mut contract, task_id) = setup();
+        assert_eq!(
+            contract.get_assigned_tasks(accounts(1), None, None).len(),
+            1
+        );
+
+        set_predecessor(accounts(1));
+        // Pending assignees can't change the task yet.
+        assert!(contract
+            .update_task(task_id, TaskStatus::Done, None)
+            .is_err());
+        contract.accept_task(task_id).unwrap();
+        assert_eq!(
+            contract.accept_task(task_id),
+            Err(ContractError::AlreadyAccepted(task_id))
+        );
+        assert_eq!(
+            assignee(&contract, task_id),
+            Some(Assignment {
+                account_id: accounts(1),
+                status: AssignmentStatus::Accepted
+            })
+        );
+        contract
+            .update_task(task_id, TaskStatus::Done, None)
+            .unwrap();
+        // Only the status, nothing else.
+        assert_eq!(
+            contract.rename_task(task_id, String::from("task_b"), None),
+            Err(ContractError::NotTaskOwner {
+                task_id,
+                account_id: accounts(1)
+            })
+        );
+        let task = contract.get_task(task_id).unwrap();
+        assert_eq!(task.task_status, TaskStatus::Done);
+        assert_eq!(task.updated_by, accounts(1));
+    }
+
+    #[test]
+    fn declining_removes_the_assignment() {
+        let (mut contract, task_id) = setup();
+
+        set_predecessor(accounts(2));
+        assert_eq!(
+            contract.decline_task(task_id),
+            Err(ContractError::NotAssignee {
+                task_id,
+                account_id: accounts(2)
+            })
+        );
+        set_predecessor(accounts(1));
+        contract.decline_task(task_id).unwrap();
+        assert_eq!(assignee(&contract, task_id), None);
+        assert_eq!(contract.get_assigned_task_count(accounts(1)), 0);
+    }
+
+    #[test]
+    fn reassigning_replaces_the_assignee() {
+        let (mut contract, task_id) = setup();
+        assert_eq!(
+            contract.assign_task(task_id, accounts(1)),
+            Err(ContractError::AlreadyAssigned {
+                task_id,
+                account_id: accounts(1)
+            })
+        );
+        contract.assign_task(task_id, accounts(2)).unwrap();
+        assert!(contract
+            .get_assigned_tasks(accounts(1), None, None)
+            .is_empty());
+        assert_eq!(
+            contract.get_assigned_tasks(accounts(2), None, None).len(),
+            1
+        );
+
+        contract.unassign_task(task_id).unwrap();
+        assert_eq!(assignee(&contract, task_id), None);
+        assert_eq!(
+            contract.unassign_task(task_id),
+            Err(ContractError::NotAssigned(task_id))
+        );
+    }
+
+    #[test]
+    fn archived_tasks_leave_the_assigned_view() {
+        let (mut contract, task_id) = setup();
+        contract.archive_task(task_id).unwrap();
+        assert_eq!(contract.get_assigned_task_count(accounts(1)), 0);
+        contract.restore_task(task_id).unwrap();
+        assert_eq!(contract.get_assigned_task_count(accounts(1)), 1);
+        contract.delete_task(task_id).unwrap();
+        assert_eq!(contract.get_assigned_task_count(accounts(1)), 0);
+    }
+}
@@ -0,0 +1,270 @@
+//! Non-transferable [NEP-171] completion badges with [NEP-177] metadata.
+//!
+//! Once a task is `DONE`, its owner can claim a badge for it with `claim_completion_badge`. The
+//! token id is the task id, and the metadata records the task and the time it was completed.
+//! Badges are soulbound: `nft_transfer` and `nft_transfer_call` always fail. They outlive the
+//! task, are charged to the owner's storage balance and are burned when the owner unregisters.
+//!
+//! [NEP-171]: https://nomicon.io/Standards/Tokens/NonFungibleToken/Core
+//! [NEP-177]: https://nomicon.io/Standards/Tokens/NonFungibleToken/Metadata
+
+use near_contract_standards::non_fungible_token::core::NonFungibleTokenCore;
+use near_contract_standards::non_fungible_token::events::{NftBurn, NftMint};
+use near_contract_standards::non_fungible_token::metadata::{
+    NFTContractMetadata, NonFungibleTokenMetadataProvider, TokenMetadata, NFT_METADATA_SPEC,
+};
+use near_contract_standards::non_fungible_token::{Token, TokenId};
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::{U128, U64};
+use near_sdk::serde_json::json;
+use near_sdk::{env, near_bindgen, AccountId, PromiseOrValue};
+
+use crate::*;
+
+#[derive(Debug, Clone, PartialEq, BorshDeserialize, BorshSerialize)]
+pub struct Badge {
+    pub owner_id: AccountId,
+    /// Name of the task when the badge was claimed.
+    pub task_name: String,
+    /// Block timestamp in nanoseconds the task was completed at.
+    pub completed_at: U64,
+}
+
+impl Badge {
+    fn to_token(&self, task_id: TaskId) -> Token {
+        Token {
+            token_id: task_id.to_string(),
+            owner_id: self.owner_id.clone(),
+            metadata: Some(TokenMetadata {
+                title: Some(format!("Completed: {}", self.task_name)),
+                description: Some(format!("Task {} was completed", task_id)),
+                media: None,
+                media_hash: None,
+                copies: Some(1),
+                // Milliseconds, like most NEP-177 tokens
+                issued_at: Some((self.completed_at.0 / 1_000_000).to_string()),
+                expires_at: None,
+                starts_at: None,
+                updated_at: None,
+                extra: Some(
+                    json!({ "task_id": task_id, "completed_at": self.completed_at }).to_string(),
+                ),
+                reference: None,
+                reference_hash: None,
+            }),
+            approved_account_ids: None,
+        }
+    }
+}
+
+#[near_bindgen]
+impl NonFungibleTokenCore for Contract {
+    // Public method - always fails, completion badges can't be transferred
+    #[payable]
+    fn nft_transfer(
+        &mut self,
+        receiver_id: AccountId,
+        token_id: TokenId,
+        approval_id: Option<u64>,
+        memo: Option<String>,
+    ) {
+        let _ = (receiver_id, approval_id, memo);
+        panic!("{}", ContractError::BadgeNotTransferable(token_id));
+    }
+
+    // Public method - always fails, completion badges can't be transferred
+    #[payable]
+    fn nft_transfer_call(
+        &mut self,
+        receiver_id: AccountId,
+        token_id: TokenId,
+        approval_id: Option<u64>,
+        memo: Option<String>,
+        msg: String,
+    ) -> PromiseOrValue<bool> {
+        let _ = (receiver_id, approval_id, memo, msg);
+        panic!("{}", ContractError::BadgeNotTransferable(token_id));
+    }
+
+    // Public method - returns the badge with the given token id, which is the task id
+    fn nft_token(&self, token_id: TokenId) -> Option<Token> {
+        let task_id: TaskId = token_id.parse().ok()?;
+        self.badges
+            .get(&task_id)
+            .map(|badge| badge.to_token(task_id))
+    }
+}
+
+#[near_bindgen]
+impl NonFungibleTokenMetadataProvider for Contract {
+    fn nft_metadata(&self) -> NFTContractMetadata {
+        NFTContractMetadata {
+            spec: NFT_METADATA_SPEC.to_string(),
+            name: String::from("Task completion badges"),
+            symbol: String::from("DONE"),
+            icon: None,
+            base_uri: None,
+            reference: None,
+            reference_hash: None,
+        }
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    // Public method - returns the number of badges of the given account, like NEP-181
+    pub fn nft_supply_for_owner(&self, account_id: AccountId) -> U128 {
+        U128(self.badges_by_account.len(&account_id).into())
+    }
+
+    // Public method - returns a page of the badges of the given account, like NEP-181
+    pub fn nft_tokens_for_owner(
+        &self,
+        account_id: AccountId,
+        from_index: Option<U128>,
+        limit: Option<u64>,
+    ) -> Vec<Token> {
+        let from_index = from_index.map(|from_index| from_index.0 as u64);
+        self.badges_by_account
+            .page(&account_id, from_index, limit)
+            .into_iter()
+            .map(|task_id| self.badges.get(&task_id).unwrap().to_token(task_id))
+            .collect()
+    }
+
+    // Public method - mint the completion badge of one of the caller's done tasks
+    // Archived tasks qualify as well. Storage is handled like in `insert_task`.
+    #[payable]
+    #[handle_result]
+    pub fn claim_completion_badge(&mut self, task_id: TaskId) -> Result<Token, ContractError> {
+        let task = self
+            .tasks
+            .get(&task_id)
+            .or_else(|| self.archived_tasks.get(&task_id))
+            .ok_or(ContractError::TaskNotFound(task_id))?;
+        let account_id = env::predecessor_account_id();
+        if task.owner_id != account_id {
+            return Err(ContractError::NotTaskOwner {
+                task_id,
+                account_id,
+            });
+        }
+        let completed_at = task
+            .completed_at
+            .filter(|_| task.task_status == TaskStatus::Done)
+            .ok_or(ContractError::TaskNotDone(task_id))?;
+        if self.badges.get(&task_id).is_some() {
+            return Err(ContractError::BadgeAlreadyClaimed(task_id));
+        }
+        let initial_storage_usage = env::storage_usage();
+        let badge = Badge {
+            owner_id: account_id,
+            task_name: task.task_name,
+            completed_at,
+        };
+        self.badges.insert(&task_id, &badge);
+        self.badges_by_account.insert(&badge.owner_id, task_id);
+        NftMint {
+            owner_id: &badge.owner_id,
+            token_ids: &[&task_id.to_string()],
+            memo: None,
+        }
+        .emit();
+        self.settle_storage(initial_storage_usage);
+        Ok(badge.to_token(task_id))
+    }
+}
+
+impl Contract {
+    /// Burns all badges of an account that unregisters.
+    pub(crate) fn burn_badges_of(&mut self, account_id: &AccountId) {
+        let task_ids = self.badges_by_account.remove_all(account_id);
+        if task_ids.is_empty() {
+            return;
+        }
+        for task_id in &task_ids {
+            self.badges.remove(task_id);
+        }
+        let token_ids: Vec<String> = task_ids.iter().map(ToString::to_string).collect();
+        NftBurn {
+            owner_id: account_id,
+            token_ids: &token_ids.iter().map(String::as_str).collect::<Vec<_>>(),
+            authorized_id: None,
+            memo: None,
+        }
+        .emit();
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use super::*;
+    use near_sdk::test_utils::{accounts, VMContextBuilder};
+    use near_sdk::{testing_env, ONE_NEAR};
+
+    fn set_context(account_id: AccountId, block_timestamp: u64) {
+        let mut context = VMContextBuilder::new();
+        context
+            .predecessor_account_id(account_id)
+            .account_balance(10 * ONE_NEAR)
+            .attached_deposit(ONE_NEAR / 100)
+            .block_timestamp(block_timestamp);
+        testing_env!(context.build());
+    }
+
+    #[test]
+    fn owner_claims_a_badge_for_a_done_task() {
+        set_context(accounts(0), 1_000);
+        let mut contract = Contract::default();
+        let task_id = contract.insert_task(String::from("task_a"));
+        assert_eq!(
+            contract.claim_completion_badge(task_id).err(),
+            Some(ContractError::TaskNotDone(task_id))
+        );
+
+        set_context(accounts(0), 5_000_000);
+        contract
+            .update_task(task_id, TaskStatus::Done, None)
+            .unwrap();
+        set_context(accounts(1), 6_000_000);
+        assert!(contract.claim_completion_badge(task_id).is_err());
+
+        set_context(accounts(0), 6_000_000);
+        let token = contract.claim_completion_badge(task_id).unwrap();
+        assert_eq!(token.token_id, "0");
+        assert_eq!(token.owner_id, accounts(0));
+        let metadata = token.metadata.unwrap();
+        assert_eq!(metadata.title.as_deref(), Some("Completed: task_a"));
+        assert_eq!(metadata.issued_at.as_deref(), Some("5"));
+        assert_eq!(
+            metadata.extra.as_deref(),
+            Some(r#"{"completed_at":"5000000","task_id":0}"#)
+        );
+        assert_eq!(
+            contract.claim_completion_badge(task_id).err(),
+            Some(ContractError::BadgeAlreadyClaimed(task_id))
+        );
+
+        // The badge outlives the task.
+        contract.delete_task(task_id).unwrap();
+        assert!(contract.nft_token(String::from("0")).is_some());
+        assert_eq!(contract.nft_supply_for_owner(accounts(0)), U128(1));
+        assert_eq!(
+            contract.nft_tokens_for_owner(accounts(0), None, None).len(),
+            1
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Completion badge 0 can't be transferred")]
+    fn badges_are_not_transferable() {
+        set_context(accounts(0), 1_000);
+        let mut contract = Contract::default();
+        let task_id = contract.insert_task(String::from("task_a"));
+        contract
+            .update_task(task_id, TaskStatus::Done, None)
+            .unwrap();
+        contract.claim_completion_badge(task_id).unwrap();
+        contract.nft_transfer(accounts(1), String::from("0"), None, None);
+    }
+}
@@ -0,0 +1,240 @@
+//! Batch versions of `create_task`, `patch_task` and `delete_task`.
+//!
+//! Every item is validated and applied in order, exactly like the single-task method would.
+//! If an item fails, the call fails with `BatchItemFailed` naming it, and the runtime reverts
+//! the changes of the items before it, so a batch is applied completely or not at all. The
+//! events of a batch are logged once per kind, with the data of all its tasks. A single event for
+//! the whole batch isn't possible: every event kind has its own data, e.g. `status_changed` and
+//! `task_updated` for `update_tasks`, and indexers following one kind must see all of its changes.
+
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::{env, near_bindgen};
+
+use crate::events;
+use crate::*;
+
+/// Maximum number of items of a batch, which keeps a batch of the most expensive items well
+/// within the gas limit of a call.
+pub const MAX_BATCH_SIZE: usize = 20;
+
+/// An item of `update_tasks`: the id of the task next to the fields of its `TaskPatch`, e.g.
+/// `{"task_id": 3, "task_status": "DONE"}`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct TaskUpdate {
+    pub task_id: TaskId,
+    #[serde(flatten)]
+    pub patch: TaskPatch,
+}
+
+fn ensure_batch_size(count: usize) -> Result<(), ContractError> {
+    if count > MAX_BATCH_SIZE {
+        return Err(ContractError::BatchTooLarge {
+            count,
+            max_count: MAX_BATCH_SIZE,
+        });
+    }
+    Ok(())
+}
+
+fn item_failed(index: usize) -> impl FnOnce(ContractError) -> ContractError {
+    move |error| ContractError::BatchItemFailed {
+        index,
+        error: Box::new(error),
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    // Public method - insert several new tasks and return their ids, in order
+    // Storage is handled like in `insert_task`, for all tasks at once.
+    #[payable]
+    #[handle_result]
+    pub fn insert_tasks(&mut self, tasks: Vec<NewTask>) -> Result<Vec<TaskId>, ContractError> {
+        ensure_batch_size(tasks.len())?;
+        self.migrate_account(&env::predecessor_account_id());
+        let initial_storage_usage = env::storage_usage();
+        let task_ids = events::collect(|| {
+            tasks
+                .into_iter()
+                .enumerate()
+                .map(|(index, task)| self.add_task(task).map_err(item_failed(index)))
+                .collect::<Result<Vec<_>, _>>()
+        })?;
+        self.settle_storage(initial_storage_usage);
+        Ok(task_ids)
+    }
+
+    // Public method - change the given fields of several tasks, like `patch_task`
+    // Like in `patch_task`, an attached deposit is added to the storage balance of the owner of
+    // the first task; the storage of each task is charged against or credited to its owner's
+    // balance. Status changes log one `status_changed` and other changes one `task_updated` event.
+    #[payable]
+    #[handle_result]
+    pub fn update_tasks(&mut self, updates: Vec<TaskUpdate>) -> Result<(), ContractError> {
+        ensure_batch_size(updates.len())?;
+        let attached_deposit = env::attached_deposit();
+        if attached_deposit > 0 {
+            let owner_id = updates
+                .first()
+                .and_then(|update| self.tasks.get(&update.task_id))
+                .map_or_else(env::predecessor_account_id, |task| task.owner_id);
+            self.deposit_storage(&owner_id, attached_deposit);
+        }
+        events::collect(|| {
+            for (index, update) in updates.into_iter().enumerate() {
+                let initial_storage_usage = env::storage_usage();
+                let owner_id = self
+                    .apply_task_patch(update.task_id, update.patch, false)
+                    .map_err(item_failed(index))?;
+                self.charge_storage(&owner_id, initial_storage_usage);
+            }
+            Ok(())
+        })
+    }
+
+    // Public method - permanently remove several active or archived tasks, like `delete_task`
+    // Subtasks have to come before their parent.
+    #[handle_result]
+    pub fn delete_tasks(&mut self, task_ids: Vec<TaskId>) -> Result<(), ContractError> {
+        ensure_batch_size(task_ids.len())?;
+        self.migrate_account(&env::predecessor_account_id());
+        events::collect(|| {
+            for (index, task_id) in task_ids.into_iter().enumerate() {
+                let initial_storage_usage = env::storage_usage();
+                let task = self.remove_task(task_id).map_err(item_failed(index))?;
+                self.charge_storage(&task.owner_id, initial_storage_usage);
+            }
+            Ok(())
+        })
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use super::*;
+    use near_sdk::serde_json::{self, json};
+    use near_sdk::test_utils::{accounts, get_logs, VMContextBuilder};
+    use near_sdk::{testing_env, ONE_NEAR};
+
+    fn set_predecessor(account_id: AccountId) {
+        let mut context = VMContextBuilder::new();
+        context
+            .predecessor_account_id(account_id)
+            .account_balance(10 * ONE_NEAR)
+            .attached_deposit(ONE_NEAR / 10);
+        testing_env!(context.build());
+    }
+
+    fn event_names() -> Vec<String> {
+        get_logs()
+            .iter()
+            .filter_map(|log| log.strip_prefix("EVENT_JSON:"))
+            .map(|log| {
+                let json: serde_json::Value = serde_json::from_str(log).unwrap();
+                json["event"].as_str().unwrap().to_owned()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn batches_log_one_event_per_kind() {
+        set_predecessor(accounts(0));
+        let mut contract = Contract::default();
+        let task_ids = contract
+            .insert_tasks(vec![
+                NewTask::new(String::from("task_a")),
+                NewTask::new(String::from("task_b")),
+            ])
+            .unwrap();
+        assert_eq!(task_ids, [0, 1]);
+        assert_eq!(event_names(), ["task_created"]);
+
+        set_predecessor(accounts(0));
+        let updates: Vec<TaskUpdate> = serde_json::from_value(json!([
+            { "task_id": 0, "task_status": "DONE" },
+            { "task_id": 1, "task_name": "task_c", "description": "notes" },
+        ]))
+        .unwrap();
+        contract.update_tasks(updates).unwrap();
+        assert_eq!(event_names(), ["status_changed", "task_updated"]);
+        assert_eq!(contract.get_task(1).unwrap().task_name, "task_c");
+
+        set_predecessor(accounts(0));
+        contract.delete_tasks(task_ids).unwrap();
+        assert_eq!(event_names(), ["task_deleted"]);
+        assert_eq!(contract.get_task_count_for(accounts(0)), 0);
+    }
+
+    #[test]
+    fn deposits_of_collaborators_go_to_the_owner() {
+        set_predecessor(accounts(0));
+        let mut contract = Contract::default();
+        let list_id = contract.create_list(String::from("shared")).unwrap();
+        contract
+            .add_collaborator(list_id, accounts(1), Role::Editor)
+            .unwrap();
+        let task_id = contract
+            .create_task(NewTask {
+                list_id: Some(list_id),
+                ..NewTask::new(String::from("task_a"))
+            })
+            .unwrap();
+        let owner_deposit =
+            |contract: &Contract| contract.storage_accounts.get(&accounts(0)).unwrap().deposit;
+        let deposit = owner_deposit(&contract);
+
+        set_predecessor(accounts(1));
+        let updates: Vec<TaskUpdate> = serde_json::from_value(json!([
+            { "task_id": task_id, "description": "notes" },
+        ]))
+        .unwrap();
+        contract.update_tasks(updates).unwrap();
+        assert_eq!(owner_deposit(&contract), deposit + ONE_NEAR / 10);
+        assert!(contract.storage_accounts.get(&accounts(1)).is_none());
+    }
+
+    #[test]
+    fn a_failing_item_fails_the_batch() {
+        set_predecessor(accounts(0));
+        let mut contract = Contract::default();
+        let task_id = contract.insert_task(String::from("task_a"));
+
+        set_predecessor(accounts(0));
+        let updates = vec![
+            TaskUpdate {
+                task_id,
+                patch: TaskPatch {
+                    task_name: Some(String::from("task_b")),
+                    ..Default::default()
+                },
+            },
+            TaskUpdate {
+                task_id: 7,
+                patch: TaskPatch::default(),
+            },
+        ];
+        assert_eq!(
+            contract.update_tasks(updates),
+            Err(ContractError::BatchItemFailed {
+                index: 1,
+                error: Box::new(ContractError::TaskNotFound(7))
+            })
+        );
+        assert!(event_names().is_empty());
+
+        set_predecessor(accounts(1));
+        assert!(matches!(
+            contract.delete_tasks(vec![task_id]),
+            Err(ContractError::BatchItemFailed { index: 0, .. })
+        ));
+        let tasks = vec![NewTask::new(String::from("task_c")); MAX_BATCH_SIZE + 1];
+        assert_eq!(
+            contract.insert_tasks(tasks),
+            Err(ContractError::BatchTooLarge {
+                count: MAX_BATCH_SIZE + 1,
+                max_count: MAX_BATCH_SIZE
+            })
+        );
+    }
+}
@@ -0,0 +1,519 @@
+//! NEAR bounties escrowed on tasks.
+//!
+//! The owner of a task attaches NEAR to it with `fund_task`. The contract holds the bounty until
+//! the owner moves the task to `DONE`, which pays it to the accepted assignee, or until the task
+//! is cancelled, deleted or the bounty expires, which refunds it to the owner. Once the accepted
+//! assignee moved the task to `IN_PROGRESS` or `DONE` the bounty is locked: the owner can't
+//! unassign, reassign, cancel, reopen, archive or delete the task and the bounty doesn't expire,
+//! until the assignee gives the task back or the owner releases the bounty. A transfer that
+//! fails, e.g. because the receiver account was deleted, comes back to the contract and is kept
+//! as an unclaimed bounty of the receiver, which can be withdrawn with `withdraw_unclaimed_bounty`.
+//! Tasks can be funded with fungible tokens as well, see `token_bounty`; the methods below that
+//! pay out or refund a bounty handle those too.
+
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::{U128, U64};
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::{env, near_bindgen, AccountId, Balance, Gas, Promise, PromiseError};
+
+use crate::details::validate_due_date;
+use crate::events::BountyData;
+use crate::*;
+
+/// Gas attached to the callback checking the outcome of a bounty transfer.
+const GAS_FOR_ON_BOUNTY_TRANSFER: Gas = Gas(10 * Gas::ONE_TERA.0);
+
+#[derive(Debug, Clone, PartialEq, BorshDeserialize, BorshSerialize, Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct Bounty {
+    /// Account that funded the bounty and gets it back on a refund.
+    pub funder_id: AccountId,
+    /// Escrowed yoctoNEAR.
+    pub amount: U128,
+    /// Block timestamp in nanoseconds after which the bounty can be refunded.
+    pub expires_at: Option<U64>,
+}
+
+/// Whether a bounty with the given expiry can be refunded.
+pub(crate) fn is_expired(expires_at: Option<U64>) -> bool {
+    expires_at.is_some_and(|expires_at| expires_at.0 <= env::block_timestamp())
+}
+
+#[near_bindgen]
+impl Contract {
+    // Public method - returns the bounty escrowed on a task
+    pub fn get_bounty(&self, task_id: TaskId) -> Option<Bounty> {
+        self.bounties.get(&task_id)
+    }
+
+    // Public method - returns the bounties that couldn't be transferred to the given account
+    pub fn get_unclaimed_bounty(&self, account_id: AccountId) -> U128 {
+        U128(self.unclaimed_bounties.get(&account_id).unwrap_or(0))
+    }
+
+    // Public method - escrow the attached deposit as a bounty on one of the caller's open tasks
+    // Funding a task again adds to its bounty. `expires_at` defaults to the due date of the
+    // task, a bounty without expiry is only refunded when the task is cancelled or deleted.
+    // The storage of the bounty is charged against the caller's storage balance, the attached
+    // deposit is not.
+    #[payable]
+    #[handle_result]
+    pub fn fund_task(
+        &mut self,
+        task_id: TaskId,
+        expires_at: Option<U64>,
+    ) -> Result<Bounty, ContractError> {
+        let amount = env::attached_deposit();
+        if amount == 0 {
+            return Err(ContractError::EmptyBounty);
+        }
+        let account_id = env::predecessor_account_id();
+        let task = self.fundable_task(task_id, &account_id)?;
+        let expires_at = validate_due_date(expires_at)?;
+        let initial_storage_usage = env::storage_usage();
+        let bounty = match self.bounties.get(&task_id) {
+            Some(bounty) => Bounty {
+                amount: U128(bounty.amount.0 + amount),
+                expires_at: expires_at.or(bounty.expires_at),
+                ..bounty
+            },
+            None => Bounty {
+                funder_id: account_id.clone(),
+                amount: U128(amount),
+                expires_at: expires_at.or(task.due_date),
+            },
+        };
+        self.bounties.insert(&task_id, &bounty);
+        TaskEvent::BountyFunded(vec![BountyData {
+            task_id: Some(task_id),
+            account_id: account_id.clone(),
+            amount: U128(amount),
+            token_id: None,
+        }])
+        .emit();
+        self.charge_storage(&account_id, initial_storage_usage);
+        Ok(bounty)
+    }
+
+    // Public method - pay the NEAR and token bounties of a finished task to its assignee
+    // Only needed when the assignee moved the task to `DONE`, the bounties are paid right away
+    // when the owner does it.
+    #[handle_result]
+    pub fn release_bounty(&mut self, task_id: TaskId) -> Result<(), ContractError> {
+        let task = self
+            .tasks
+            .get(&task_id)
+            .ok_or(ContractError::TaskNotFound(task_id))?;
+        let account_id = env::predecessor_account_id();
+        if task.owner_id != account_id {
+            return Err(ContractError::NotTaskOwner {
+                task_id,
+                account_id,
+            });
+        }
+        let assignee = task
+            .assignee
+            .filter(|assignee| assignee.status == AssignmentStatus::Accepted)
+            .filter(|_| task.task_status == TaskStatus::Done)
+            .ok_or(ContractError::BountyNotReleasable(task_id))?;
+        let initial_storage_usage = env::storage_usage();
+        if !self.pay_bounties(task_id, Some(&assignee.account_id)) {
+            return Err(ContractError::NoBounty(task_id));
+        }
+        self.charge_storage(&task.owner_id, initial_storage_usage);
+        Ok(())
+    }
+
+    // Public method - refund the expired NEAR and token bounties of a task to their funder,
+    // anyone may call this
+    #[handle_result]
+    pub fn refund_expired_bounty(&mut self, task_id: TaskId) -> Result<(), ContractError> {
+        let bounty = self.bounties.get(&task_id);
+        let funder_id = bounty
+            .as_ref()
+            .map(|bounty| bounty.funder_id.clone())
+            .or_else(|| {
+                self.token_bounties
+                    .get(&task_id)
+                    .and_then(|bounties| bounties.first().map(|bounty| bounty.funder_id.clone()))
+            })
+            .ok_or(ContractError::NoBounty(task_id))?;
+        if self
+            .any_task(task_id)
+            .is_some_and(|task| self.is_bounty_locked(&task))
+        {
+            return Err(ContractError::BountyLocked(task_id));
+        }
+        let initial_storage_usage = env::storage_usage();
+        let mut refunded = false;
+        if bounty.is_some_and(|bounty| is_expired(bounty.expires_at)) {
+            refunded = self.pay_bounty(task_id, None);
+        }
+        refunded |= self.pay_token_bounties(task_id, None, |bounty| is_expired(bounty.expires_at));
+        if !refunded {
+            return Err(ContractError::BountyNotExpired(task_id));
+        }
+        self.charge_storage(&funder_id, initial_storage_usage);
+        Ok(())
+    }
+
+    // Public method - retry the transfer of the caller's unclaimed bounties
+    // Resolves to `false` if the transfer failed again, the bounties stay unclaimed then.
+    #[handle_result]
+    pub fn withdraw_unclaimed_bounty(&mut self) -> Result<Promise, ContractError> {
+        let account_id = env::predecessor_account_id();
+        let amount = self
+            .unclaimed_bounties
+            .remove(&account_id)
+            .ok_or_else(|| ContractError::NoUnclaimedBounty(account_id.clone()))?;
+        Ok(Self::transfer_bounty(None, account_id, amount))
+    }
+
+    // Private method - keeps the bounty as unclaimed if its transfer failed
+    // The receiver may not be registered, so the contract pays for this record itself.
+    #[private]
+    pub fn on_bounty_transfer(
+        &mut self,
+        task_id: Option<TaskId>,
+        account_id: AccountId,
+        amount: U128,
+        #[callback_result] result: Result<(), PromiseError>,
+    ) -> bool {
+        if result.is_ok() {
+            return true;
+        }
+        let unclaimed = self.unclaimed_bounties.get(&account_id).unwrap_or(0);
+        self.unclaimed_bounties
+            .insert(&account_id, &(unclaimed + amount.0));
+        TaskEvent::BountyUnclaimed(vec![BountyData {
+            task_id,
+            account_id,
+            amount,
+            token_id: None,
+        }])
+        .emit();
+        false
+    }
+}
+
+impl Contract {
+    /// Loads an open task of `account_id` to fund.
+    pub(crate) fn fundable_task(
+        &self,
+        task_id: TaskId,
+        account_id: &AccountId,
+    ) -> Result<Task, ContractError> {
+        let task = self
+            .tasks
+            .get(&task_id)
+            .ok_or(ContractError::TaskNotFound(task_id))?;
+        if task.owner_id != *account_id {
+            return Err(ContractError::NotTaskOwner {
+                task_id,
+                account_id: account_id.clone(),
+            });
+        }
+        if matches!(task.task_status, TaskStatus::Done | TaskStatus::Cancelled) {
+            return Err(ContractError::TaskClosed(task_id));
+        }
+        Ok(task)
+    }
+
+    /// Pays or refunds the bounties after the status of the task changed: moving the task to
+    /// `DONE` pays the accepted assignee, or refunds the owner if there is none, and cancelling
+    /// it refunds the owner. Only the owner finishing the task releases the bounties.
+    pub(crate) fn settle_bounty(&mut self, task: &Task) {
+        match task.task_status {
+            TaskStatus::Done if env::predecessor_account_id() == task.owner_id => {
+                let assignee_id = task
+                    .assignee
+                    .as_ref()
+                    .filter(|assignee| assignee.status == AssignmentStatus::Accepted)
+                    .map(|assignee| &assignee.account_id);
+                self.pay_bounties(task.id, assignee_id);
+            }
+            TaskStatus::Cancelled => {
+                self.pay_bounties(task.id, None);
+            }
+            _ => {}
+        }
+    }
+
+    /// Whether the task has bounties that are owed to its accepted assignee, who started or
+    /// finished it.
+    pub(crate) fn is_bounty_locked(&self, task: &Task) -> bool {
+        let accepted = task
+            .assignee
+            .as_ref()
+            .is_some_and(|assignee| assignee.status == AssignmentStatus::Accepted);
+        accepted
+            && matches!(task.task_status, TaskStatus::InProgress | TaskStatus::Done)
+            && (self.bounties.contains_key(&task.id) || self.token_bounties.contains_key(&task.id))
+    }
+
+    /// Refunds the bounties of a task that is being deleted, or pays them to the assignee if
+    /// they are locked.
+    pub(crate) fn refund_bounty(&mut self, task: &Task) {
+        let assignee_id = task
+            .assignee
+            .as_ref()
+            .filter(|_| self.is_bounty_locked(task))
+            .map(|assignee| assignee.account_id.clone());
+        self.pay_bounties(task.id, assignee_id.as_ref());
+    }
+
+    /// Pays the NEAR and token bounties of a task to `receiver_id`, or refunds them to their
+    /// funders if it is `None`. Returns whether the task had any bounty.
+    fn pay_bounties(&mut self, task_id: TaskId, receiver_id: Option<&AccountId>) -> bool {
+        let paid = self.pay_bounty(task_id, receiver_id);
+        self.pay_token_bounties(task_id, receiver_id, |_| true) || paid
+    }
+
+    /// Takes the NEAR bounty out of escrow and transfers it.
+    fn pay_bounty(&mut self, task_id: TaskId, receiver_id: Option<&AccountId>) -> bool {
+        let bounty = match self.bounties.remove(&task_id) {
+            Some(bounty) => bounty,
+            None => return false,
+        };
+        let receiver_id = receiver_id.unwrap_or(&bounty.funder_id).clone();
+        let data = vec![BountyData {
+            task_id: Some(task_id),
+            account_id: receiver_id.clone(),
+            amount: bounty.amount,
+            token_id: None,
+        }];
+        if receiver_id == bounty.funder_id {
+            TaskEvent::BountyRefunded(data).emit();
+        } else {
+            TaskEvent::BountyReleased(data).emit();
+        }
+        Self::transfer_bounty(Some(task_id), receiver_id, bounty.amount.0);
+        true
+    }
+
+    pub(crate) fn transfer_bounty(
+        task_id: Option<TaskId>,
+        account_id: AccountId,
+        amount: Balance,
+    ) -> Promise {
+        Promise::new(account_id.clone()).transfer(amount).then(
+            Self::ext(env::current_account_id())
+                .with_static_gas(GAS_FOR_ON_BOUNTY_TRANSFER)
+                .on_bounty_transfer(task_id, account_id, U128(amount)),
+        )
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use super::*;
+    use near_sdk::mock::VmAction;
+    use near_sdk::test_utils::{accounts, get_created_receipts, VMContextBuilder};
+    use near_sdk::{testing_env, ONE_NEAR};
+
+    fn set_context(account_id: AccountId, deposit: Balance) {
+        let mut context = VMContextBuilder::new();
+        context
+            .current_account_id(accounts(5))
+            .predecessor_account_id(account_id)
+            .account_balance(10 * ONE_NEAR)
+            .attached_deposit(deposit)
+            .block_timestamp(1_000);
+        testing_env!(context.build());
+    }
+
+    // Transfers made by the last call
+    fn transfers() -> Vec<(AccountId, Balance)> {
+        get_created_receipts()
+            .into_iter()
+            .flat_map(|receipt| {
+                let receiver_id = receipt.receiver_id;
+                receipt
+                    .actions
+                    .into_iter()
+                    .filter_map(move |action| match action {
+                        VmAction::Transfer { deposit } => Some((receiver_id.clone(), deposit)),
+                        _ => None,
+                    })
+            })
+            .collect()
+    }
+
+    // alice funds a task with 2 NEAR, bob accepted it
+    fn setup() -> (Contract, TaskId) {
+        set_context(accounts(0), ONE_NEAR / 100);
+        let mut contract = Contract::default();
+        let task_id = contract.insert_task(String::from("task_a"));
+        contract.assign_task(task_id, accounts(1)).unwrap();
+        set_context(accounts(1), 0);
+        contract.accept_task(task_id).unwrap();
+
+        set_context(accounts(0), 2 * ONE_NEAR);
+        contract.fund_task(task_id, None).unwrap();
+        (contract, task_id)
+    }
+
+    #[test]
+    fn owner_finishing_pays_the_assignee() {
+        let (mut contract, task_id) = setup();
+        assert_eq!(
+            contract.get_bounty(task_id).unwrap().amount,
+            U128(2 * ONE_NEAR)
+        );
+
+        set_context(accounts(0), 0);
+        contract
+            .update_task(task_id, TaskStatus::Done, None)
+            .unwrap();
+        assert_eq!(transfers(), vec![(accounts(1), 2 * ONE_NEAR)]);
+        assert_eq!(contract.get_bounty(task_id), None);
+    }
+
+    #[test]
+    fn assignee_finishing_waits_for_the_owner() {
+        let (mut contract, task_id) = setup();
+
+        set_context(accounts(1), 0);
+        contract
+            .update_task(task_id, TaskStatus::Done, None)
+            .unwrap();
+        assert!(transfers().is_empty());
+        assert_eq!(
+            contract.release_bounty(task_id),
+            Err(ContractError::NotTaskOwner {
+                task_id,
+                account_id: accounts(1)
+            })
+        );
+
+        set_context(accounts(0), 0);
+        contract.release_bounty(task_id).unwrap();
+        assert_eq!(transfers(), vec![(accounts(1), 2 * ONE_NEAR)]);
+    }
+
+    #[test]
+    fn cancelling_or_deleting_refunds_the_owner() {
+        let (mut contract, task_id) = setup();
+        set_context(accounts(0), 0);
+        contract
+            .update_task(task_id, TaskStatus::Cancelled, None)
+            .unwrap();
+        assert_eq!(transfers(), vec![(accounts(0), 2 * ONE_NEAR)]);
+        set_context(accounts(0), ONE_NEAR);
+        assert_eq!(
+            contract.fund_task(task_id, None),
+            Err(ContractError::TaskClosed(task_id))
+        );
+
+        let (mut contract, task_id) = setup();
+        set_context(accounts(0), 0);
+        contract.delete_task(task_id).unwrap();
+        assert_eq!(transfers(), vec![(accounts(0), 2 * ONE_NEAR)]);
+    }
+
+    #[test]
+    fn expired_bounties_are_refunded() {
+        let (mut contract, task_id) = setup();
+        set_context(accounts(0), ONE_NEAR);
+        let bounty = contract.fund_task(task_id, Some(U64(2_000))).unwrap();
+        assert_eq!(bounty.amount, U128(3 * ONE_NEAR));
+
+        set_context(accounts(2), 0);
+        assert_eq!(
+            contract.refund_expired_bounty(task_id),
+            Err(ContractError::BountyNotExpired(task_id))
+        );
+        let mut context = VMContextBuilder::new();
+        context
+            .current_account_id(accounts(5))
+            .predecessor_account_id(accounts(2))
+            .block_timestamp(2_000);
+        testing_env!(context.build());
+        contract.refund_expired_bounty(task_id).unwrap();
+        assert_eq!(transfers(), vec![(accounts(0), 3 * ONE_NEAR)]);
+    }
+
+    #[test]
+    fn started_tasks_cannot_be_unassigned_then_cancelled() {
+        let (mut contract, task_id) = setup();
+        set_context(accounts(1), 0);
+        contract
+            .update_task(task_id, TaskStatus::InProgress, None)
+            .unwrap();
+
+        set_context(accounts(0), 0);
+        assert_eq!(
+            contract.unassign_task(task_id),
+            Err(ContractError::BountyLocked(task_id))
+        );
+        assert_eq!(
+            contract.update_task(task_id, TaskStatus::Cancelled, None),
+            Err(ContractError::BountyLocked(task_id))
+        );
+        assert_eq!(
+            contract.delete_task(task_id),
+            Err(ContractError::BountyLocked(task_id))
+        );
+        assert_eq!(
+            contract.archive_task(task_id),
+            Err(ContractError::BountyLocked(task_id))
+        );
+        assert!(transfers().is_empty());
+
+        // Giving the task back unlocks the bounty.
+        set_context(accounts(1), 0);
+        contract.decline_task(task_id).unwrap();
+        set_context(accounts(0), 0);
+        contract
+            .update_task(task_id, TaskStatus::Cancelled, None)
+            .unwrap();
+        assert_eq!(transfers(), vec![(accounts(0), 2 * ONE_NEAR)]);
+    }
+
+    #[test]
+    fn bounties_finished_by_the_assignee_do_not_expire() {
+        let (mut contract, task_id) = setup();
+        set_context(accounts(0), ONE_NEAR);
+        contract.fund_task(task_id, Some(U64(2_000))).unwrap();
+        set_context(accounts(1), 0);
+        contract
+            .update_task(task_id, TaskStatus::Done, None)
+            .unwrap();
+
+        let mut context = VMContextBuilder::new();
+        context
+            .current_account_id(accounts(5))
+            .predecessor_account_id(accounts(0))
+            .block_timestamp(2_000);
+        testing_env!(context.build());
+        assert_eq!(
+            contract.refund_expired_bounty(task_id),
+            Err(ContractError::BountyLocked(task_id))
+        );
+        assert_eq!(
+            contract.update_task(task_id, TaskStatus::Todo, None),
+            Err(ContractError::BountyLocked(task_id))
+        );
+        contract.release_bounty(task_id).unwrap();
+        assert_eq!(transfers(), vec![(accounts(1), 3 * ONE_NEAR)]);
+    }
+
+    #[test]
+    fn failed_transfers_stay_claimable() {
+        let (mut contract, _) = setup();
+        set_context(accounts(5), 0);
+        assert!(!contract.on_bounty_transfer(
+            Some(0),
+            accounts(1),
+            U128(ONE_NEAR),
+            Err(PromiseError::Failed)
+        ));
+        assert!(contract.on_bounty_transfer(Some(0), accounts(1), U128(ONE_NEAR), Ok(())));
+        assert_eq!(contract.get_unclaimed_bounty(accounts(1)), U128(ONE_NEAR));
+
+        set_context(accounts(1), 0);
+        contract.withdraw_unclaimed_bounty().unwrap();
+        assert_eq!(transfers(), vec![(accounts(1), ONE_NEAR)]);
+        assert_eq!(contract.get_unclaimed_bounty(accounts(1)), U128(0));
+    }
+}
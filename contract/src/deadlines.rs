@@ -0,0 +1,356 @@
+//! Overdue and upcoming tasks.
+//!
+//! `DueDateIndex` keeps the open active tasks of every account that have a due date in a
+//! `TreeMap` ordered by due date, so `get_overdue_tasks` and `get_tasks_due_within` only visit
+//! the tasks they return instead of every task of the account.
+
+use std::ops::{Bound, RangeBounds};
+
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::collections::{LookupMap, LookupSet, TreeMap};
+use near_sdk::json_types::U64;
+use near_sdk::{env, near_bindgen, AccountId};
+
+use crate::index::{DEFAULT_PAGE_SIZE, MAX_PAGE_SIZE};
+use crate::*;
+
+/// Due date and id of a task, ordered by due date first.
+type DueDateKey = (u64, TaskId);
+
+/// Per-account index of the open active tasks with a due date, earliest first.
+///
+/// Like `TaskIndex`, every account gets its own `TreeMap` under
+/// `tree_prefix ++ sha256(account_id)`. Contracts upgraded from a layout without the index
+/// track in `backfilled` which accounts have been added to it so far. For all other accounts
+/// the views scan the account's tasks instead, until `Contract::migrate_account` backfills the
+/// index on the account's next write.
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct DueDateIndex {
+    trees: LookupMap<AccountId, TreeMap<DueDateKey, ()>>,
+    tree_prefix: Vec<u8>,
+    backfilled: Option<LookupSet<AccountId>>,
+}
+
+impl DueDateIndex {
+    pub fn new(prefix: &[u8], tree_prefix: &[u8]) -> Self {
+        Self {
+            trees: LookupMap::new(prefix),
+            tree_prefix: tree_prefix.to_vec(),
+            backfilled: None,
+        }
+    }
+
+    /// Marks the index as created after tasks existed, see `backfill`.
+    pub fn with_backfill(mut self, backfilled_prefix: &[u8]) -> Self {
+        self.backfilled = Some(LookupSet::new(backfilled_prefix));
+        self
+    }
+
+    fn new_tree(&self, account_id: &AccountId) -> TreeMap<DueDateKey, ()> {
+        let prefix = [
+            self.tree_prefix.as_slice(),
+            &env::sha256(account_id.as_bytes()),
+        ]
+        .concat();
+        TreeMap::new(prefix)
+    }
+
+    fn key(task: &Task) -> Option<DueDateKey> {
+        task.due_date.map(|due_date| (due_date.0, task.id))
+    }
+
+    /// Adds the task if it is open and has a due date.
+    pub fn insert(&mut self, task: &Task) {
+        let key = match Self::key(task) {
+            Some(key) if task.is_open() => key,
+            _ => return,
+        };
+        let mut tree = self
+            .trees
+            .get(&task.owner_id)
+            .unwrap_or_else(|| self.new_tree(&task.owner_id));
+        tree.insert(&key, &());
+        self.trees.insert(&task.owner_id, &tree);
+    }
+
+    /// Removes the task as it is stored, dropping the account's tree once it is empty.
+    pub fn remove(&mut self, task: &Task) {
+        let (key, mut tree) = match (Self::key(task), self.trees.get(&task.owner_id)) {
+            (Some(key), Some(tree)) => (key, tree),
+            _ => return,
+        };
+        if tree.remove(&key).is_none() {
+            return;
+        }
+        if tree.is_empty() {
+            self.trees.remove(&task.owner_id);
+        } else {
+            self.trees.insert(&task.owner_id, &tree);
+        }
+    }
+
+    pub fn remove_all(&mut self, account_id: &AccountId) {
+        if let Some(mut tree) = self.trees.remove(account_id) {
+            tree.clear();
+        }
+        if let Some(backfilled) = self.backfilled.as_mut() {
+            backfilled.remove(account_id);
+        }
+    }
+
+    /// Whether all open tasks of the account with a due date are in the index.
+    pub fn is_complete(&self, account_id: &AccountId) -> bool {
+        self.backfilled
+            .as_ref()
+            .is_none_or(|backfilled| backfilled.contains(account_id))
+    }
+
+    /// Adds the tasks of an account that were created before the index.
+    pub fn backfill(&mut self, account_id: &AccountId, tasks: impl IntoIterator<Item = Task>) {
+        if self.is_complete(account_id) {
+            return;
+        }
+        for task in tasks {
+            self.insert(&task);
+        }
+        if let Some(backfilled) = self.backfilled.as_mut() {
+            backfilled.insert(account_id);
+        }
+    }
+
+    /// Returns a page of the ids of the account's tasks due within the bounds, earliest first.
+    pub fn page(
+        &self,
+        account_id: &AccountId,
+        bounds: (Bound<DueDateKey>, Bound<DueDateKey>),
+        from_index: Option<u64>,
+        limit: Option<u64>,
+    ) -> Vec<TaskId> {
+        let tree = match self.trees.get(account_id) {
+            Some(tree) => tree,
+            None => return Vec::new(),
+        };
+        tree.range(bounds)
+            .skip(from_index.unwrap_or(0) as usize)
+            .take(page_size(limit))
+            .map(|((_, task_id), _)| task_id)
+            .collect()
+    }
+}
+
+fn page_size(limit: Option<u64>) -> usize {
+    limit.unwrap_or(DEFAULT_PAGE_SIZE).min(MAX_PAGE_SIZE) as usize
+}
+
+#[near_bindgen]
+impl Contract {
+    // Public method - returns a page of the open tasks of the given account that are past due
+    // at the block timestamp `at` (default: now), earliest due date first
+    pub fn get_overdue_tasks(
+        &self,
+        account_id: AccountId,
+        at: Option<U64>,
+        from_index: Option<u64>,
+        limit: Option<u64>,
+    ) -> Vec<Task> {
+        let at = at.map_or_else(env::block_timestamp, |at| at.0);
+        // `TreeMap::range` doesn't iterate from an unbounded start.
+        self.due_tasks(
+            &account_id,
+            (Bound::Included((0, 0)), Bound::Excluded((at, 0))),
+            from_index,
+            limit,
+        )
+    }
+
+    // Public method - returns a page of the open tasks of the given account that are due within
+    // `window` nanoseconds after the block timestamp `at` (default: now), earliest first
+    pub fn get_tasks_due_within(
+        &self,
+        account_id: AccountId,
+        window: U64,
+        at: Option<U64>,
+        from_index: Option<u64>,
+        limit: Option<u64>,
+    ) -> Vec<Task> {
+        let at = at.map_or_else(env::block_timestamp, |at| at.0);
+        let until = at.saturating_add(window.0);
+        self.due_tasks(
+            &account_id,
+            (
+                Bound::Included((at, 0)),
+                Bound::Included((until, TaskId::MAX)),
+            ),
+            from_index,
+            limit,
+        )
+    }
+}
+
+impl Contract {
+    fn due_tasks(
+        &self,
+        account_id: &AccountId,
+        bounds: (Bound<DueDateKey>, Bound<DueDateKey>),
+        from_index: Option<u64>,
+        limit: Option<u64>,
+    ) -> Vec<Task> {
+        if !self.tasks_by_due_date.is_complete(account_id) {
+            return self.scan_due_tasks(account_id, bounds, from_index, limit);
+        }
+        self.tasks_by_due_date
+            .page(account_id, bounds, from_index, limit)
+            .into_iter()
+            .map(|task_id| self.tasks.get(&task_id).unwrap())
+            .collect()
+    }
+
+    /// Serves accounts that haven't been backfilled yet from their task list.
+    fn scan_due_tasks(
+        &self,
+        account_id: &AccountId,
+        bounds: (Bound<DueDateKey>, Bound<DueDateKey>),
+        from_index: Option<u64>,
+        limit: Option<u64>,
+    ) -> Vec<Task> {
+        let mut tasks: Vec<(DueDateKey, Task)> = self
+            .tasks_by_account
+            .ids(account_id)
+            .into_iter()
+            .filter_map(|task_id| self.tasks.get(&task_id))
+            .filter(Task::is_open)
+            .filter_map(|task| Some((DueDateIndex::key(&task)?, task)))
+            .filter(|(key, _)| bounds.contains(key))
+            .collect();
+        tasks.sort_by_key(|(key, _)| *key);
+        tasks
+            .into_iter()
+            .skip(from_index.unwrap_or(0) as usize)
+            .take(page_size(limit))
+            .map(|(_, task)| task)
+            .collect()
+    }
+
+    /// Adds the account's tasks to the due date index if they predate it.
+    pub(crate) fn backfill_due_dates(&mut self, account_id: &AccountId) {
+        if self.tasks_by_due_date.is_complete(account_id) {
+            return;
+        }
+        let tasks: Vec<Task> = self
+            .tasks_by_account
+            .ids(account_id)
+            .into_iter()
+            .filter_map(|task_id| self.tasks.get(&task_id))
+            .collect();
+        self.tasks_by_due_date.backfill(account_id, tasks);
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use super::*;
+    use near_sdk::test_utils::{accounts, VMContextBuilder};
+    use near_sdk::{testing_env, ONE_NEAR};
+
+    fn set_block_timestamp(block_timestamp: u64) {
+        let mut context = VMContextBuilder::new();
+        context
+            .predecessor_account_id(accounts(0))
+            .account_balance(10 * ONE_NEAR)
+            .attached_deposit(ONE_NEAR / 100)
+            .block_timestamp(block_timestamp);
+        testing_env!(context.build());
+    }
+
+    fn create_due_task(contract: &mut Contract, due_date: u64) -> TaskId {
+        contract
+            .create_task(NewTask {
+                due_date: Some(U64(due_date)),
+                ..NewTask::new(format!("due_{}", due_date))
+            })
+            .unwrap()
+    }
+
+    fn ids(tasks: Vec<Task>) -> Vec<TaskId> {
+        tasks.into_iter().map(|task| task.id).collect()
+    }
+
+    #[test]
+    fn overdue_and_upcoming_tasks() {
+        set_block_timestamp(100);
+        let mut contract = Contract::default();
+        let late = create_due_task(&mut contract, 300);
+        let early = create_due_task(&mut contract, 200);
+        let later = create_due_task(&mut contract, 500);
+        let done = create_due_task(&mut contract, 250);
+        contract.insert_task(String::from("no_due_date"));
+        contract.update_task(done, TaskStatus::Done, None).unwrap();
+
+        set_block_timestamp(400);
+        let alice = accounts(0);
+        assert_eq!(
+            ids(contract.get_overdue_tasks(alice.clone(), None, None, None)),
+            vec![early, late]
+        );
+        assert_eq!(
+            ids(contract.get_overdue_tasks(alice.clone(), Some(U64(300)), None, None)),
+            vec![early]
+        );
+        assert_eq!(
+            ids(contract.get_overdue_tasks(alice.clone(), None, Some(1), Some(1))),
+            vec![late]
+        );
+        assert_eq!(
+            ids(contract.get_tasks_due_within(alice.clone(), U64(100), None, None, None)),
+            vec![later]
+        );
+        assert_eq!(
+            ids(contract.get_tasks_due_within(alice.clone(), U64(100), Some(U64(200)), None, None)),
+            vec![early, late]
+        );
+
+        // Moving the due date or archiving a task updates the index.
+        contract
+            .patch_task(
+                late,
+                TaskPatch {
+                    due_date: Some(Some(U64(1_000))),
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+        contract.archive_task(early).unwrap();
+        assert!(contract
+            .get_overdue_tasks(alice.clone(), None, None, None)
+            .is_empty());
+        contract.update_task(done, TaskStatus::Todo, None).unwrap();
+        assert_eq!(
+            ids(contract.get_overdue_tasks(alice, None, None, None)),
+            vec![done]
+        );
+    }
+
+    #[test]
+    fn tasks_created_before_the_index_are_backfilled() {
+        set_block_timestamp(100);
+        let mut contract = Contract::default();
+        let first = create_due_task(&mut contract, 300);
+        let second = create_due_task(&mut contract, 200);
+        // An upgraded contract starts with an empty index.
+        contract.tasks_by_due_date = DueDateIndex::new(b"v", b"V").with_backfill(b"w");
+
+        set_block_timestamp(400);
+        let alice = accounts(0);
+        assert_eq!(
+            ids(contract.get_overdue_tasks(alice.clone(), None, None, None)),
+            vec![second, first]
+        );
+        contract.insert_task(String::from("task_c"));
+        assert!(contract.tasks_by_due_date.is_complete(&alice));
+        let all = (Bound::Included((0, 0)), Bound::Unbounded);
+        assert_eq!(
+            contract.tasks_by_due_date.page(&alice, all, None, None),
+            vec![second, first]
+        );
+    }
+}
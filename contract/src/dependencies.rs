@@ -0,0 +1,311 @@
+//! "Blocked by" dependencies between tasks.
+//!
+//! `add_blocker(task_id, blocker_id)` records that a task can't be moved to `IN_PROGRESS` or
+//! `DONE` before the blocker is `DONE`. Edges are stored on both tasks, as `blocker_ids` and
+//! `dependent_ids`, so the dependency graph can be drawn from the tasks alone, and changing an
+//! edge changes the version of both tasks. Edges that would make a task wait for itself are
+//! rejected.
+
+use near_sdk::{env, near_bindgen};
+
+use crate::details::TaskChanges;
+use crate::*;
+
+/// Maximum number of blockers of a task.
+pub const MAX_BLOCKERS: usize = 20;
+/// Maximum number of tasks a single task blocks. The dependent's owner pays for the edge on the
+/// blocker too, so this bounds what others can add to a task.
+pub const MAX_DEPENDENTS: usize = 50;
+
+#[near_bindgen]
+impl Contract {
+    // Public method - returns the active and archived tasks blocking a task
+    pub fn get_blockers(&self, task_id: TaskId) -> Vec<Task> {
+        self.any_task(task_id)
+            .map(|task| self.tasks_of(&task.blocker_ids))
+            .unwrap_or_default()
+    }
+
+    // Public method - returns the active and archived tasks blocked by a task
+    pub fn get_dependents(&self, task_id: TaskId) -> Vec<Task> {
+        self.any_task(task_id)
+            .map(|task| self.tasks_of(&task.dependent_ids))
+            .unwrap_or_default()
+    }
+
+    // Public method - block a task until another active task is done
+    // The caller has to be allowed to change both tasks. Adding an existing blocker does
+    // nothing. Storage is charged against the owner of the blocked task.
+    #[payable]
+    #[handle_result]
+    pub fn add_blocker(
+        &mut self,
+        task_id: TaskId,
+        blocker_id: TaskId,
+    ) -> Result<(), ContractError> {
+        let mut task = self.editable_task(task_id)?;
+        let mut blocker = self.editable_task(blocker_id)?;
+        if task.blocker_ids.contains(&blocker_id) {
+            return Ok(());
+        }
+        if task.blocker_ids.len() >= MAX_BLOCKERS {
+            return Err(ContractError::TooManyBlockers {
+                task_id,
+                max_count: MAX_BLOCKERS,
+            });
+        }
+        if blocker.dependent_ids.len() >= MAX_DEPENDENTS {
+            return Err(ContractError::TooManyDependents {
+                task_id: blocker_id,
+                max_count: MAX_DEPENDENTS,
+            });
+        }
+        if blocker_id == task_id || self.is_blocked_by(&blocker, task_id) {
+            return Err(ContractError::DependencyCycle {
+                task_id,
+                blocker_id,
+            });
+        }
+        let initial_storage_usage = env::storage_usage();
+        blocker.dependent_ids.push(task_id);
+        blocker.touch();
+        self.tasks.insert(&blocker_id, &blocker);
+        let old_blocker_ids = task.blocker_ids.clone();
+        task.blocker_ids.push(blocker_id);
+        self.change_blockers(&mut task, old_blocker_ids);
+        self.settle_storage_of(&task.owner_id, initial_storage_usage);
+        Ok(())
+    }
+
+    // Public method - remove a blocker from an active task
+    // The blocker may have been archived since.
+    #[payable]
+    #[handle_result]
+    pub fn remove_blocker(
+        &mut self,
+        task_id: TaskId,
+        blocker_id: TaskId,
+    ) -> Result<(), ContractError> {
+        let mut task = self.editable_task(task_id)?;
+        if !task.blocker_ids.contains(&blocker_id) {
+            return Err(ContractError::NotBlockedBy {
+                task_id,
+                blocker_id,
+            });
+        }
+        let initial_storage_usage = env::storage_usage();
+        self.update_any_task(blocker_id, |blocker| {
+            blocker.dependent_ids.retain(|&id| id != task_id);
+            blocker.touch();
+        });
+        let old_blocker_ids = task.blocker_ids.clone();
+        task.blocker_ids.retain(|&id| id != blocker_id);
+        self.change_blockers(&mut task, old_blocker_ids);
+        self.settle_storage_of(&task.owner_id, initial_storage_usage);
+        Ok(())
+    }
+}
+
+impl Contract {
+    fn tasks_of(&self, task_ids: &[TaskId]) -> Vec<Task> {
+        task_ids
+            .iter()
+            .filter_map(|&task_id| self.any_task(task_id))
+            .collect()
+    }
+
+    /// Whether the task waits for `blocker_id`, directly or through other blockers.
+    fn is_blocked_by(&self, task: &Task, blocker_id: TaskId) -> bool {
+        let mut visited = vec![task.id];
+        let mut pending = task.blocker_ids.clone();
+        while let Some(task_id) = pending.pop() {
+            if task_id == blocker_id {
+                return true;
+            }
+            if visited.contains(&task_id) {
+                continue;
+            }
+            visited.push(task_id);
+            if let Some(task) = self.any_task(task_id) {
+                pending.extend(task.blocker_ids);
+            }
+        }
+        false
+    }
+
+    /// Fails if a blocker of the task isn't done yet. Deleted blockers don't count.
+    pub(crate) fn ensure_unblocked(&self, task: &Task) -> Result<(), ContractError> {
+        let blocker_ids: Vec<TaskId> = self
+            .tasks_of(&task.blocker_ids)
+            .iter()
+            .filter(|blocker| blocker.task_status != TaskStatus::Done)
+            .map(|blocker| blocker.id)
+            .collect();
+        if !blocker_ids.is_empty() {
+            return Err(ContractError::Blocked {
+                task_id: task.id,
+                blocker_ids,
+            });
+        }
+        Ok(())
+    }
+
+    /// Removes the edges of a deleted task from its blockers and dependents.
+    pub(crate) fn detach_dependencies(&mut self, task: &Task) {
+        for &blocker_id in &task.blocker_ids {
+            self.update_any_task(blocker_id, |blocker| {
+                blocker.dependent_ids.retain(|&id| id != task.id);
+                blocker.touch();
+            });
+        }
+        for &dependent_id in &task.dependent_ids {
+            self.update_any_task(dependent_id, |dependent| {
+                dependent.blocker_ids.retain(|&id| id != task.id);
+                dependent.touch();
+            });
+        }
+    }
+
+    /// Stores the task with its changed blockers and records the change.
+    fn change_blockers(&mut self, task: &mut Task, old_blocker_ids: Vec<TaskId>) {
+        let mut changes = TaskChanges::default();
+        changes.record(task, "blocker_ids", old_blocker_ids, &task.blocker_ids);
+        task.touch();
+        self.tasks.insert(&task.id, task);
+        self.record_history(task, std::mem::take(&mut changes.history));
+        changes.emit();
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use super::*;
+    use near_sdk::test_utils::{accounts, VMContextBuilder};
+    use near_sdk::{testing_env, ONE_NEAR};
+
+    fn set_predecessor(account_id: AccountId) {
+        let mut context = VMContextBuilder::new();
+        context
+            .predecessor_account_id(account_id)
+            .account_balance(10 * ONE_NEAR)
+            .attached_deposit(ONE_NEAR / 100);
+        testing_env!(context.build());
+    }
+
+    #[test]
+    fn blocked_tasks_wait_for_their_blockers() {
+        set_predecessor(accounts(0));
+        let mut contract = Contract::default();
+        let design_id = contract.insert_task(String::from("design"));
+        let build_id = contract.insert_task(String::from("build"));
+        contract.add_blocker(build_id, design_id).unwrap();
+        assert_eq!(
+            contract.get_task(build_id).unwrap().blocker_ids,
+            [design_id]
+        );
+        assert_eq!(contract.get_dependents(design_id)[0].id, build_id);
+        assert_eq!(contract.get_blockers(build_id)[0].id, design_id);
+
+        assert_eq!(
+            contract.update_task(build_id, TaskStatus::InProgress, None),
+            Err(ContractError::Blocked {
+                task_id: build_id,
+                blocker_ids: vec![design_id]
+            })
+        );
+        assert!(contract.complete_task(build_id, true).is_err());
+        contract
+            .update_task(build_id, TaskStatus::Cancelled, None)
+            .unwrap();
+        contract
+            .update_task(build_id, TaskStatus::Todo, None)
+            .unwrap();
+
+        contract
+            .update_task(design_id, TaskStatus::Done, None)
+            .unwrap();
+        contract
+            .update_task(build_id, TaskStatus::InProgress, None)
+            .unwrap();
+    }
+
+    #[test]
+    fn rejects_cycles() {
+        set_predecessor(accounts(0));
+        let mut contract = Contract::default();
+        let a = contract.insert_task(String::from("task_a"));
+        let b = contract.insert_task(String::from("task_b"));
+        let c = contract.insert_task(String::from("task_c"));
+        contract.add_blocker(b, a).unwrap();
+        contract.add_blocker(c, b).unwrap();
+        contract.add_blocker(c, a).unwrap();
+
+        assert_eq!(
+            contract.add_blocker(a, c),
+            Err(ContractError::DependencyCycle {
+                task_id: a,
+                blocker_id: c
+            })
+        );
+        assert_eq!(
+            contract.add_blocker(a, a),
+            Err(ContractError::DependencyCycle {
+                task_id: a,
+                blocker_id: a
+            })
+        );
+
+        contract.remove_blocker(c, b).unwrap();
+        contract.remove_blocker(c, a).unwrap();
+        assert!(contract.get_dependents(a).iter().all(|task| task.id == b));
+        contract.add_blocker(a, c).unwrap();
+    }
+
+    #[test]
+    fn edges_change_the_version_of_both_tasks() {
+        set_predecessor(accounts(0));
+        let mut contract = Contract::default();
+        let a = contract.insert_task(String::from("task_a"));
+        let b = contract.insert_task(String::from("task_b"));
+        let version = |contract: &Contract, task_id| contract.get_task(task_id).unwrap().version;
+        contract.add_blocker(b, a).unwrap();
+        assert_eq!((version(&contract, a), version(&contract, b)), (1, 1));
+        contract.remove_blocker(b, a).unwrap();
+        assert_eq!((version(&contract, a), version(&contract, b)), (2, 2));
+
+        for _ in 0..MAX_DEPENDENTS {
+            set_predecessor(accounts(0));
+            let dependent_id = contract.insert_task(String::from("task_c"));
+            contract.add_blocker(dependent_id, a).unwrap();
+        }
+        assert_eq!(
+            contract.add_blocker(b, a),
+            Err(ContractError::TooManyDependents {
+                task_id: a,
+                max_count: MAX_DEPENDENTS
+            })
+        );
+    }
+
+    #[test]
+    fn deleting_a_task_removes_its_edges() {
+        set_predecessor(accounts(0));
+        let mut contract = Contract::default();
+        let a = contract.insert_task(String::from("task_a"));
+        let b = contract.insert_task(String::from("task_b"));
+        contract.add_blocker(b, a).unwrap();
+        contract.delete_task(a).unwrap();
+        assert!(contract.get_task(b).unwrap().blocker_ids.is_empty());
+        assert_eq!(contract.get_task(b).unwrap().version, 2);
+        contract
+            .update_task(b, TaskStatus::InProgress, None)
+            .unwrap();
+
+        set_predecessor(accounts(1));
+        let other = contract.insert_task(String::from("task_c"));
+        assert!(matches!(
+            contract.add_blocker(other, b),
+            Err(ContractError::NotTaskOwner { .. })
+        ));
+    }
+}
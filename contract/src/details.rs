@@ -0,0 +1,474 @@
+//! Optional task details: description, priority, due date and tags, with their limits, and the
+//! limit of the task name.
+
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::U64;
+use near_sdk::serde::{Deserialize, Deserializer, Serialize};
+use near_sdk::serde_json;
+use near_sdk::{env, AccountId};
+
+use crate::events::{StatusChangedData, TaskUpdatedData};
+use crate::recurrence::validate_recurrence;
+use crate::*;
+
+/// Maximum length of a task name, in characters.
+pub const MAX_TASK_NAME_LENGTH: usize = 100;
+/// Maximum length of a task description, in characters.
+pub const MAX_DESCRIPTION_LENGTH: usize = 1000;
+/// Maximum number of tags on a single task.
+pub const MAX_TAGS: usize = 10;
+/// Maximum length of a single tag, in characters.
+pub const MAX_TAG_LENGTH: usize = 32;
+
+/// How urgent a task is, `Normal` unless set otherwise.
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    Default,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    BorshDeserialize,
+    BorshSerialize,
+    Serialize,
+    Deserialize,
+)]
+#[serde(crate = "near_sdk::serde", rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum Priority {
+    Low,
+    #[default]
+    Normal,
+    High,
+    Urgent,
+}
+
+/// Arguments of `create_task`. Only `task_name` is required.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct NewTask {
+    pub task_name: String,
+    #[serde(default)]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub priority: Priority,
+    /// Block timestamp in nanoseconds, has to be in the future.
+    #[serde(default)]
+    pub due_date: Option<U64>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// Keep the changes of the task in its history, see `get_task_history`.
+    #[serde(default)]
+    pub track_history: bool,
+    /// Shared list to create the task in, the caller has to be an editor of it.
+    #[serde(default)]
+    pub list_id: Option<ListId>,
+    /// Task to create the task as a subtask of, the caller has to be allowed to change it.
+    /// The subtask is created in the list of its parent.
+    #[serde(default)]
+    pub parent_id: Option<TaskId>,
+    /// Create the next occurrence whenever the task is done.
+    #[serde(default)]
+    pub recurrence: Option<Recurrence>,
+}
+
+impl NewTask {
+    pub fn new(task_name: String) -> Self {
+        Self {
+            task_name,
+            ..Default::default()
+        }
+    }
+
+    /// Validates the details and builds the task to store.
+    pub(crate) fn into_task(self, id: TaskId, owner_id: AccountId) -> Result<Task, ContractError> {
+        let now = U64(env::block_timestamp());
+        Ok(Task {
+            id,
+            updated_by: owner_id.clone(),
+            owner_id,
+            task_name: validate_task_name(self.task_name)?,
+            task_status: TaskStatus::Todo,
+            description: validate_description(self.description)?,
+            priority: self.priority,
+            due_date: validate_due_date(self.due_date)?,
+            tags: validate_tags(self.tags)?,
+            created_at: now,
+            updated_at: now,
+            completed_at: None,
+            track_history: self.track_history,
+            list_id: self.list_id,
+            assignee: None,
+            parent_id: self.parent_id,
+            subtask_ids: Vec::new(),
+            checklist: Vec::new(),
+            blocker_ids: Vec::new(),
+            dependent_ids: Vec::new(),
+            recurrence: validate_recurrence(self.recurrence)?,
+            version: 0,
+        })
+    }
+}
+
+/// Arguments of `patch_task`: fields that are left out stay as they are.
+///
+/// `description`, `due_date` and `recurrence` can be cleared by passing `null`.
+/// `expected_version` isn't a field: the patch fails with `VersionConflict` unless the task is
+/// still at that `version`, so a stale edit doesn't overwrite changes made in the meantime. Only
+/// the methods taking a `TaskPatch` or an `expected_version` check it: `update_task`,
+/// `rename_task`, `patch_task` and `update_tasks`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct TaskPatch {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub task_name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub task_status: Option<TaskStatus>,
+    #[serde(
+        default,
+        deserialize_with = "deserialize_nullable",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub description: Option<Option<String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub priority: Option<Priority>,
+    #[serde(
+        default,
+        deserialize_with = "deserialize_nullable",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub due_date: Option<Option<U64>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tags: Option<Vec<String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub track_history: Option<bool>,
+    #[serde(
+        default,
+        deserialize_with = "deserialize_nullable",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub recurrence: Option<Option<Recurrence>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expected_version: Option<u64>,
+}
+
+impl TaskPatch {
+    /// Whether the patch only changes the status, which accepted assignees may do.
+    pub(crate) fn is_status_only(&self) -> bool {
+        self.task_status.is_some()
+            && self.task_name.is_none()
+            && self.description.is_none()
+            && self.priority.is_none()
+            && self.due_date.is_none()
+            && self.tags.is_none()
+            && self.track_history.is_none()
+            && self.recurrence.is_none()
+    }
+}
+
+/// Tells a field set to `null` (`Some(None)`) apart from a missing one (`None`).
+fn deserialize_nullable<'de, T, D>(deserializer: D) -> Result<Option<Option<T>>, D::Error>
+where
+    T: Deserialize<'de>,
+    D: Deserializer<'de>,
+{
+    Option::<T>::deserialize(deserializer).map(Some)
+}
+
+/// Events and history entries describing the changes made by `Task::apply_patch`.
+#[derive(Default)]
+pub(crate) struct TaskChanges {
+    pub updated: Vec<TaskUpdatedData>,
+    pub status_changed: Vec<StatusChangedData>,
+    pub history: Vec<TaskChange>,
+}
+
+impl TaskChanges {
+    pub(crate) fn record<T: Serialize + PartialEq>(
+        &mut self,
+        task: &Task,
+        field: &'static str,
+        old_value: T,
+        new_value: &T,
+    ) {
+        if old_value != *new_value {
+            self.history.push(TaskChange::FieldUpdated {
+                field: field.to_owned(),
+            });
+            self.updated.push(TaskUpdatedData {
+                owner_id: task.owner_id.clone(),
+                task_id: task.id,
+                field,
+                old_value: serde_json::to_value(old_value).unwrap(),
+                new_value: serde_json::to_value(new_value).unwrap(),
+            });
+        }
+    }
+
+    pub fn emit(self) {
+        if !self.status_changed.is_empty() {
+            TaskEvent::StatusChanged(self.status_changed).emit();
+        }
+        if !self.updated.is_empty() {
+            TaskEvent::TaskUpdated(self.updated).emit();
+        }
+    }
+}
+
+impl Task {
+    /// Validates the patch and applies it. Nothing is changed if any field is invalid.
+    pub(crate) fn apply_patch(&mut self, patch: TaskPatch) -> Result<TaskChanges, ContractError> {
+        if let Some(expected_version) = patch.expected_version {
+            if expected_version != self.version {
+                return Err(ContractError::VersionConflict {
+                    task_id: self.id,
+                    expected_version,
+                    version: self.version,
+                });
+            }
+        }
+        if let Some(task_status) = patch.task_status {
+            if !self.task_status.can_transition_to(task_status) {
+                return Err(ContractError::IllegalTransition {
+                    task_id: self.id,
+                    from: self.task_status,
+                    to: task_status,
+                });
+            }
+        }
+        let task_name = patch.task_name.map(validate_task_name).transpose()?;
+        let description = patch.description.map(validate_description).transpose()?;
+        let due_date = patch.due_date.map(validate_due_date).transpose()?;
+        let tags = patch.tags.map(validate_tags).transpose()?;
+        let recurrence = patch.recurrence.map(validate_recurrence).transpose()?;
+
+        let mut changes = TaskChanges::default();
+        if let Some(task_status) = patch.task_status {
+            changes.status_changed.push(StatusChangedData {
+                owner_id: self.owner_id.clone(),
+                task_id: self.id,
+                old_status: self.task_status,
+                new_status: task_status,
+            });
+            changes.history.push(TaskChange::StatusChanged {
+                from: self.task_status,
+                to: task_status,
+            });
+            self.task_status = task_status;
+            self.completed_at = match task_status {
+                TaskStatus::Done => Some(U64(env::block_timestamp())),
+                _ => None,
+            };
+        }
+        if let Some(task_name) = task_name {
+            let old_value = std::mem::replace(&mut self.task_name, task_name);
+            changes.record(self, "task_name", old_value, &self.task_name);
+        }
+        if let Some(description) = description {
+            let old_value = std::mem::replace(&mut self.description, description);
+            changes.record(self, "description", old_value, &self.description);
+        }
+        if let Some(priority) = patch.priority {
+            let old_value = std::mem::replace(&mut self.priority, priority);
+            changes.record(self, "priority", old_value, &self.priority);
+        }
+        if let Some(due_date) = due_date {
+            let old_value = std::mem::replace(&mut self.due_date, due_date);
+            changes.record(self, "due_date", old_value, &self.due_date);
+        }
+        if let Some(tags) = tags {
+            let old_value = std::mem::replace(&mut self.tags, tags);
+            changes.record(self, "tags", old_value, &self.tags);
+        }
+        if let Some(track_history) = patch.track_history {
+            let old_value = std::mem::replace(&mut self.track_history, track_history);
+            changes.record(self, "track_history", old_value, &self.track_history);
+        }
+        if let Some(recurrence) = recurrence {
+            let old_value = std::mem::replace(&mut self.recurrence, recurrence);
+            changes.record(self, "recurrence", old_value, &self.recurrence);
+        }
+        if !changes.history.is_empty() {
+            self.touch();
+        }
+        Ok(changes)
+    }
+
+    /// Records the predecessor as the last editor of the task and counts the change.
+    pub(crate) fn touch(&mut self) {
+        self.version += 1;
+        self.updated_at = U64(env::block_timestamp());
+        self.updated_by = env::predecessor_account_id();
+    }
+}
+
+pub(crate) fn validate_task_name(task_name: String) -> Result<String, ContractError> {
+    let length = task_name.chars().count();
+    if length > MAX_TASK_NAME_LENGTH {
+        return Err(ContractError::TaskNameTooLong {
+            length,
+            max_length: MAX_TASK_NAME_LENGTH,
+        });
+    }
+    Ok(task_name)
+}
+
+/// Trims the description, an empty one is stored as no description.
+pub(crate) fn validate_description(
+    description: Option<String>,
+) -> Result<Option<String>, ContractError> {
+    let description = match description {
+        Some(description) if !description.trim().is_empty() => description.trim().to_owned(),
+        _ => return Ok(None),
+    };
+    let length = description.chars().count();
+    if length > MAX_DESCRIPTION_LENGTH {
+        return Err(ContractError::DescriptionTooLong {
+            length,
+            max_length: MAX_DESCRIPTION_LENGTH,
+        });
+    }
+    Ok(Some(description))
+}
+
+pub(crate) fn validate_due_date(due_date: Option<U64>) -> Result<Option<U64>, ContractError> {
+    if let Some(due_date) = due_date {
+        let block_timestamp = env::block_timestamp();
+        if due_date.0 <= block_timestamp {
+            return Err(ContractError::DueDateInPast {
+                due_date: due_date.0,
+                block_timestamp,
+            });
+        }
+    }
+    Ok(due_date)
+}
+
+/// Lowercases the tags and drops duplicates, keeping the order in which they were given.
+/// Tags consist of `a-z`, `0-9`, `-` and `_`.
+pub(crate) fn validate_tags(tags: Vec<String>) -> Result<Vec<String>, ContractError> {
+    let mut normalized: Vec<String> = Vec::with_capacity(tags.len());
+    for tag in tags {
+        let tag = tag.trim().to_lowercase();
+        let is_valid = !tag.is_empty()
+            && tag.chars().count() <= MAX_TAG_LENGTH
+            && tag
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+        if !is_valid {
+            return Err(ContractError::InvalidTag(tag));
+        }
+        if !normalized.contains(&tag) {
+            normalized.push(tag);
+        }
+    }
+    if normalized.len() > MAX_TAGS {
+        return Err(ContractError::TooManyTags {
+            count: normalized.len(),
+            max_count: MAX_TAGS,
+        });
+    }
+    Ok(normalized)
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use super::*;
+    use near_sdk::serde_json::{self, json};
+    use near_sdk::test_utils::VMContextBuilder;
+    use near_sdk::testing_env;
+
+    #[test]
+    fn patch_tells_null_from_missing() {
+        let patch: TaskPatch =
+            serde_json::from_value(json!({"description": null, "priority": "HIGH"})).unwrap();
+        assert_eq!(patch.description, Some(None));
+        assert_eq!(patch.due_date, None);
+        assert_eq!(patch.priority, Some(Priority::High));
+        assert_eq!(patch.task_name, None);
+    }
+
+    #[test]
+    fn new_task_defaults() {
+        let task: NewTask = serde_json::from_value(json!({"task_name": "task_a"})).unwrap();
+        assert_eq!(task.priority, Priority::Normal);
+        assert!(task.tags.is_empty());
+        assert_eq!(task.due_date, None);
+    }
+
+    #[test]
+    fn validates_tags() {
+        assert_eq!(
+            validate_tags(vec![" Work ".into(), "work".into(), "q3-plan".into()]),
+            Ok(vec!["work".to_owned(), "q3-plan".to_owned()])
+        );
+        assert_eq!(
+            validate_tags(vec!["two words".into()]),
+            Err(ContractError::InvalidTag("two words".into()))
+        );
+        assert_eq!(
+            validate_tags(vec!["".into()]),
+            Err(ContractError::InvalidTag("".into()))
+        );
+        assert!(validate_tags(vec!["a".repeat(MAX_TAG_LENGTH + 1)]).is_err());
+        let too_many = (0..=MAX_TAGS).map(|i| format!("tag{}", i)).collect();
+        assert_eq!(
+            validate_tags(too_many),
+            Err(ContractError::TooManyTags {
+                count: MAX_TAGS + 1,
+                max_count: MAX_TAGS
+            })
+        );
+    }
+
+    #[test]
+    fn validates_description_and_due_date() {
+        let mut context = VMContextBuilder::new();
+        context.block_timestamp(1_000);
+        testing_env!(context.build());
+
+        assert_eq!(validate_description(Some("  ".into())), Ok(None));
+        assert_eq!(
+            validate_description(Some(" notes ".into())),
+            Ok(Some("notes".into()))
+        );
+        assert!(validate_description(Some("a".repeat(MAX_DESCRIPTION_LENGTH + 1))).is_err());
+
+        assert_eq!(validate_due_date(Some(U64(1_001))), Ok(Some(U64(1_001))));
+        assert_eq!(
+            validate_due_date(Some(U64(1_000))),
+            Err(ContractError::DueDateInPast {
+                due_date: 1_000,
+                block_timestamp: 1_000
+            })
+        );
+        assert_eq!(validate_due_date(None), Ok(None));
+    }
+
+    #[test]
+    fn validates_task_name_on_create_and_patch() {
+        testing_env!(VMContextBuilder::new().build());
+        let too_long = Some(ContractError::TaskNameTooLong {
+            length: MAX_TASK_NAME_LENGTH + 1,
+            max_length: MAX_TASK_NAME_LENGTH,
+        });
+        let owner_id: AccountId = "alice.near".parse().unwrap();
+        assert_eq!(
+            NewTask::new("é".repeat(MAX_TASK_NAME_LENGTH + 1))
+                .into_task(0, owner_id.clone())
+                .err(),
+            too_long
+        );
+
+        let mut task = NewTask::new("é".repeat(MAX_TASK_NAME_LENGTH))
+            .into_task(0, owner_id)
+            .unwrap();
+        let patch = TaskPatch {
+            task_name: Some("a".repeat(MAX_TASK_NAME_LENGTH + 1)),
+            ..Default::default()
+        };
+        assert_eq!(task.apply_patch(patch).err(), too_long);
+        assert_eq!(task.task_name, "é".repeat(MAX_TASK_NAME_LENGTH));
+    }
+}
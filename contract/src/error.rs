@@ -0,0 +1,446 @@
+use std::fmt;
+
+use near_sdk::{AccountId, FunctionError};
+
+use crate::{ListId, Role, TaskId, TaskStatus};
+
+/// Errors returned by contract methods marked with `#[handle_result]`.
+///
+/// The runtime panics with the `Display` output, which is what callers see as the
+/// failure message of the transaction.
+#[derive(Debug, PartialEq, Eq, FunctionError)]
+pub enum ContractError {
+    /// No task is stored under the given id.
+    TaskNotFound(TaskId),
+    /// The caller is not allowed to modify the task.
+    NotTaskOwner {
+        task_id: TaskId,
+        account_id: AccountId,
+    },
+    /// The requested status change is not allowed by the task state machine.
+    IllegalTransition {
+        task_id: TaskId,
+        from: TaskStatus,
+        to: TaskStatus,
+    },
+    /// The greeting is empty or only whitespace.
+    EmptyGreeting,
+    /// The greeting is longer than the allowed maximum.
+    GreetingTooLong { length: usize, max_length: usize },
+    /// The task name is longer than the allowed maximum.
+    TaskNameTooLong { length: usize, max_length: usize },
+    /// The task description is longer than the allowed maximum.
+    DescriptionTooLong { length: usize, max_length: usize },
+    /// The due date is not after the current block timestamp.
+    DueDateInPast { due_date: u64, block_timestamp: u64 },
+    /// The tag is empty, too long or contains characters other than `a-z`, `0-9`, `-` and `_`.
+    InvalidTag(String),
+    /// The task has more tags than allowed.
+    TooManyTags { count: usize, max_count: usize },
+    /// No task list is stored under the given id.
+    ListNotFound(ListId),
+    /// The caller doesn't have the role the action requires in the list.
+    NotPermitted {
+        list_id: ListId,
+        account_id: AccountId,
+        required: Role,
+    },
+    /// Only the owner of the list may do this.
+    NotListOwner {
+        list_id: ListId,
+        account_id: AccountId,
+    },
+    /// The owner of a list always has full access and can't be given a role.
+    OwnerIsNotCollaborator(ListId),
+    /// The account is not a collaborator of the list.
+    NotCollaborator {
+        list_id: ListId,
+        account_id: AccountId,
+    },
+    /// The list already has the maximum number of collaborators.
+    TooManyCollaborators { list_id: ListId, max_count: usize },
+    /// The account already owns or collaborates on the maximum number of lists.
+    TooManyLists {
+        account_id: AccountId,
+        max_count: usize,
+    },
+    /// The list still has active or archived tasks.
+    ListNotEmpty(ListId),
+    /// The list name is empty or only whitespace.
+    EmptyListName,
+    /// The list name is longer than the allowed maximum.
+    ListNameTooLong { length: usize, max_length: usize },
+    /// The task is already assigned to the account.
+    AlreadyAssigned {
+        task_id: TaskId,
+        account_id: AccountId,
+    },
+    /// The task isn't assigned to anyone.
+    NotAssigned(TaskId),
+    /// The task isn't assigned to the caller.
+    NotAssignee {
+        task_id: TaskId,
+        account_id: AccountId,
+    },
+    /// The assignee already accepted the task.
+    AlreadyAccepted(TaskId),
+    /// No deposit was attached to fund the bounty.
+    EmptyBounty,
+    /// The task has no bounty.
+    NoBounty(TaskId),
+    /// The bounty can't be refunded before it expires.
+    BountyNotExpired(TaskId),
+    /// The task is done or cancelled.
+    TaskClosed(TaskId),
+    /// The task isn't done or has no accepted assignee to pay the bounty to.
+    BountyNotReleasable(TaskId),
+    /// The accepted assignee started or finished the task, its bounty is owed to them.
+    BountyLocked(TaskId),
+    /// The account has no bounties left to withdraw.
+    NoUnclaimedBounty(AccountId),
+    /// The message of a token transfer doesn't name a task to fund.
+    InvalidBountyMessage(String),
+    /// The task owner doesn't accept the token as bounty, see `allow_bounty_token`.
+    TokenNotAllowed {
+        account_id: AccountId,
+        token_id: AccountId,
+    },
+    /// More bounty tokens than allowed for an account or a task.
+    TooManyBountyTokens { count: usize, max_count: usize },
+    /// The task isn't done.
+    TaskNotDone(TaskId),
+    /// The completion badge of the task was already minted.
+    BadgeAlreadyClaimed(TaskId),
+    /// Completion badges are bound to the owner of the task.
+    BadgeNotTransferable(String),
+    /// The parent task is already nested as deep as allowed.
+    SubtaskTooDeep { task_id: TaskId, max_depth: usize },
+    /// The parent task already has the maximum number of subtasks.
+    TooManySubtasks { task_id: TaskId, max_count: usize },
+    /// Subtasks or checklist items of the task are still open.
+    OpenSubtasks { task_id: TaskId, open_count: usize },
+    /// The task still has subtasks.
+    HasSubtasks(TaskId),
+    /// The checklist item is empty or only whitespace.
+    EmptyChecklistItem,
+    /// The checklist item is longer than the allowed maximum.
+    ChecklistItemTooLong { length: usize, max_length: usize },
+    /// The task already has the maximum number of checklist items.
+    TooManyChecklistItems { task_id: TaskId, max_count: usize },
+    /// The task has no checklist item at the index.
+    ChecklistItemNotFound { task_id: TaskId, index: u32 },
+    /// The task would end up blocking itself, directly or through other tasks.
+    DependencyCycle { task_id: TaskId, blocker_id: TaskId },
+    /// The task already has the maximum number of blockers.
+    TooManyBlockers { task_id: TaskId, max_count: usize },
+    /// The task already blocks the maximum number of tasks.
+    TooManyDependents { task_id: TaskId, max_count: usize },
+    /// The task is not blocked by the given task.
+    NotBlockedBy { task_id: TaskId, blocker_id: TaskId },
+    /// The task can't be started or finished before its blockers are done.
+    Blocked {
+        task_id: TaskId,
+        blocker_ids: Vec<TaskId>,
+    },
+    /// The interval of the recurrence rule is zero or longer than the allowed maximum.
+    InvalidRecurrence { days: u16, max_days: u16 },
+    /// No deposit was attached to stake.
+    EmptyStake,
+    /// No beneficiary for missed stakes was configured yet.
+    StakingNotConfigured,
+    /// The task already has a stake.
+    AlreadyStaked(TaskId),
+    /// Neither a deadline nor a due date was given for the stake.
+    NoDeadline(TaskId),
+    /// The task has no stake.
+    NoStake(TaskId),
+    /// The stake can't be slashed before its deadline has passed.
+    StakeNotExpired(TaskId),
+    /// The batch has more items than allowed.
+    BatchTooLarge { count: usize, max_count: usize },
+    /// An item of a batch failed, so none of the batch was applied.
+    BatchItemFailed {
+        index: usize,
+        error: Box<ContractError>,
+    },
+    /// The task was changed since the caller read it.
+    VersionConflict {
+        task_id: TaskId,
+        expected_version: u64,
+        version: u64,
+    },
+}
+
+impl fmt::Display for ContractError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ContractError::TaskNotFound(task_id) => write!(f, "Task {} not found", task_id),
+            ContractError::NotTaskOwner {
+                task_id,
+                account_id,
+            } => write!(f, "Account {} doesn't own task {}", account_id, task_id),
+            ContractError::IllegalTransition { task_id, from, to } => write!(
+                f,
+                "Illegal status transition for task {}: {} -> {}",
+                task_id, from, to
+            ),
+            ContractError::EmptyGreeting => write!(f, "The greeting can't be empty"),
+            ContractError::GreetingTooLong { length, max_length } => write!(
+                f,
+                "The greeting is {} bytes long, at most {} are allowed",
+                length, max_length
+            ),
+            ContractError::TaskNameTooLong { length, max_length } => write!(
+                f,
+                "The task name is {} characters long, at most {} are allowed",
+                length, max_length
+            ),
+            ContractError::DescriptionTooLong { length, max_length } => write!(
+                f,
+                "The description is {} characters long, at most {} are allowed",
+                length, max_length
+            ),
+            ContractError::DueDateInPast {
+                due_date,
+                block_timestamp,
+            } => write!(
+                f,
+                "The due date {} is not after the current block timestamp {}",
+                due_date, block_timestamp
+            ),
+            ContractError::InvalidTag(tag) => write!(
+                f,
+                "Invalid tag {:?}, tags are 1 to {} characters of a-z, 0-9, - and _",
+                tag,
+                crate::details::MAX_TAG_LENGTH
+            ),
+            ContractError::TooManyTags { count, max_count } => write!(
+                f,
+                "The task has {} tags, at most {} are allowed",
+                count, max_count
+            ),
+            ContractError::ListNotFound(list_id) => write!(f, "Task list {} not found", list_id),
+            ContractError::NotPermitted {
+                list_id,
+                account_id,
+                required,
+            } => write!(
+                f,
+                "Account {} needs the {} role in task list {}",
+                account_id, required, list_id
+            ),
+            ContractError::NotListOwner {
+                list_id,
+                account_id,
+            } => write!(
+                f,
+                "Account {} doesn't own task list {}",
+                account_id, list_id
+            ),
+            ContractError::OwnerIsNotCollaborator(list_id) => write!(
+                f,
+                "The owner of task list {} can't be added or removed as a collaborator",
+                list_id
+            ),
+            ContractError::NotCollaborator {
+                list_id,
+                account_id,
+            } => write!(
+                f,
+                "Account {} is not a collaborator of task list {}",
+                account_id, list_id
+            ),
+            ContractError::TooManyCollaborators { list_id, max_count } => write!(
+                f,
+                "Task list {} already has {} collaborators",
+                list_id, max_count
+            ),
+            ContractError::TooManyLists {
+                account_id,
+                max_count,
+            } => write!(
+                f,
+                "Account {} already owns or collaborates on {} task lists",
+                account_id, max_count
+            ),
+            ContractError::ListNotEmpty(list_id) => write!(
+                f,
+                "Task list {} still has tasks, delete them first",
+                list_id
+            ),
+            ContractError::EmptyListName => write!(f, "The list name can't be empty"),
+            ContractError::ListNameTooLong { length, max_length } => write!(
+                f,
+                "The list name is {} characters long, at most {} are allowed",
+                length, max_length
+            ),
+            ContractError::AlreadyAssigned {
+                task_id,
+                account_id,
+            } => write!(f, "Task {} is already assigned to {}", task_id, account_id),
+            ContractError::NotAssigned(task_id) => {
+                write!(f, "Task {} isn't assigned to anyone", task_id)
+            }
+            ContractError::NotAssignee {
+                task_id,
+                account_id,
+            } => write!(
+                f,
+                "Task {} isn't assigned to account {}",
+                task_id, account_id
+            ),
+            ContractError::AlreadyAccepted(task_id) => {
+                write!(f, "Task {} was already accepted", task_id)
+            }
+            ContractError::EmptyBounty => write!(f, "Attach a deposit to fund the bounty"),
+            ContractError::NoBounty(task_id) => write!(f, "Task {} has no bounty", task_id),
+            ContractError::BountyNotExpired(task_id) => {
+                write!(f, "The bounty of task {} hasn't expired yet", task_id)
+            }
+            ContractError::TaskClosed(task_id) => {
+                write!(f, "Task {} is already done or cancelled", task_id)
+            }
+            ContractError::BountyNotReleasable(task_id) => write!(
+                f,
+                "Task {} must be done by an accepted assignee to release its bounty",
+                task_id
+            ),
+            ContractError::BountyLocked(task_id) => write!(
+                f,
+                "The bounty of task {} is locked while its accepted assignee works on it",
+                task_id
+            ),
+            ContractError::NoUnclaimedBounty(account_id) => {
+                write!(f, "Account {} has no unclaimed bounties", account_id)
+            }
+            ContractError::InvalidBountyMessage(reason) => {
+                write!(f, "Invalid bounty message: {}", reason)
+            }
+            ContractError::TokenNotAllowed {
+                account_id,
+                token_id,
+            } => write!(
+                f,
+                "Account {} doesn't accept bounties in token {}",
+                account_id, token_id
+            ),
+            ContractError::TooManyBountyTokens { count, max_count } => write!(
+                f,
+                "That makes {} bounty tokens, at most {} are allowed",
+                count, max_count
+            ),
+            ContractError::TaskNotDone(task_id) => write!(f, "Task {} isn't done", task_id),
+            ContractError::BadgeAlreadyClaimed(task_id) => {
+                write!(f, "The badge of task {} was already claimed", task_id)
+            }
+            ContractError::BadgeNotTransferable(token_id) => {
+                write!(f, "Completion badge {} can't be transferred", token_id)
+            }
+            ContractError::SubtaskTooDeep { task_id, max_depth } => write!(
+                f,
+                "Task {} is nested {} levels deep, subtasks can't be added to it",
+                task_id, max_depth
+            ),
+            ContractError::TooManySubtasks { task_id, max_count } => write!(
+                f,
+                "Task {} already has {} subtasks, the maximum",
+                task_id, max_count
+            ),
+            ContractError::OpenSubtasks {
+                task_id,
+                open_count,
+            } => write!(
+                f,
+                "Task {} has {} open subtasks or checklist items, finish them or force it",
+                task_id, open_count
+            ),
+            ContractError::HasSubtasks(task_id) => {
+                write!(f, "Task {} has subtasks, delete them first", task_id)
+            }
+            ContractError::EmptyChecklistItem => write!(f, "The checklist item can't be empty"),
+            ContractError::ChecklistItemTooLong { length, max_length } => write!(
+                f,
+                "The checklist item is {} characters long, at most {} are allowed",
+                length, max_length
+            ),
+            ContractError::TooManyChecklistItems { task_id, max_count } => write!(
+                f,
+                "Task {} already has {} checklist items, the maximum",
+                task_id, max_count
+            ),
+            ContractError::ChecklistItemNotFound { task_id, index } => {
+                write!(f, "Task {} has no checklist item {}", task_id, index)
+            }
+            ContractError::DependencyCycle {
+                task_id,
+                blocker_id,
+            } => write!(
+                f,
+                "Task {} can't be blocked by task {}, that would create a cycle",
+                task_id, blocker_id
+            ),
+            ContractError::TooManyBlockers { task_id, max_count } => write!(
+                f,
+                "Task {} can't have more than {} blockers",
+                task_id, max_count
+            ),
+            ContractError::TooManyDependents { task_id, max_count } => write!(
+                f,
+                "Task {} can't block more than {} tasks",
+                task_id, max_count
+            ),
+            ContractError::NotBlockedBy {
+                task_id,
+                blocker_id,
+            } => write!(f, "Task {} is not blocked by task {}", task_id, blocker_id),
+            ContractError::Blocked {
+                task_id,
+                blocker_ids,
+            } => write!(
+                f,
+                "Task {} is blocked by unfinished tasks {:?}",
+                task_id, blocker_ids
+            ),
+            ContractError::InvalidRecurrence { days, max_days } => write!(
+                f,
+                "A task can recur every 1 to {} days, not every {} days",
+                max_days, days
+            ),
+            ContractError::EmptyStake => write!(f, "Attach a deposit to stake"),
+            ContractError::StakingNotConfigured => {
+                write!(f, "No beneficiary for missed stakes is configured")
+            }
+            ContractError::AlreadyStaked(task_id) => {
+                write!(f, "Task {} already has a stake", task_id)
+            }
+            ContractError::NoDeadline(task_id) => write!(
+                f,
+                "Task {} has no due date, pass a deadline for the stake",
+                task_id
+            ),
+            ContractError::NoStake(task_id) => write!(f, "Task {} has no stake", task_id),
+            ContractError::StakeNotExpired(task_id) => {
+                write!(
+                    f,
+                    "The deadline of the stake on task {} hasn't passed yet",
+                    task_id
+                )
+            }
+            ContractError::BatchTooLarge { count, max_count } => {
+                write!(f, "A batch has at most {} items, got {}", max_count, count)
+            }
+            ContractError::BatchItemFailed { index, error } => {
+                write!(f, "Item {} of the batch failed: {}", index, error)
+            }
+            ContractError::VersionConflict {
+                task_id,
+                expected_version,
+                version,
+            } => write!(
+                f,
+                "Task {} is at version {}, not {}, reload it and try again",
+                task_id, version, expected_version
+            ),
+        }
+    }
+}
@@ -0,0 +1,386 @@
+//! [NEP-297] events emitted for every task mutation.
+//!
+//! Events are logged as `EVENT_JSON:{"standard":"tasks","version":...,"event":...,"data":[...]}`,
+//! with `EVENT_STANDARD_VERSION` as version. `data` is a list so that a single event can describe several tasks. Batch calls log one event
+//! of each kind with the data of all their tasks, see `collect`.
+//!
+//! [NEP-297]: https://nomicon.io/Standards/EventsFormat
+
+use std::cell::RefCell;
+
+use near_sdk::json_types::{U128, U64};
+use near_sdk::serde::Serialize;
+use near_sdk::serde_json::{self, Value};
+use near_sdk::{env, AccountId};
+
+use crate::{ListId, Priority, Recurrence, Role, Stake, Task, TaskId, TaskList, TaskStatus};
+
+/// Name of the event standard implemented by the contract.
+pub const EVENT_STANDARD: &str = "tasks";
+/// Version of the event standard, bumped whenever the data of an event changes.
+pub const EVENT_STANDARD_VERSION: &str = "1.8.0";
+
+#[derive(Debug, Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct TaskCreatedData {
+    pub owner_id: AccountId,
+    pub task_id: TaskId,
+    pub task_name: String,
+    pub task_status: TaskStatus,
+    /// Since 1.1.0
+    pub description: Option<String>,
+    /// Since 1.1.0
+    pub priority: Priority,
+    /// Since 1.1.0
+    pub due_date: Option<U64>,
+    /// Since 1.1.0
+    pub tags: Vec<String>,
+    /// Since 1.2.0
+    pub list_id: Option<ListId>,
+    /// Since 1.6.0
+    pub parent_id: Option<TaskId>,
+    /// Since 1.7.0
+    pub recurrence: Option<Recurrence>,
+}
+
+impl From<&Task> for TaskCreatedData {
+    fn from(task: &Task) -> Self {
+        Self {
+            owner_id: task.owner_id.clone(),
+            task_id: task.id,
+            task_name: task.task_name.clone(),
+            task_status: task.task_status,
+            description: task.description.clone(),
+            priority: task.priority,
+            due_date: task.due_date,
+            tags: task.tags.clone(),
+            list_id: task.list_id,
+            parent_id: task.parent_id,
+            recurrence: task.recurrence,
+        }
+    }
+}
+
+/// A single changed field of a task, other than its status.
+#[derive(Debug, Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct TaskUpdatedData {
+    pub owner_id: AccountId,
+    pub task_id: TaskId,
+    pub field: &'static str,
+    pub old_value: Value,
+    pub new_value: Value,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct StatusChangedData {
+    pub owner_id: AccountId,
+    pub task_id: TaskId,
+    pub old_status: TaskStatus,
+    pub new_status: TaskStatus,
+}
+
+/// The task as it was when it got deleted, archived or restored.
+#[derive(Debug, Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct TaskRemovedData {
+    pub owner_id: AccountId,
+    pub task_id: TaskId,
+    pub task_name: String,
+    pub task_status: TaskStatus,
+}
+
+impl From<&Task> for TaskRemovedData {
+    fn from(task: &Task) -> Self {
+        Self {
+            owner_id: task.owner_id.clone(),
+            task_id: task.id,
+            task_name: task.task_name.clone(),
+            task_status: task.task_status,
+        }
+    }
+}
+
+/// Since 1.2.0
+#[derive(Debug, Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct ListData {
+    pub owner_id: AccountId,
+    pub list_id: ListId,
+    pub name: String,
+}
+
+impl From<&TaskList> for ListData {
+    fn from(list: &TaskList) -> Self {
+        Self {
+            owner_id: list.owner_id.clone(),
+            list_id: list.id,
+            name: list.name.clone(),
+        }
+    }
+}
+
+/// Since 1.2.0, `role` is omitted when the collaborator was removed.
+#[derive(Debug, Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct CollaboratorData {
+    pub list_id: ListId,
+    pub account_id: AccountId,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub role: Option<Role>,
+}
+
+/// Since 1.3.0, `assignee_id` is the account the event is about.
+#[derive(Debug, Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct AssignmentData {
+    pub owner_id: AccountId,
+    pub task_id: TaskId,
+    pub assignee_id: AccountId,
+}
+
+impl AssignmentData {
+    pub fn new(task: &Task, assignee_id: AccountId) -> Self {
+        Self {
+            owner_id: task.owner_id.clone(),
+            task_id: task.id,
+            assignee_id,
+        }
+    }
+}
+
+/// Since 1.4.0, `account_id` funded the bounty or received it. `task_id` is omitted only in the
+/// `bounty_unclaimed` event of a withdrawal of unclaimed bounties whose transfer failed again.
+#[derive(Debug, Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct BountyData {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub task_id: Option<TaskId>,
+    pub account_id: AccountId,
+    pub amount: U128,
+    /// Since 1.5.0, the NEP-141 token of the bounty, omitted for NEAR.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub token_id: Option<AccountId>,
+}
+
+/// Since 1.8.0, `account_id` staked, got the stake back or received the missed stake.
+#[derive(Debug, Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct StakeData {
+    pub task_id: TaskId,
+    pub account_id: AccountId,
+    pub amount: U128,
+}
+
+impl StakeData {
+    pub fn new(task_id: TaskId, stake: &Stake, account_id: &AccountId) -> Self {
+        Self {
+            task_id,
+            account_id: account_id.clone(),
+            amount: stake.amount,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+#[serde(
+    crate = "near_sdk::serde",
+    tag = "event",
+    content = "data",
+    rename_all = "snake_case"
+)]
+pub enum TaskEvent {
+    TaskCreated(Vec<TaskCreatedData>),
+    TaskUpdated(Vec<TaskUpdatedData>),
+    StatusChanged(Vec<StatusChangedData>),
+    TaskDeleted(Vec<TaskRemovedData>),
+    TaskArchived(Vec<TaskRemovedData>),
+    TaskRestored(Vec<TaskRemovedData>),
+    ListCreated(Vec<ListData>),
+    ListDeleted(Vec<ListData>),
+    CollaboratorAdded(Vec<CollaboratorData>),
+    CollaboratorRemoved(Vec<CollaboratorData>),
+    TaskAssigned(Vec<AssignmentData>),
+    TaskUnassigned(Vec<AssignmentData>),
+    AssignmentAccepted(Vec<AssignmentData>),
+    AssignmentDeclined(Vec<AssignmentData>),
+    BountyFunded(Vec<BountyData>),
+    BountyReleased(Vec<BountyData>),
+    BountyRefunded(Vec<BountyData>),
+    BountyUnclaimed(Vec<BountyData>),
+    StakeLocked(Vec<StakeData>),
+    StakeReclaimed(Vec<StakeData>),
+    StakeSlashed(Vec<StakeData>),
+}
+
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+struct EventLog<'a, E> {
+    standard: &'static str,
+    version: &'static str,
+    #[serde(flatten)]
+    event: &'a E,
+}
+
+/// Serializes an event in the NEP-297 format.
+fn to_log<E: Serialize>(event: &E) -> String {
+    let log = EventLog {
+        standard: EVENT_STANDARD,
+        version: EVENT_STANDARD_VERSION,
+        event,
+    };
+    format!("EVENT_JSON:{}", serde_json::to_string(&log).unwrap())
+}
+
+impl TaskEvent {
+    /// Serializes the event in the NEP-297 format.
+    pub fn to_log(&self) -> String {
+        to_log(self)
+    }
+
+    /// Logs the event, or holds it back until the end of the enclosing `collect`.
+    pub fn emit(self) {
+        let event = COLLECTED.with(|collected| match collected.borrow_mut().as_mut() {
+            Some(events) => {
+                events.push(self);
+                None
+            }
+            None => Some(self),
+        });
+        if let Some(event) = event {
+            env::log_str(&event.to_log());
+        }
+    }
+}
+
+thread_local! {
+    /// Events held back by `collect`, `None` outside of it.
+    static COLLECTED: RefCell<Option<Vec<TaskEvent>>> = const { RefCell::new(None) };
+}
+
+/// Events of the same kind merged into one.
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+struct MergedEvent {
+    event: String,
+    data: Vec<Value>,
+}
+
+/// Runs `f` holding back the events it emits, then logs one event of each kind with the data of
+/// all of them, in the order the kinds were first emitted. Nothing is logged if `f` fails.
+/// Calls don't nest.
+pub(crate) fn collect<T, E>(f: impl FnOnce() -> Result<T, E>) -> Result<T, E> {
+    COLLECTED.with(|collected| *collected.borrow_mut() = Some(Vec::new()));
+    let result = f();
+    let events = COLLECTED
+        .with(|collected| collected.borrow_mut().take())
+        .unwrap_or_default();
+    if result.is_ok() {
+        for event in merge(events) {
+            env::log_str(&to_log(&event));
+        }
+    }
+    result
+}
+
+fn merge(events: Vec<TaskEvent>) -> Vec<MergedEvent> {
+    let mut merged: Vec<MergedEvent> = Vec::new();
+    for event in events {
+        let mut fields = match serde_json::to_value(&event).unwrap() {
+            Value::Object(fields) => fields,
+            _ => unreachable!("events serialize to objects"),
+        };
+        let name = match fields.remove("event") {
+            Some(Value::String(name)) => name,
+            _ => unreachable!("events are tagged with their name"),
+        };
+        let data = match fields.remove("data") {
+            Some(Value::Array(data)) => data,
+            _ => unreachable!("event data is a list"),
+        };
+        match merged.iter_mut().find(|event| event.event == name) {
+            Some(event) => event.data.extend(data),
+            None => merged.push(MergedEvent { event: name, data }),
+        }
+    }
+    merged
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use super::*;
+    use near_sdk::serde_json::json;
+    use near_sdk::test_utils::{accounts, get_logs};
+
+    // The only test naming the version, every other one uses `EVENT_STANDARD_VERSION`.
+    #[test]
+    fn event_log_format() {
+        let event = TaskEvent::StatusChanged(vec![StatusChangedData {
+            owner_id: accounts(0),
+            task_id: 3,
+            old_status: TaskStatus::Todo,
+            new_status: TaskStatus::Done,
+        }]);
+        let log = event.to_log();
+        let json: Value = serde_json::from_str(log.strip_prefix("EVENT_JSON:").unwrap()).unwrap();
+        assert_eq!(
+            json,
+            json!({
+                "standard": "tasks",
+                "version": "1.8.0",
+                "event": "status_changed",
+                "data": [{
+                    "owner_id": "alice",
+                    "task_id": 3,
+                    "old_status": "TODO",
+                    "new_status": "DONE"
+                }]
+            })
+        );
+    }
+
+    #[test]
+    fn collected_events_are_merged_by_kind() {
+        let status_changed = |task_id| {
+            TaskEvent::StatusChanged(vec![StatusChangedData {
+                owner_id: accounts(0),
+                task_id,
+                old_status: TaskStatus::Todo,
+                new_status: TaskStatus::Done,
+            }])
+        };
+        let deleted = |task_id| {
+            TaskEvent::TaskDeleted(vec![TaskRemovedData {
+                owner_id: accounts(0),
+                task_id,
+                task_name: String::from("task_a"),
+                task_status: TaskStatus::Done,
+            }])
+        };
+        collect(|| {
+            status_changed(1).emit();
+            deleted(1).emit();
+            status_changed(2).emit();
+            Ok::<_, ()>(())
+        })
+        .unwrap();
+        let logs = get_logs();
+        assert_eq!(logs.len(), 2);
+        let json: Value =
+            serde_json::from_str(logs[0].strip_prefix("EVENT_JSON:").unwrap()).unwrap();
+        assert_eq!(json["event"], "status_changed");
+        assert_eq!(json["data"][1]["task_id"], 2);
+        assert!(logs[0].starts_with(&format!(
+            r#"EVENT_JSON:{{"standard":"tasks","version":"{}","event""#,
+            EVENT_STANDARD_VERSION
+        )));
+
+        assert!(collect(|| {
+            deleted(3).emit();
+            Err::<(), _>(())
+        })
+        .is_err());
+        assert_eq!(get_logs().len(), 2);
+    }
+}
@@ -0,0 +1,162 @@
+//! Per-account greetings shown by the frontend next to the task list.
+//!
+//! Every account sees `DEFAULT_GREETING` until it sets its own message. Like a task, a greeting
+//! is stored at the expense of its account's storage balance.
+
+use near_sdk::{env, log, near_bindgen, AccountId};
+
+use crate::*;
+
+/// Greeting returned for accounts that haven't set their own.
+pub const DEFAULT_GREETING: &str = "Hello";
+/// Maximum length of a greeting, in bytes.
+pub const MAX_GREETING_LENGTH: usize = 100;
+
+#[near_bindgen]
+impl Contract {
+    // Public method - returns the greeting of the caller
+    // Note: like `get_tasks` this only works in change calls, view calls should use
+    // `get_greeting_for` instead.
+    pub fn get_greeting(&self) -> String {
+        self.get_greeting_for(env::predecessor_account_id())
+    }
+
+    // Public method - returns the greeting of the given account, or the default one
+    pub fn get_greeting_for(&self, account_id: AccountId) -> String {
+        self.greetings
+            .get(&account_id)
+            .unwrap_or_else(|| DEFAULT_GREETING.to_owned())
+    }
+
+    // Public method - returns the greeting of accounts that haven't set their own
+    pub fn get_default_greeting(&self) -> String {
+        DEFAULT_GREETING.to_owned()
+    }
+
+    // Public method - sets the greeting of the caller
+    // Storage is handled like in `insert_task`.
+    #[payable]
+    #[handle_result]
+    pub fn set_greeting(&mut self, message: String) -> Result<(), ContractError> {
+        let length = message.len();
+        if message.trim().is_empty() {
+            return Err(ContractError::EmptyGreeting);
+        }
+        if length > MAX_GREETING_LENGTH {
+            return Err(ContractError::GreetingTooLong {
+                length,
+                max_length: MAX_GREETING_LENGTH,
+            });
+        }
+        let account_id = env::predecessor_account_id();
+        log!("Saving greeting {} for {}", message, account_id);
+        let initial_storage_usage = env::storage_usage();
+        self.greetings.insert(&account_id, &message);
+        self.settle_storage(initial_storage_usage);
+        Ok(())
+    }
+
+    // Public method - goes back to the default greeting for the caller
+    // The released storage is credited to the caller's storage balance.
+    pub fn reset_greeting(&mut self) {
+        let account_id = env::predecessor_account_id();
+        let initial_storage_usage = env::storage_usage();
+        if self.greetings.remove(&account_id).is_some() {
+            log!("Reset greeting for {}", account_id);
+            // Greetings set before they were charged may belong to unregistered accounts.
+            if self.storage_accounts.get(&account_id).is_some() {
+                self.charge_storage(&account_id, initial_storage_usage);
+            }
+        }
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use super::*;
+    use near_contract_standards::storage_management::StorageManagement;
+    use near_sdk::test_utils::{accounts, VMContextBuilder};
+    use near_sdk::{testing_env, Balance, ONE_NEAR};
+
+    fn set_context(predecessor: AccountId, deposit: Balance) {
+        let mut context = VMContextBuilder::new();
+        context
+            .predecessor_account_id(predecessor)
+            .account_balance(10 * ONE_NEAR)
+            .attached_deposit(deposit);
+        testing_env!(context.build());
+    }
+
+    fn setup(predecessor: AccountId) -> Contract {
+        set_context(predecessor, ONE_NEAR / 100);
+        Contract::default()
+    }
+
+    #[test]
+    fn get_default_greeting() {
+        let contract = setup(accounts(0));
+        assert_eq!(contract.get_greeting(), "Hello");
+        assert_eq!(contract.get_greeting_for(accounts(1)), "Hello");
+        assert_eq!(contract.get_default_greeting(), "Hello");
+    }
+
+    #[test]
+    fn set_then_get_greeting() {
+        let mut contract = setup(accounts(0));
+        contract.set_greeting("howdy".to_owned()).unwrap();
+        assert_eq!(contract.get_greeting(), "howdy");
+        assert_eq!(contract.get_greeting_for(accounts(0)), "howdy");
+        // Other accounts keep the default greeting.
+        assert_eq!(contract.get_greeting_for(accounts(1)), "Hello");
+
+        contract.reset_greeting();
+        assert_eq!(contract.get_greeting(), "Hello");
+    }
+
+    #[test]
+    fn greeting_length_is_bounded() {
+        let mut contract = setup(accounts(0));
+        assert_eq!(
+            contract.set_greeting(" ".to_owned()),
+            Err(ContractError::EmptyGreeting)
+        );
+        // The limit counts bytes, "é" takes two of them.
+        let long = "é".repeat(MAX_GREETING_LENGTH / 2 + 1);
+        assert_eq!(
+            contract.set_greeting(long),
+            Err(ContractError::GreetingTooLong {
+                length: MAX_GREETING_LENGTH + 2,
+                max_length: MAX_GREETING_LENGTH
+            })
+        );
+        contract
+            .set_greeting("é".repeat(MAX_GREETING_LENGTH / 2))
+            .unwrap();
+        assert_eq!(contract.get_greeting().len(), MAX_GREETING_LENGTH);
+    }
+
+    #[test]
+    fn greetings_are_charged_to_the_storage_balance() {
+        let mut contract = setup(accounts(0));
+        let available = |contract: &Contract| {
+            contract
+                .storage_balance_of(accounts(0))
+                .map(|balance| balance.available.0)
+        };
+        contract.set_greeting("howdy".to_owned()).unwrap();
+        let charged = available(&contract).unwrap();
+        assert!(charged < ONE_NEAR / 100);
+
+        set_context(accounts(0), 0);
+        contract.reset_greeting();
+        assert!(available(&contract).unwrap() > charged);
+    }
+
+    #[test]
+    #[should_panic(expected = "is not registered")]
+    fn unregistered_accounts_cannot_set_a_greeting() {
+        let mut contract = setup(accounts(0));
+        set_context(accounts(1), 0);
+        contract.set_greeting("howdy".to_owned()).unwrap();
+    }
+}
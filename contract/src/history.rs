@@ -0,0 +1,181 @@
+//! Optional per-task change history.
+//!
+//! Tasks created or patched with `track_history` keep their last `MAX_HISTORY_LENGTH` changes
+//! in a collection of their own, so the task records themselves stay small. The history is
+//! charged to the storage balance of the task's owner and is removed with the task.
+
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::U64;
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::{env, near_bindgen, AccountId};
+
+use crate::*;
+
+/// Number of changes kept per task, older ones are dropped first.
+pub const MAX_HISTORY_LENGTH: usize = 20;
+
+/// What happened to a task. Field updates only name the field to keep the history small,
+/// the values can be found in the `task_updated` events.
+#[derive(Debug, Clone, PartialEq, BorshDeserialize, BorshSerialize, Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde", tag = "type", rename_all = "snake_case")]
+pub enum TaskChange {
+    Created,
+    StatusChanged { from: TaskStatus, to: TaskStatus },
+    FieldUpdated { field: String },
+    Archived,
+    Restored,
+    Assigned { account_id: AccountId },
+    Unassigned,
+    AssignmentAccepted,
+    AssignmentDeclined,
+}
+
+#[derive(Debug, Clone, PartialEq, BorshDeserialize, BorshSerialize, Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct TaskHistoryEntry {
+    pub account_id: AccountId,
+    /// Block timestamp in nanoseconds.
+    pub timestamp: U64,
+    pub change: TaskChange,
+}
+
+#[near_bindgen]
+impl Contract {
+    // Public method - returns the recorded changes of a task, oldest first
+    pub fn get_task_history(&self, task_id: TaskId) -> Vec<TaskHistoryEntry> {
+        self.task_history.get(&task_id).unwrap_or_default()
+    }
+}
+
+impl Contract {
+    /// Appends the changes made by the predecessor to the task's history, if it is tracked.
+    pub(crate) fn record_history(&mut self, task: &Task, changes: Vec<TaskChange>) {
+        if !task.track_history || changes.is_empty() {
+            return;
+        }
+        let account_id = env::predecessor_account_id();
+        let timestamp = U64(env::block_timestamp());
+        let mut history = self.task_history.get(&task.id).unwrap_or_default();
+        history.extend(changes.into_iter().map(|change| TaskHistoryEntry {
+            account_id: account_id.clone(),
+            timestamp,
+            change,
+        }));
+        let overflow = history.len().saturating_sub(MAX_HISTORY_LENGTH);
+        history.drain(..overflow);
+        self.task_history.insert(&task.id, &history);
+    }
+
+    pub(crate) fn remove_history(&mut self, task_id: TaskId) {
+        self.task_history.remove(&task_id);
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use super::*;
+    use near_sdk::test_utils::{accounts, VMContextBuilder};
+    use near_sdk::{testing_env, ONE_NEAR};
+
+    fn set_context(block_timestamp: u64) {
+        let mut context = VMContextBuilder::new();
+        context
+            .predecessor_account_id(accounts(0))
+            .account_balance(10 * ONE_NEAR)
+            .attached_deposit(ONE_NEAR / 100)
+            .block_timestamp(block_timestamp);
+        testing_env!(context.build());
+    }
+
+    fn tracked_task(contract: &mut Contract) -> TaskId {
+        contract
+            .create_task(NewTask {
+                track_history: true,
+                ..NewTask::new(String::from("task_a"))
+            })
+            .unwrap()
+    }
+
+    #[test]
+    fn records_who_finished_a_task_and_when() {
+        set_context(1_000);
+        let mut contract = Contract::default();
+        let task_id = tracked_task(&mut contract);
+
+        set_context(2_000);
+        contract
+            .update_task(task_id, TaskStatus::Done, None)
+            .unwrap();
+
+        assert_eq!(
+            contract.get_task_history(task_id),
+            vec![
+                TaskHistoryEntry {
+                    account_id: accounts(0),
+                    timestamp: U64(1_000),
+                    change: TaskChange::Created,
+                },
+                TaskHistoryEntry {
+                    account_id: accounts(0),
+                    timestamp: U64(2_000),
+                    change: TaskChange::StatusChanged {
+                        from: TaskStatus::Todo,
+                        to: TaskStatus::Done,
+                    },
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn history_is_bounded() {
+        set_context(0);
+        let mut contract = Contract::default();
+        let task_id = tracked_task(&mut contract);
+        for i in 0..MAX_HISTORY_LENGTH {
+            contract
+                .rename_task(task_id, format!("task_{}", i), None)
+                .unwrap();
+        }
+
+        let history = contract.get_task_history(task_id);
+        assert_eq!(history.len(), MAX_HISTORY_LENGTH);
+        // The creation was the oldest entry and got dropped.
+        assert!(history.iter().all(|entry| entry.change
+            == TaskChange::FieldUpdated {
+                field: String::from("task_name")
+            }));
+    }
+
+    #[test]
+    fn untracked_tasks_have_no_history() {
+        set_context(0);
+        let mut contract = Contract::default();
+        let task_id = contract.insert_task(String::from("task_a"));
+        contract
+            .update_task(task_id, TaskStatus::Done, None)
+            .unwrap();
+        assert!(contract.get_task_history(task_id).is_empty());
+    }
+
+    #[test]
+    fn disabling_tracking_removes_history() {
+        set_context(0);
+        let mut contract = Contract::default();
+        let task_id = tracked_task(&mut contract);
+        contract
+            .patch_task(
+                task_id,
+                TaskPatch {
+                    track_history: Some(false),
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+        assert!(contract.get_task_history(task_id).is_empty());
+
+        let task_id = tracked_task(&mut contract);
+        contract.delete_task(task_id).unwrap();
+        assert!(contract.get_task_history(task_id).is_empty());
+    }
+}
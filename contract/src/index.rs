@@ -0,0 +1,255 @@
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::collections::{LookupMap, UnorderedSet};
+use near_sdk::{env, AccountId};
+
+use crate::TaskId;
+
+/// Number of tasks returned by paginated views when no `limit` is given.
+pub const DEFAULT_PAGE_SIZE: u64 = 50;
+/// Upper bound for `limit` in paginated views, keeps a single view call within gas limits.
+pub const MAX_PAGE_SIZE: u64 = 100;
+
+/// Key of a `TaskIndex`, turned into bytes to derive the prefix of its set.
+pub trait IndexKey: BorshSerialize + BorshDeserialize {
+    fn key_bytes(&self) -> Vec<u8>;
+}
+
+impl IndexKey for AccountId {
+    fn key_bytes(&self) -> Vec<u8> {
+        self.as_bytes().to_vec()
+    }
+}
+
+impl IndexKey for u64 {
+    fn key_bytes(&self) -> Vec<u8> {
+        self.to_le_bytes().to_vec()
+    }
+}
+
+/// Per-account (or per-list) set of task ids.
+///
+/// Every account gets its own `UnorderedSet` under `set_prefix ++ sha256(account_id)`, so adding
+/// or removing a task only touches a couple of storage entries instead of rewriting a `Vec`
+/// with all of the account's ids.
+///
+/// Indexes created before the per-account sets store a `Vec<TaskId>` per account under
+/// `legacy_prefix`. Those are still served by the views and are moved into a set by
+/// `migrate_account` on the owner's next write.
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct TaskIndex<K: IndexKey = AccountId> {
+    sets: LookupMap<K, UnorderedSet<TaskId>>,
+    set_prefix: Vec<u8>,
+    legacy: Option<LookupMap<K, Vec<TaskId>>>,
+}
+
+impl<K: IndexKey> TaskIndex<K> {
+    pub fn new(prefix: &[u8], set_prefix: &[u8]) -> Self {
+        Self {
+            sets: LookupMap::new(prefix),
+            set_prefix: set_prefix.to_vec(),
+            legacy: None,
+        }
+    }
+
+    /// Serves and migrates accounts from a `Vec` based index stored under `legacy_prefix`.
+    pub fn with_legacy(mut self, legacy_prefix: &[u8]) -> Self {
+        self.legacy = Some(LookupMap::new(legacy_prefix));
+        self
+    }
+
+    fn new_set(&self, account_id: &K) -> UnorderedSet<TaskId> {
+        let prefix = [
+            self.set_prefix.as_slice(),
+            &env::sha256(&account_id.key_bytes()),
+        ]
+        .concat();
+        UnorderedSet::new(prefix)
+    }
+
+    fn legacy_ids(&self, account_id: &K) -> Option<Vec<TaskId>> {
+        self.legacy
+            .as_ref()
+            .and_then(|legacy| legacy.get(account_id))
+    }
+
+    pub fn insert(&mut self, account_id: &K, task_id: TaskId) {
+        self.migrate_account(account_id);
+        let mut set = self
+            .sets
+            .get(account_id)
+            .unwrap_or_else(|| self.new_set(account_id));
+        set.insert(&task_id);
+        self.sets.insert(account_id, &set);
+    }
+
+    /// Removes the task id, dropping the account's set once it is empty.
+    pub fn remove(&mut self, account_id: &K, task_id: TaskId) -> bool {
+        self.migrate_account(account_id);
+        let mut set = match self.sets.get(account_id) {
+            Some(set) => set,
+            None => return false,
+        };
+        let removed = set.remove(&task_id);
+        if set.is_empty() {
+            self.sets.remove(account_id);
+        } else {
+            self.sets.insert(account_id, &set);
+        }
+        removed
+    }
+
+    /// Returns all task ids of the account, for change calls that have to visit each of them.
+    pub fn ids(&self, account_id: &K) -> Vec<TaskId> {
+        match self.sets.get(account_id) {
+            Some(set) => set.to_vec(),
+            None => self.legacy_ids(account_id).unwrap_or_default(),
+        }
+    }
+
+    /// Removes all task ids of the account and returns them.
+    pub fn remove_all(&mut self, account_id: &K) -> Vec<TaskId> {
+        self.migrate_account(account_id);
+        match self.sets.remove(account_id) {
+            Some(mut set) => {
+                let task_ids = set.to_vec();
+                set.clear();
+                task_ids
+            }
+            None => Vec::new(),
+        }
+    }
+
+    pub fn contains(&self, account_id: &K, task_id: TaskId) -> bool {
+        match self.sets.get(account_id) {
+            Some(set) => set.contains(&task_id),
+            None => self
+                .legacy_ids(account_id)
+                .is_some_and(|ids| ids.contains(&task_id)),
+        }
+    }
+
+    pub fn len(&self, account_id: &K) -> u64 {
+        match self.sets.get(account_id) {
+            Some(set) => set.len(),
+            None => self
+                .legacy_ids(account_id)
+                .map_or(0, |ids| ids.len() as u64),
+        }
+    }
+
+    pub fn is_empty(&self, account_id: &K) -> bool {
+        self.len(account_id) == 0
+    }
+
+    /// Returns up to `limit` task ids starting at `from_index`, in insertion order as long as
+    /// no task was removed (removal moves the last task id into the freed slot).
+    pub fn page(&self, account_id: &K, from_index: Option<u64>, limit: Option<u64>) -> Vec<TaskId> {
+        let from_index = from_index.unwrap_or(0);
+        let limit = limit.unwrap_or(DEFAULT_PAGE_SIZE).min(MAX_PAGE_SIZE) as usize;
+        match self.sets.get(account_id) {
+            Some(set) => set
+                .as_vector()
+                .iter()
+                .skip(from_index as usize)
+                .take(limit)
+                .collect(),
+            None => self
+                .legacy_ids(account_id)
+                .unwrap_or_default()
+                .into_iter()
+                .skip(from_index as usize)
+                .take(limit)
+                .collect(),
+        }
+    }
+
+    /// Moves the account's ids from the legacy `Vec` index into its set, if there are any.
+    pub fn migrate_account(&mut self, account_id: &K) {
+        let legacy_ids = match self
+            .legacy
+            .as_mut()
+            .and_then(|legacy| legacy.remove(account_id))
+        {
+            Some(legacy_ids) => legacy_ids,
+            None => return,
+        };
+        let mut set = self
+            .sets
+            .get(account_id)
+            .unwrap_or_else(|| self.new_set(account_id));
+        set.extend(legacy_ids);
+        if !set.is_empty() {
+            self.sets.insert(account_id, &set);
+        }
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use super::*;
+    use near_sdk::test_utils::{accounts, VMContextBuilder};
+    use near_sdk::testing_env;
+
+    #[test]
+    fn insert_remove_and_page() {
+        testing_env!(VMContextBuilder::new().build());
+        let alice = accounts(0);
+        let bob = accounts(1);
+        let mut index = TaskIndex::new(b"a", b"A");
+        for task_id in 0..5 {
+            index.insert(&alice, task_id);
+        }
+        index.insert(&bob, 5);
+
+        assert_eq!(index.len(&alice), 5);
+        assert_eq!(index.page(&alice, None, None), vec![0, 1, 2, 3, 4]);
+        assert_eq!(index.page(&alice, Some(1), Some(2)), vec![1, 2]);
+        assert_eq!(index.page(&alice, Some(10), None), Vec::<TaskId>::new());
+        assert_eq!(index.page(&bob, None, None), vec![5]);
+
+        assert!(index.remove(&alice, 1));
+        assert!(!index.remove(&alice, 1));
+        assert_eq!(index.page(&alice, None, None), vec![0, 4, 2, 3]);
+        assert!(index.contains(&alice, 4));
+        assert!(!index.contains(&bob, 4));
+
+        assert_eq!(index.remove_all(&alice).len(), 4);
+        assert!(index.is_empty(&alice));
+        assert_eq!(index.len(&bob), 1);
+    }
+
+    #[test]
+    fn page_size_is_bounded() {
+        testing_env!(VMContextBuilder::new().build());
+        let alice = accounts(0);
+        let mut index = TaskIndex::new(b"a", b"A");
+        for task_id in 0..(MAX_PAGE_SIZE + 10) {
+            index.insert(&alice, task_id);
+        }
+        assert_eq!(
+            index.page(&alice, None, None).len() as u64,
+            DEFAULT_PAGE_SIZE
+        );
+        assert_eq!(
+            index.page(&alice, None, Some(1000)).len() as u64,
+            MAX_PAGE_SIZE
+        );
+    }
+
+    #[test]
+    fn serves_and_migrates_legacy_vec_index() {
+        testing_env!(VMContextBuilder::new().build());
+        let alice = accounts(0);
+        let mut legacy: LookupMap<AccountId, Vec<TaskId>> = LookupMap::new(b"v");
+        legacy.insert(&alice, &vec![3, 1, 2]);
+
+        let mut index = TaskIndex::new(b"a", b"A").with_legacy(b"v");
+        assert_eq!(index.len(&alice), 3);
+        assert_eq!(index.page(&alice, Some(1), None), vec![1, 2]);
+        assert!(index.contains(&alice, 3));
+
+        index.insert(&alice, 4);
+        assert!(!legacy.contains_key(&alice));
+        assert_eq!(index.page(&alice, None, None), vec![3, 1, 2, 4]);
+    }
+}
@@ -0,0 +1,367 @@
+//! Support for state written by earlier versions of the contract, which keyed tasks by
+//! `<owner>.<task_name>` strings under the `ta`/`t` prefixes.
+//!
+//! Neither collection can be iterated, so tasks are moved account by account: lazily on the
+//! owner's next `insert_task`, or explicitly through `migrate_legacy_tasks`.
+
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::collections::LookupMap;
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::{env, log, near_bindgen, AccountId};
+
+use crate::*;
+
+const LEGACY_TASKS_BY_ACCOUNT_PREFIX: &[u8] = b"ta";
+const LEGACY_TASKS_PREFIX: &[u8] = b"t";
+
+type LegacyTaskId = String;
+
+/// Contract state before tasks had numeric ids.
+#[derive(BorshDeserialize)]
+pub(crate) struct LegacyContract {
+    #[allow(dead_code)]
+    tasks_by_account: LookupMap<AccountId, Vec<LegacyTaskId>>,
+    #[allow(dead_code)]
+    tasks: LookupMap<LegacyTaskId, LegacyTask>,
+}
+
+/// Task record with a string id and a typed status.
+#[derive(BorshDeserialize, BorshSerialize)]
+struct LegacyTask {
+    id: LegacyTaskId,
+    task_name: String,
+    task_status: TaskStatus,
+}
+
+/// Task record with a string id and a free-form status string.
+#[derive(BorshDeserialize)]
+struct UntypedLegacyTask {
+    id: LegacyTaskId,
+    task_name: String,
+    task_status: String,
+}
+
+impl UntypedLegacyTask {
+    fn into_typed(self) -> LegacyTask {
+        let task_status = TaskStatus::from_legacy(&self.task_status).unwrap_or_else(|| {
+            log!(
+                "Unknown status {} for task {}, resetting to {}",
+                self.task_status,
+                self.id,
+                TaskStatus::Todo
+            );
+            TaskStatus::Todo
+        });
+        LegacyTask {
+            id: self.id,
+            task_name: self.task_name,
+            task_status,
+        }
+    }
+}
+
+/// How `reconcile_orphaned_tasks` resolves a task that is missing from its owner's index.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde", rename_all = "snake_case")]
+pub enum OrphanResolution {
+    /// Add the task to its owner's index so it gets migrated with the owner's other tasks.
+    Adopt,
+    /// Remove the task record from storage.
+    Remove,
+}
+
+fn legacy_tasks_by_account() -> LookupMap<AccountId, Vec<LegacyTaskId>> {
+    LookupMap::new(LEGACY_TASKS_BY_ACCOUNT_PREFIX)
+}
+
+fn legacy_task_key(task_id: &LegacyTaskId) -> Vec<u8> {
+    [LEGACY_TASKS_PREFIX, &task_id.try_to_vec().unwrap()].concat()
+}
+
+/// Returns the owner part of a `<owner>.<task_name>` id, `None` if the id doesn't end with the
+/// name of the task stored under it.
+fn legacy_owner<'a>(task_id: &'a str, task: &LegacyTask) -> Option<&'a str> {
+    task_id
+        .strip_suffix(task.task_name.as_str())?
+        .strip_suffix('.')
+}
+
+/// Reads a legacy task in either of its two historical layouts.
+fn read_legacy_task(task_id: &LegacyTaskId) -> Option<LegacyTask> {
+    let raw = env::storage_read(&legacy_task_key(task_id))?;
+    let task = LegacyTask::try_from_slice(&raw)
+        .or_else(|_| UntypedLegacyTask::try_from_slice(&raw).map(UntypedLegacyTask::into_typed))
+        .unwrap_or_else(|_| env::panic_str("Cannot deserialize legacy task"));
+    Some(task)
+}
+
+#[near_bindgen]
+impl Contract {
+    // Public method - moves all tasks the given account created before the id migration
+    // to the current layout and returns their new ids. Anyone may call it.
+    pub fn migrate_legacy_tasks(&mut self, account_id: AccountId) -> Vec<TaskId> {
+        self.migrate_legacy_tasks_of(&account_id)
+    }
+
+    // Public method - returns the id a task created before the id migration was given
+    pub fn get_migrated_task_id(&self, legacy_task_id: String) -> Option<TaskId> {
+        self.legacy_task_ids.get(&legacy_task_id)
+    }
+
+    // Public method - returns the given legacy task ids that are stored but missing from
+    // the owner's legacy index, i.e. records written by `update_task` for tasks that were never
+    // inserted. `LookupMap` can't be iterated, so candidate ids have to be supplied by the caller
+    // (for example collected from the contract's "Update task" logs by an indexer).
+    // Account ids and task names may both contain dots, so the owner of an id is taken from the
+    // stored record: the id minus `.<task_name>` has to be exactly `account_id`.
+    pub fn find_orphaned_tasks(&self, account_id: AccountId, task_ids: Vec<String>) -> Vec<String> {
+        let indexed = legacy_tasks_by_account()
+            .get(&account_id)
+            .unwrap_or_default();
+        task_ids
+            .into_iter()
+            .filter(|task_id| {
+                !indexed.contains(task_id)
+                    && read_legacy_task(task_id).is_some_and(|task| {
+                        legacy_owner(task_id, &task) == Some(account_id.as_str())
+                    })
+            })
+            .collect()
+    }
+
+    // Private method - adopts or removes the orphaned tasks among the given legacy ids.
+    // Returns the ids that were reconciled.
+    #[private]
+    pub fn reconcile_orphaned_tasks(
+        &mut self,
+        account_id: AccountId,
+        task_ids: Vec<String>,
+        resolution: OrphanResolution,
+    ) -> Vec<String> {
+        let orphans = self.find_orphaned_tasks(account_id.clone(), task_ids);
+        match resolution {
+            OrphanResolution::Adopt => {
+                let mut index = legacy_tasks_by_account();
+                let mut indexed = index.get(&account_id).unwrap_or_default();
+                indexed.extend(orphans.iter().cloned());
+                index.insert(&account_id, &indexed);
+            }
+            OrphanResolution::Remove => {
+                for task_id in orphans.iter() {
+                    env::storage_remove(&legacy_task_key(task_id));
+                }
+            }
+        }
+        log!(
+            "Reconciled {} orphaned tasks of {} ({:?})",
+            orphans.len(),
+            account_id,
+            resolution
+        );
+        orphans
+    }
+}
+
+impl Contract {
+    /// Moves the account's legacy tasks to freshly allocated ids, keeping their order.
+    /// Legacy ids listed more than once (inserting the same name twice) are migrated once.
+    pub(crate) fn migrate_legacy_tasks_of(&mut self, account_id: &AccountId) -> Vec<TaskId> {
+        let mut index = legacy_tasks_by_account();
+        let legacy_ids = match index.remove(account_id) {
+            Some(legacy_ids) => legacy_ids,
+            None => return Vec::new(),
+        };
+        let mut migrated = Vec::new();
+        let mut created = Vec::new();
+        for legacy_id in legacy_ids {
+            if self.legacy_task_ids.contains_key(&legacy_id) {
+                continue;
+            }
+            let legacy = match read_legacy_task(&legacy_id) {
+                Some(legacy) => legacy,
+                None => continue,
+            };
+            let task_id = self.next_task_id;
+            self.next_task_id += 1;
+            let task = Task {
+                id: task_id,
+                owner_id: account_id.clone(),
+                task_name: legacy.task_name,
+                task_status: legacy.task_status,
+                description: None,
+                priority: Priority::default(),
+                due_date: None,
+                tags: Vec::new(),
+                // The legacy layout didn't keep track of when tasks were created.
+                created_at: U64(0),
+                updated_at: U64(0),
+                updated_by: account_id.clone(),
+                completed_at: None,
+                track_history: false,
+                list_id: None,
+                assignee: None,
+                parent_id: None,
+                subtask_ids: Vec::new(),
+                checklist: Vec::new(),
+                blocker_ids: Vec::new(),
+                dependent_ids: Vec::new(),
+                recurrence: None,
+                version: 0,
+            };
+            self.tasks.insert(&task_id, &task);
+            self.legacy_task_ids.insert(&legacy_id, &task_id);
+            env::storage_remove(&legacy_task_key(&legacy_id));
+            self.tasks_by_account.insert(account_id, task_id);
+            created.push((&task).into());
+            migrated.push(task_id);
+        }
+        if !created.is_empty() {
+            TaskEvent::TaskCreated(created).emit();
+        }
+        log!("Migrated {} legacy tasks of {}", migrated.len(), account_id);
+        migrated
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use super::*;
+    use near_sdk::test_utils::{accounts, VMContextBuilder};
+    use near_sdk::{testing_env, ONE_NEAR};
+
+    #[derive(BorshSerialize)]
+    struct OldTask {
+        id: LegacyTaskId,
+        task_name: String,
+        task_status: String,
+    }
+
+    fn write_legacy_task(owner: &AccountId, task_name: &str, task_status: &str) -> LegacyTaskId {
+        let task_id = format!("{}.{}", owner, task_name);
+        let task = OldTask {
+            id: task_id.clone(),
+            task_name: task_name.to_owned(),
+            task_status: task_status.to_owned(),
+        };
+        env::storage_write(&legacy_task_key(&task_id), &task.try_to_vec().unwrap());
+        task_id
+    }
+
+    fn setup() -> Contract {
+        let mut context = VMContextBuilder::new();
+        context
+            .current_account_id(accounts(0))
+            .predecessor_account_id(accounts(0))
+            .account_balance(10 * ONE_NEAR)
+            .attached_deposit(ONE_NEAR / 100);
+        testing_env!(context.build());
+        Contract::default()
+    }
+
+    #[test]
+    fn migrates_legacy_tasks_to_numeric_ids() {
+        let mut contract = setup();
+        let alice = accounts(0);
+        let task_a = write_legacy_task(&alice, "task_a", "DONE");
+        let task_b = write_legacy_task(&alice, "task_b", "in progress");
+        let task_c = write_legacy_task(&alice, "task_c", "someday");
+        // Inserting `task_a` twice used to list its id twice.
+        let legacy_ids = vec![task_a.clone(), task_b, task_a.clone(), task_c];
+        legacy_tasks_by_account().insert(&alice, &legacy_ids);
+
+        assert_eq!(contract.migrate_legacy_tasks(alice.clone()), vec![0, 1, 2]);
+        let tasks = contract.get_tasks_for(alice.clone(), None, None);
+        let names: Vec<&str> = tasks.iter().map(|t| t.task_name.as_str()).collect();
+        assert_eq!(names, vec!["task_a", "task_b", "task_c"]);
+        let statuses: Vec<TaskStatus> = tasks.iter().map(|t| t.task_status).collect();
+        assert_eq!(
+            statuses,
+            vec![TaskStatus::Done, TaskStatus::InProgress, TaskStatus::Todo]
+        );
+        assert_eq!(contract.get_migrated_task_id(task_a.clone()), Some(0));
+        assert!(!env::storage_has_key(&legacy_task_key(&task_a)));
+
+        // Migration is idempotent, and new tasks continue the id sequence.
+        assert!(contract.migrate_legacy_tasks(alice.clone()).is_empty());
+        assert_eq!(contract.insert_task(String::from("task_d")), 3);
+        assert_eq!(contract.get_task_count_for(alice), 4);
+    }
+
+    #[test]
+    fn insert_task_migrates_legacy_tasks_first() {
+        let mut contract = setup();
+        let alice = accounts(0);
+        let task_a = write_legacy_task(&alice, "task_a", "TODO");
+        legacy_tasks_by_account().insert(&alice, &vec![task_a]);
+
+        assert_eq!(contract.insert_task(String::from("task_b")), 1);
+        let names: Vec<String> = contract
+            .get_tasks_for(alice, None, None)
+            .into_iter()
+            .map(|t| t.task_name)
+            .collect();
+        assert_eq!(names, vec!["task_a", "task_b"]);
+    }
+
+    #[test]
+    fn reconcile_adopts_orphaned_tasks() {
+        let mut contract = setup();
+        let alice = accounts(0);
+        let bob = accounts(1);
+        let task_a = write_legacy_task(&alice, "task_a", "TODO");
+        legacy_tasks_by_account().insert(&alice, &vec![task_a.clone()]);
+        let orphan = write_legacy_task(&alice, "orphan", "DONE");
+        let other = write_legacy_task(&bob, "orphan", "DONE");
+
+        let candidates = vec![task_a, orphan.clone(), String::from("alice.missing"), other];
+        assert_eq!(
+            contract.find_orphaned_tasks(alice.clone(), candidates.clone()),
+            vec![orphan.clone()]
+        );
+        assert_eq!(
+            contract.reconcile_orphaned_tasks(
+                alice.clone(),
+                candidates.clone(),
+                OrphanResolution::Adopt
+            ),
+            vec![orphan]
+        );
+        assert!(contract
+            .find_orphaned_tasks(alice.clone(), candidates)
+            .is_empty());
+        assert_eq!(contract.migrate_legacy_tasks(alice).len(), 2);
+    }
+
+    #[test]
+    fn reconcile_removes_orphaned_tasks() {
+        let mut contract = setup();
+        let alice = accounts(0);
+        let orphan = write_legacy_task(&alice, "orphan", "DONE");
+
+        contract.reconcile_orphaned_tasks(
+            alice.clone(),
+            vec![orphan.clone()],
+            OrphanResolution::Remove,
+        );
+        assert!(!env::storage_has_key(&legacy_task_key(&orphan)));
+        assert!(contract.migrate_legacy_tasks(alice).is_empty());
+    }
+
+    #[test]
+    fn orphans_are_matched_to_their_exact_owner() {
+        let contract = setup();
+        let alice = accounts(0);
+        let alice_near: AccountId = "alice.near".parse().unwrap();
+        // `alice` naming a task `near.orphan` writes the id `alice.near.orphan`.
+        let orphan = write_legacy_task(&alice, "near.orphan", "DONE");
+        assert_eq!(orphan, "alice.near.orphan");
+
+        assert!(contract
+            .find_orphaned_tasks(alice_near, vec![orphan.clone()])
+            .is_empty());
+        assert_eq!(
+            contract.find_orphaned_tasks(alice, vec![orphan.clone()]),
+            vec![orphan]
+        );
+    }
+}
@@ -8,23 +8,220 @@
 
 use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
 use near_sdk::collections::LookupMap;
-use near_sdk::{env, log, near_bindgen, AccountId};
+use near_sdk::json_types::U64;
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::{env, near_bindgen, AccountId, Balance};
 
-type TaskId = String;
+mod assignment;
+mod badge;
+mod batch;
+mod bounty;
+mod deadlines;
+mod dependencies;
+mod details;
+mod error;
+mod events;
+mod greeting;
+mod history;
+mod index;
+mod legacy;
+mod lists;
+mod recurrence;
+mod stake;
+mod status;
+mod storage;
+mod subtasks;
+mod token_bounty;
+mod versioned;
 
-#[derive(Debug, BorshDeserialize, BorshSerialize, PartialEq)]
+pub use crate::assignment::{Assignment, AssignmentStatus};
+pub use crate::badge::Badge;
+pub use crate::batch::TaskUpdate;
+pub use crate::bounty::Bounty;
+pub use crate::deadlines::DueDateIndex;
+pub use crate::details::{NewTask, Priority, TaskPatch};
+pub use crate::error::ContractError;
+pub use crate::events::TaskEvent;
+pub use crate::history::{TaskChange, TaskHistoryEntry};
+pub use crate::index::TaskIndex;
+pub use crate::legacy::OrphanResolution;
+pub use crate::lists::{Collaborator, ListId, Role, TaskList};
+pub use crate::recurrence::Recurrence;
+pub use crate::stake::Stake;
+pub use crate::status::TaskStatus;
+pub use crate::storage::StorageAccount;
+pub use crate::subtasks::{ChecklistItem, TaskProgress};
+pub use crate::token_bounty::TokenBounty;
+pub use crate::versioned::{TaskStore, VersionedTask, STATE_VERSION};
+
+/// Opaque task id, allocated from a contract-wide counter and never reused.
+pub type TaskId = u64;
+
+const TASKS_BY_ACCOUNT_PREFIX: &[u8] = b"c";
+const TASK_SETS_PREFIX: &[u8] = b"C";
+const TASKS_PREFIX: &[u8] = b"T";
+const LEGACY_TASK_IDS_PREFIX: &[u8] = b"l";
+const ARCHIVED_BY_ACCOUNT_PREFIX: &[u8] = b"z";
+const ARCHIVED_SETS_PREFIX: &[u8] = b"Z";
+const ARCHIVED_TASKS_PREFIX: &[u8] = b"Y";
+// `Vec` based indexes used before the per-account sets
+const VEC_TASKS_BY_ACCOUNT_PREFIX: &[u8] = b"a";
+const VEC_ARCHIVED_BY_ACCOUNT_PREFIX: &[u8] = b"x";
+// Task records stored before they were versioned
+const UNVERSIONED_TASKS_PREFIX: &[u8] = b"i";
+const UNVERSIONED_ARCHIVED_TASKS_PREFIX: &[u8] = b"y";
+const STORAGE_ACCOUNTS_PREFIX: &[u8] = b"s";
+const GREETINGS_PREFIX: &[u8] = b"g";
+const TASK_HISTORY_PREFIX: &[u8] = b"h";
+const TASK_LISTS_PREFIX: &[u8] = b"L";
+const LISTS_BY_ACCOUNT_PREFIX: &[u8] = b"m";
+const LIST_SETS_PREFIX: &[u8] = b"M";
+const TASKS_BY_LIST_PREFIX: &[u8] = b"k";
+const LIST_TASK_SETS_PREFIX: &[u8] = b"K";
+const ARCHIVED_BY_LIST_PREFIX: &[u8] = b"q";
+const ARCHIVED_LIST_TASK_SETS_PREFIX: &[u8] = b"Q";
+const ASSIGNED_BY_ACCOUNT_PREFIX: &[u8] = b"n";
+const ASSIGNED_SETS_PREFIX: &[u8] = b"N";
+const BOUNTIES_PREFIX: &[u8] = b"b";
+const UNCLAIMED_BOUNTIES_PREFIX: &[u8] = b"u";
+const TOKEN_BOUNTIES_PREFIX: &[u8] = b"f";
+const UNCLAIMED_TOKEN_BOUNTIES_PREFIX: &[u8] = b"F";
+const BADGES_PREFIX: &[u8] = b"B";
+const BADGES_BY_ACCOUNT_PREFIX: &[u8] = b"o";
+const BADGE_SETS_PREFIX: &[u8] = b"O";
+const DUE_DATES_BY_ACCOUNT_PREFIX: &[u8] = b"d";
+const DUE_DATE_TREES_PREFIX: &[u8] = b"D";
+// Accounts added to the due date index after an upgrade
+const DUE_DATES_BACKFILLED_PREFIX: &[u8] = b"e";
+const STAKES_PREFIX: &[u8] = b"j";
+const STAKES_BY_ACCOUNT_PREFIX: &[u8] = b"p";
+const STAKE_SETS_PREFIX: &[u8] = b"P";
+const BOUNTY_TOKENS_PREFIX: &[u8] = b"r";
+
+/// A single task, as stored on-chain and as returned by view methods.
+///
+/// The JSON representation is part of the public API used by the frontend,
+/// so the field names below must stay stable:
+///
+/// ```json
+/// {
+///   "id": 42,
+///   "owner_id": "alice.testnet",
+///   "task_name": "task_a",
+///   "task_status": "TODO",
+///   "description": "Weekly planning notes",
+///   "priority": "HIGH",
+///   "due_date": "1700000000000000000",
+///   "tags": ["work", "planning"],
+///   "created_at": "1690000000000000000",
+///   "updated_at": "1690000000000000000",
+///   "updated_by": "alice.testnet",
+///   "completed_at": null,
+///   "track_history": false,
+///   "list_id": null,
+///   "assignee": { "account_id": "bob.testnet", "status": "PENDING" },
+///   "parent_id": null,
+///   "subtask_ids": [43, 44],
+///   "checklist": [{ "text": "Book a room", "done": true }],
+///   "blocker_ids": [40],
+///   "dependent_ids": [],
+///   "recurrence": { "EVERY_N_DAYS": 3 },
+///   "version": 5
+/// }
+/// ```
+#[derive(Debug, Clone, BorshDeserialize, BorshSerialize, Serialize, Deserialize, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
 pub struct Task {
-    id: TaskId,
-    task_name: String,
-    task_status: String,
+    /// Unique task id.
+    pub id: TaskId,
+    /// Account that created the task.
+    pub owner_id: AccountId,
+    /// Display name of the task, not required to be unique.
+    pub task_name: String,
+    /// Current status of the task, e.g. `"TODO"` or `"DONE"`.
+    pub task_status: TaskStatus,
+    /// Optional longer text, at most `MAX_DESCRIPTION_LENGTH` characters.
+    pub description: Option<String>,
+    pub priority: Priority,
+    /// Block timestamp in nanoseconds the task is due at, serialized as a string.
+    pub due_date: Option<U64>,
+    /// Lowercase tags, at most `MAX_TAGS` of them.
+    pub tags: Vec<String>,
+    /// Block timestamp the task was created at, in nanoseconds.
+    pub created_at: U64,
+    /// Block timestamp of the last change, in nanoseconds.
+    pub updated_at: U64,
+    /// Account that made the last change.
+    pub updated_by: AccountId,
+    /// Block timestamp the task was moved to `DONE`, `null` while it isn't done.
+    pub completed_at: Option<U64>,
+    /// Whether changes are kept in the task history, see `get_task_history`.
+    pub track_history: bool,
+    /// Shared list the task belongs to, `null` for a private task.
+    pub list_id: Option<ListId>,
+    /// Account the task is assigned to, see `assign_task`.
+    pub assignee: Option<Assignment>,
+    /// Task this one is a subtask of, `null` for a top-level task.
+    pub parent_id: Option<TaskId>,
+    /// Subtasks in the order they were created, see `get_subtasks`.
+    pub subtask_ids: Vec<TaskId>,
+    /// Lightweight items to check off, see `add_checklist_item`.
+    pub checklist: Vec<ChecklistItem>,
+    /// Tasks that have to be done before this one can be started, see `add_blocker`.
+    pub blocker_ids: Vec<TaskId>,
+    /// Tasks blocked by this one, kept in sync with their `blocker_ids`.
+    pub dependent_ids: Vec<TaskId>,
+    /// Rule the next occurrence is created by once the task is done, see `Recurrence`.
+    pub recurrence: Option<Recurrence>,
+    /// Number of changes made to the task, bumped by every method that changes it. Only some of
+    /// them check it, see `TaskPatch::expected_version`.
+    pub version: u64,
 }
 
 // Define the contract structure
 #[near_bindgen]
-#[derive(Debug, BorshDeserialize, BorshSerialize)]
+#[derive(BorshDeserialize, BorshSerialize)]
 pub struct Contract {
-    tasks_by_account: LookupMap<AccountId, Vec<TaskId>>,
-    tasks: LookupMap<TaskId, Task>,
+    tasks_by_account: TaskIndex,
+    tasks: TaskStore,
+    next_task_id: TaskId,
+    // Maps `<owner>.<task_name>` ids of the previous layout to their migrated ids
+    legacy_task_ids: LookupMap<String, TaskId>,
+    archived_by_account: TaskIndex,
+    archived_tasks: TaskStore,
+    storage_accounts: LookupMap<AccountId, StorageAccount>,
+    greetings: LookupMap<AccountId, String>,
+    task_history: LookupMap<TaskId, Vec<TaskHistoryEntry>>,
+    task_lists: LookupMap<ListId, TaskList>,
+    next_list_id: ListId,
+    // Lists owned by or shared with an account
+    lists_by_account: TaskIndex,
+    tasks_by_list: TaskIndex<ListId>,
+    archived_by_list: TaskIndex<ListId>,
+    // Active tasks assigned to an account
+    assigned_by_account: TaskIndex,
+    // NEAR escrowed on tasks, see `bounty`
+    bounties: LookupMap<TaskId, Bounty>,
+    // Bounties whose transfer failed, by receiver
+    unclaimed_bounties: LookupMap<AccountId, Balance>,
+    // NEP-141 tokens escrowed on tasks, see `token_bounty`
+    token_bounties: LookupMap<TaskId, Vec<TokenBounty>>,
+    // Token bounties whose transfer failed, by receiver and token
+    unclaimed_token_bounties: LookupMap<(AccountId, AccountId), Balance>,
+    // Completion badges by task id, see `badge`
+    badges: LookupMap<TaskId, Badge>,
+    badges_by_account: TaskIndex,
+    // Open active tasks with a due date, see `deadlines`
+    tasks_by_due_date: DueDateIndex,
+    // NEAR staked on finishing tasks in time, see `stake`
+    stakes: LookupMap<TaskId, Stake>,
+    stakes_by_account: TaskIndex,
+    // Account missed stakes are transferred to
+    stake_beneficiary: Option<AccountId>,
+    // NEP-141 tokens an account accepts as bounties on its tasks, see `token_bounty`
+    bounty_tokens: LookupMap<AccountId, Vec<AccountId>>,
+    // Layout version of this struct, see `versioned::VersionedState`
+    state_version: u32,
 }
 
 // Define the default, which automatically initializes the contract
@@ -32,8 +229,42 @@ pub struct Contract {
 impl Default for Contract {
     fn default() -> Self {
         Self {
-            tasks_by_account: LookupMap::new(b"ta".to_vec()),
-            tasks: LookupMap::new(b"t"),
+            tasks_by_account: TaskIndex::new(TASKS_BY_ACCOUNT_PREFIX, TASK_SETS_PREFIX)
+                .with_legacy(VEC_TASKS_BY_ACCOUNT_PREFIX),
+            tasks: TaskStore::new(TASKS_PREFIX).with_unversioned(UNVERSIONED_TASKS_PREFIX),
+            next_task_id: 0,
+            legacy_task_ids: LookupMap::new(LEGACY_TASK_IDS_PREFIX),
+            archived_by_account: TaskIndex::new(ARCHIVED_BY_ACCOUNT_PREFIX, ARCHIVED_SETS_PREFIX)
+                .with_legacy(VEC_ARCHIVED_BY_ACCOUNT_PREFIX),
+            archived_tasks: TaskStore::new(ARCHIVED_TASKS_PREFIX)
+                .with_unversioned(UNVERSIONED_ARCHIVED_TASKS_PREFIX),
+            storage_accounts: LookupMap::new(STORAGE_ACCOUNTS_PREFIX),
+            greetings: LookupMap::new(GREETINGS_PREFIX),
+            task_history: LookupMap::new(TASK_HISTORY_PREFIX),
+            task_lists: LookupMap::new(TASK_LISTS_PREFIX),
+            next_list_id: 0,
+            lists_by_account: TaskIndex::new(LISTS_BY_ACCOUNT_PREFIX, LIST_SETS_PREFIX),
+            tasks_by_list: TaskIndex::new(TASKS_BY_LIST_PREFIX, LIST_TASK_SETS_PREFIX),
+            archived_by_list: TaskIndex::new(
+                ARCHIVED_BY_LIST_PREFIX,
+                ARCHIVED_LIST_TASK_SETS_PREFIX,
+            ),
+            assigned_by_account: TaskIndex::new(ASSIGNED_BY_ACCOUNT_PREFIX, ASSIGNED_SETS_PREFIX),
+            bounties: LookupMap::new(BOUNTIES_PREFIX),
+            unclaimed_bounties: LookupMap::new(UNCLAIMED_BOUNTIES_PREFIX),
+            token_bounties: LookupMap::new(TOKEN_BOUNTIES_PREFIX),
+            unclaimed_token_bounties: LookupMap::new(UNCLAIMED_TOKEN_BOUNTIES_PREFIX),
+            badges: LookupMap::new(BADGES_PREFIX),
+            badges_by_account: TaskIndex::new(BADGES_BY_ACCOUNT_PREFIX, BADGE_SETS_PREFIX),
+            tasks_by_due_date: DueDateIndex::new(
+                DUE_DATES_BY_ACCOUNT_PREFIX,
+                DUE_DATE_TREES_PREFIX,
+            ),
+            stakes: LookupMap::new(STAKES_PREFIX),
+            stakes_by_account: TaskIndex::new(STAKES_BY_ACCOUNT_PREFIX, STAKE_SETS_PREFIX),
+            stake_beneficiary: None,
+            bounty_tokens: LookupMap::new(BOUNTY_TOKENS_PREFIX),
+            state_version: STATE_VERSION,
         }
     }
 }
@@ -41,52 +272,432 @@ impl Default for Contract {
 // Implement the contract structure
 #[near_bindgen]
 impl Contract {
-    // Public method - returns the current tasks list
+    // Public method - returns the first page of the caller's tasks list
+    // Note: this reads the predecessor account and therefore only works in change calls,
+    // view calls should use `get_tasks_for` instead.
     pub fn get_tasks(&self) -> Vec<Task> {
-        let owner = env::predecessor_account_id();
-        match self.tasks_by_account.get(&owner) {
-            Some(tasks) => {
-                return tasks
-                    .clone()
-                    .into_iter()
-                    .map(|t| self.tasks.get(&(t as TaskId)).unwrap())
-                    .collect::<Vec<Task>>()
-            }
-            None => return Vec::new(),
-        };
+        self.get_tasks_for(env::predecessor_account_id(), None, None)
     }
 
-    // Public method - insert new task to tasks list
-    pub fn insert_task(&mut self, task_name: String) {
-        log!("Insert new task {}", task_name);
-        let owner = env::predecessor_account_id();
-        let task_id = format!("{}.{}", owner, task_name);
-        let task_obj = Task {
-            id: task_id.clone(),
-            task_name,
-            task_status: "TODO".to_owned(),
-        };
-        let task_id_converted = (task_id as TaskId).clone();
-        self.tasks.insert(&task_id_converted, &task_obj);
-        let mut new_task_lists = match self.tasks_by_account.get(&owner) {
-            Some(tasks) => tasks.clone(),
-            _ => Vec::new(),
-        };
-        new_task_lists.push(task_id_converted);
-        self.tasks_by_account.insert(&owner, &new_task_lists);
+    // Public method - returns a page of the tasks list of the given account
+    // `limit` defaults to 50 and is capped at 100 tasks per call.
+    pub fn get_tasks_for(
+        &self,
+        account_id: AccountId,
+        from_index: Option<u64>,
+        limit: Option<u64>,
+    ) -> Vec<Task> {
+        self.tasks_by_account
+            .page(&account_id, from_index, limit)
+            .into_iter()
+            .map(|t| self.tasks.get(&t).unwrap())
+            .collect()
+    }
+
+    // Public method - returns the number of tasks owned by the given account
+    pub fn get_task_count_for(&self, account_id: AccountId) -> u64 {
+        self.tasks_by_account.len(&account_id)
+    }
+
+    // Public method - returns a single task by its id
+    pub fn get_task(&self, task_id: TaskId) -> Option<Task> {
+        self.tasks.get(&task_id)
+    }
+
+    // Public method - returns a page of the archived tasks of the given account
+    pub fn get_archived_tasks_for(
+        &self,
+        account_id: AccountId,
+        from_index: Option<u64>,
+        limit: Option<u64>,
+    ) -> Vec<Task> {
+        self.archived_by_account
+            .page(&account_id, from_index, limit)
+            .into_iter()
+            .map(|t| self.archived_tasks.get(&t).unwrap())
+            .collect()
+    }
+
+    // Public method - insert new task to tasks list and return its id
+    // Several tasks may share the same name, each of them gets its own id.
+    // The storage is charged against the caller's storage balance, an attached deposit
+    // is added to that balance first (registering the caller if needed).
+    #[payable]
+    pub fn insert_task(&mut self, task_name: String) -> TaskId {
+        self.create_task(NewTask::new(task_name))
+            .unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    // Public method - insert new task with its details and return its id
+    // Tasks created in a shared list require the editor role in it. A subtask is created in
+    // the list of its parent.
+    // Storage is handled like in `insert_task`.
+    #[payable]
+    #[handle_result]
+    pub fn create_task(&mut self, task: NewTask) -> Result<TaskId, ContractError> {
+        self.migrate_account(&env::predecessor_account_id());
+        let initial_storage_usage = env::storage_usage();
+        let task_id = self.add_task(task)?;
+        self.settle_storage(initial_storage_usage);
+        Ok(task_id)
     }
 
     // Public method - update task status in tasks list
-    pub fn update_task(&mut self, task_name: String, task_status: String) {
-        log!("Update task {} to {}", task_name, task_status);
-        let owner = env::predecessor_account_id();
-        let task_id = format!("{}.{}", owner, task_name);
-        let task_obj = Task {
-            id: task_id.clone(),
-            task_name: task_name,
-            task_status: task_status,
+    // Fails if the task doesn't exist, the caller may not change it or the status change is
+    // not allowed. Tasks can be changed by their owner and by editors of their list, the status
+    // also by an assignee that accepted the task. With `expected_version`, the call fails
+    // instead of overwriting a change made since the caller read the task's `version`.
+    #[payable]
+    #[handle_result]
+    pub fn update_task(
+        &mut self,
+        task_id: TaskId,
+        task_status: TaskStatus,
+        expected_version: Option<u64>,
+    ) -> Result<(), ContractError> {
+        self.patch_task(
+            task_id,
+            TaskPatch {
+                task_status: Some(task_status),
+                expected_version,
+                ..Default::default()
+            },
+        )
+    }
+
+    // Public method - change the display name of a task
+    // A longer name is charged against the owner's storage balance, a shorter one is credited.
+    // An attached deposit is added to the owner's balance first. `expected_version` works like
+    // in `update_task`.
+    #[payable]
+    #[handle_result]
+    pub fn rename_task(
+        &mut self,
+        task_id: TaskId,
+        task_name: String,
+        expected_version: Option<u64>,
+    ) -> Result<(), ContractError> {
+        self.patch_task(
+            task_id,
+            TaskPatch {
+                task_name: Some(task_name),
+                expected_version,
+                ..Default::default()
+            },
+        )
+    }
+
+    // Public method - change only the given fields of a task
+    // Every field is validated before anything is changed, the status has to follow the
+    // task state machine and a blocked task can't be started or finished. Storage is settled
+    // like in `rename_task`.
+    #[payable]
+    #[handle_result]
+    pub fn patch_task(&mut self, task_id: TaskId, patch: TaskPatch) -> Result<(), ContractError> {
+        self.change_task(task_id, patch, false)
+    }
+
+    // Public method - permanently remove an active or archived task
+    // Tasks can be deleted by their owner and by admins of their list, once their subtasks
+    // are deleted. The storage released
+    // by the task is credited to its owner's storage balance, from where it can be taken out
+    // with `storage_withdraw`.
+    #[handle_result]
+    pub fn delete_task(&mut self, task_id: TaskId) -> Result<(), ContractError> {
+        self.migrate_account(&env::predecessor_account_id());
+        let initial_storage_usage = env::storage_usage();
+        let task = self.remove_task(task_id)?;
+        self.settle_storage_of(&task.owner_id, initial_storage_usage);
+        Ok(())
+    }
+
+    // Public method - move a task out of the active tasks list into the archive
+    // Not possible while the assignee's bounty is locked, see `bounty`.
+    #[payable]
+    #[handle_result]
+    pub fn archive_task(&mut self, task_id: TaskId) -> Result<(), ContractError> {
+        self.migrate_account(&env::predecessor_account_id());
+        let initial_storage_usage = env::storage_usage();
+        let mut task = self.editable_task(task_id)?;
+        if self.is_bounty_locked(&task) {
+            return Err(ContractError::BountyLocked(task_id));
+        }
+        task.touch();
+        self.tasks.remove(&task_id);
+        self.unindex_task(&task);
+        self.archived_tasks.insert(&task_id, &task);
+        self.index_archived_task(&task);
+        self.record_history(&task, vec![TaskChange::Archived]);
+        TaskEvent::TaskArchived(vec![(&task).into()]).emit();
+        self.settle_storage_of(&task.owner_id, initial_storage_usage);
+        Ok(())
+    }
+
+    // Public method - move an archived task back to the end of the active tasks list
+    #[payable]
+    #[handle_result]
+    pub fn restore_task(&mut self, task_id: TaskId) -> Result<(), ContractError> {
+        self.migrate_account(&env::predecessor_account_id());
+        let initial_storage_usage = env::storage_usage();
+        let mut task = self.editable_archived_task(task_id)?;
+        task.touch();
+        self.archived_tasks.remove(&task_id);
+        self.unindex_archived_task(&task);
+        self.tasks.insert(&task_id, &task);
+        self.index_task(&task);
+        self.record_history(&task, vec![TaskChange::Restored]);
+        TaskEvent::TaskRestored(vec![(&task).into()]).emit();
+        self.settle_storage_of(&task.owner_id, initial_storage_usage);
+        Ok(())
+    }
+}
+
+impl Contract {
+    /// Applies a patch like `patch_task`, moving a task with open subtasks or checklist items
+    /// to `DONE` only if `force` is set. Open blockers can't be forced.
+    pub(crate) fn change_task(
+        &mut self,
+        task_id: TaskId,
+        patch: TaskPatch,
+        force: bool,
+    ) -> Result<(), ContractError> {
+        let initial_storage_usage = env::storage_usage();
+        let owner_id = self.apply_task_patch(task_id, patch, force)?;
+        self.settle_storage_of(&owner_id, initial_storage_usage);
+        Ok(())
+    }
+
+    /// Changes a task like `change_task` without settling its storage, returning the owner
+    /// that pays for it.
+    fn apply_task_patch(
+        &mut self,
+        task_id: TaskId,
+        patch: TaskPatch,
+        force: bool,
+    ) -> Result<AccountId, ContractError> {
+        let mut task = self
+            .tasks
+            .get(&task_id)
+            .ok_or(ContractError::TaskNotFound(task_id))?;
+        let by_assignee = task.is_accepted_by(&env::predecessor_account_id());
+        if !(patch.is_status_only() && by_assignee) {
+            self.ensure_access(&task, Role::Editor)?;
+        }
+        // Only the assignee can take a task with a locked bounty out of their hands.
+        if patch.task_status.is_some_and(|task_status| {
+            !matches!(task_status, TaskStatus::InProgress | TaskStatus::Done)
+        }) && !by_assignee
+            && self.is_bounty_locked(&task)
+        {
+            return Err(ContractError::BountyLocked(task_id));
+        }
+        if matches!(
+            patch.task_status,
+            Some(TaskStatus::InProgress | TaskStatus::Done)
+        ) {
+            self.ensure_unblocked(&task)?;
+        }
+        if patch.task_status == Some(TaskStatus::Done) && !force {
+            self.ensure_subtasks_done(&task)?;
+        }
+        // The due date index is keyed by the due date and only holds open tasks.
+        let old_task =
+            (patch.due_date.is_some() || patch.task_status.is_some()).then(|| task.clone());
+        let mut changes = task.apply_patch(patch)?;
+        let status_changed = !changes.status_changed.is_empty();
+        let next_occurrence = if status_changed && task.task_status == TaskStatus::Done {
+            self.spawn_next_occurrence(&mut task)
+        } else {
+            None
         };
+        self.tasks.insert(&task_id, &task);
+        if let Some(old_task) = old_task {
+            self.tasks_by_due_date.remove(&old_task);
+            self.tasks_by_due_date.insert(&task);
+        }
+        if task.track_history {
+            self.record_history(&task, std::mem::take(&mut changes.history));
+        } else {
+            self.remove_history(task_id);
+        }
+        changes.emit();
+        if let Some(next_occurrence) = next_occurrence {
+            TaskEvent::TaskCreated(vec![(&next_occurrence).into()]).emit();
+        }
+        if status_changed {
+            self.settle_bounty(&task);
+            self.settle_stake(&task);
+        }
+        Ok(task.owner_id)
+    }
+
+    /// Validates and stores a new task of the predecessor, without settling its storage.
+    fn add_task(&mut self, task: NewTask) -> Result<TaskId, ContractError> {
+        let parent = task
+            .parent_id
+            .map(|parent_id| self.subtask_parent(parent_id))
+            .transpose()?;
+        let task = match &parent {
+            Some(parent) => NewTask {
+                list_id: parent.list_id,
+                ..task
+            },
+            None => task,
+        };
+        if let Some(list_id) = task.list_id {
+            let list = self
+                .get_list(list_id)
+                .ok_or(ContractError::ListNotFound(list_id))?;
+            Self::ensure_role(&list, Role::Editor)?;
+        }
+        let owner = env::predecessor_account_id();
+        let task_id = self.next_task_id;
+        let task_obj = task.into_task(task_id, owner)?;
+        self.next_task_id += 1;
         self.tasks.insert(&task_id, &task_obj);
+        self.index_task(&task_obj);
+        if let Some(mut parent) = parent {
+            parent.subtask_ids.push(task_id);
+            parent.touch();
+            self.tasks.insert(&parent.id, &parent);
+        }
+        self.record_history(&task_obj, vec![TaskChange::Created]);
+        TaskEvent::TaskCreated(vec![(&task_obj).into()]).emit();
+        Ok(task_id)
+    }
+
+    /// Deletes an active or archived task like `delete_task` and returns it, without settling
+    /// its storage.
+    fn remove_task(&mut self, task_id: TaskId) -> Result<Task, ContractError> {
+        let archived = !self.tasks.contains_key(&task_id);
+        let task = if archived {
+            self.archived_tasks.get(&task_id)
+        } else {
+            self.tasks.get(&task_id)
+        }
+        .ok_or(ContractError::TaskNotFound(task_id))?;
+        self.ensure_access(&task, Role::Admin)?;
+        if !task.subtask_ids.is_empty() {
+            return Err(ContractError::HasSubtasks(task_id));
+        }
+        if self.is_bounty_locked(&task) {
+            return Err(ContractError::BountyLocked(task_id));
+        }
+        if archived {
+            self.archived_tasks.remove(&task_id);
+            self.unindex_archived_task(&task);
+        } else {
+            self.tasks.remove(&task_id);
+            self.unindex_task(&task);
+        }
+        self.detach_subtask(&task);
+        self.detach_dependencies(&task);
+        self.remove_history(task_id);
+        self.refund_bounty(&task);
+        TaskEvent::TaskDeleted(vec![(&task).into()]).emit();
+        Ok(task)
+    }
+
+    /// Loads an active or archived task.
+    fn any_task(&self, task_id: TaskId) -> Option<Task> {
+        self.tasks
+            .get(&task_id)
+            .or_else(|| self.archived_tasks.get(&task_id))
+    }
+
+    /// Changes an active or archived task in place, if it still exists.
+    fn update_any_task(&mut self, task_id: TaskId, update: impl FnOnce(&mut Task)) {
+        let store = if self.tasks.contains_key(&task_id) {
+            &mut self.tasks
+        } else {
+            &mut self.archived_tasks
+        };
+        if let Some(mut task) = store.get(&task_id) {
+            update(&mut task);
+            store.insert(&task_id, &task);
+        }
+    }
+
+    /// Loads an active task that the predecessor is allowed to modify.
+    fn editable_task(&self, task_id: TaskId) -> Result<Task, ContractError> {
+        let task = self
+            .tasks
+            .get(&task_id)
+            .ok_or(ContractError::TaskNotFound(task_id))?;
+        self.ensure_access(&task, Role::Editor)?;
+        Ok(task)
+    }
+
+    /// Loads an archived task that the predecessor is allowed to modify.
+    fn editable_archived_task(&self, task_id: TaskId) -> Result<Task, ContractError> {
+        let task = self
+            .archived_tasks
+            .get(&task_id)
+            .ok_or(ContractError::TaskNotFound(task_id))?;
+        self.ensure_access(&task, Role::Editor)?;
+        Ok(task)
+    }
+
+    fn index_task(&mut self, task: &Task) {
+        self.tasks_by_account.insert(&task.owner_id, task.id);
+        if let Some(list_id) = task.list_id {
+            self.tasks_by_list.insert(&list_id, task.id);
+        }
+        if let Some(assignee) = &task.assignee {
+            self.assigned_by_account
+                .insert(&assignee.account_id, task.id);
+        }
+        self.tasks_by_due_date.insert(task);
+    }
+
+    fn unindex_task(&mut self, task: &Task) {
+        self.tasks_by_account.remove(&task.owner_id, task.id);
+        if let Some(list_id) = task.list_id {
+            self.tasks_by_list.remove(&list_id, task.id);
+        }
+        if let Some(assignee) = &task.assignee {
+            self.assigned_by_account
+                .remove(&assignee.account_id, task.id);
+        }
+        self.tasks_by_due_date.remove(task);
+    }
+
+    fn index_archived_task(&mut self, task: &Task) {
+        self.archived_by_account.insert(&task.owner_id, task.id);
+        if let Some(list_id) = task.list_id {
+            self.archived_by_list.insert(&list_id, task.id);
+        }
+    }
+
+    fn unindex_archived_task(&mut self, task: &Task) {
+        self.archived_by_account.remove(&task.owner_id, task.id);
+        if let Some(list_id) = task.list_id {
+            self.archived_by_list.remove(&list_id, task.id);
+        }
+    }
+
+    /// Moves the account's data written by earlier contract versions to the current layout.
+    /// Called before measuring storage, so the caller isn't charged for the migration.
+    fn migrate_account(&mut self, account_id: &AccountId) {
+        self.migrate_legacy_tasks_of(account_id);
+        self.tasks_by_account.migrate_account(account_id);
+        self.archived_by_account.migrate_account(account_id);
+        self.backfill_due_dates(account_id);
+    }
+
+    /// Checks that the predecessor owns the task, or has the `required` role in its list.
+    fn ensure_access(&self, task: &Task, required: Role) -> Result<(), ContractError> {
+        let account_id = env::predecessor_account_id();
+        if task.owner_id == account_id {
+            return Ok(());
+        }
+        match task.list_id.and_then(|list_id| self.get_list(list_id)) {
+            Some(list) => Self::ensure_role(&list, required),
+            None => Err(ContractError::NotTaskOwner {
+                task_id: task.id,
+                account_id,
+            }),
+        }
     }
 }
 
@@ -97,8 +708,14 @@ impl Contract {
 #[cfg(all(test, not(target_arch = "wasm32")))]
 mod tests {
     use super::*;
+    use crate::events::EVENT_STANDARD_VERSION;
+    use near_contract_standards::storage_management::StorageManagement;
+    use near_sdk::serde_json::{self, json};
     use near_sdk::test_utils::{accounts, VMContextBuilder};
-    use near_sdk::testing_env;
+    use near_sdk::{testing_env, Balance, ONE_NEAR};
+
+    // Enough to cover the storage of a few tasks
+    const STORAGE_DEPOSIT: Balance = ONE_NEAR / 100;
 
     fn get_context(is_view: bool) -> VMContextBuilder {
         let mut builder = VMContextBuilder::new();
@@ -106,30 +723,61 @@ mod tests {
             .current_account_id(accounts(0))
             .signer_account_id(accounts(0))
             .predecessor_account_id(accounts(0))
+            .account_balance(10 * ONE_NEAR)
             .is_view(is_view);
+        if !is_view {
+            builder.attached_deposit(STORAGE_DEPOSIT);
+        }
 
         builder
     }
 
+    // Parses the NEP-297 events logged by the last call
+    fn get_events() -> Vec<serde_json::Value> {
+        near_sdk::test_utils::get_logs()
+            .iter()
+            .filter_map(|log| log.strip_prefix("EVENT_JSON:"))
+            .map(|event| serde_json::from_str(event).unwrap())
+            .collect()
+    }
+
     #[test]
     fn insert_then_get_task() {
         let mut context = get_context(false);
         let alice: AccountId = accounts(0);
 
         context
-            .account_balance(1000)
+            .account_balance(10 * ONE_NEAR)
             .predecessor_account_id(alice.clone())
-            .attached_deposit(1000)
+            .attached_deposit(STORAGE_DEPOSIT)
             .signer_account_id(alice.clone());
 
         testing_env!(context.build());
         let mut contract = Contract::default();
-        let mut output_tasks = Vec::new();
-        output_tasks.push(Task {
-            id: format!("{}.{}", alice, "task_a"),
+        let output_tasks = vec![Task {
+            id: 0,
+            owner_id: alice.clone(),
             task_name: String::from("task_a"),
-            task_status: String::from("TODO"),
-        });
+            task_status: TaskStatus::Todo,
+            description: None,
+            priority: Priority::Normal,
+            due_date: None,
+            tags: Vec::new(),
+            created_at: U64(0),
+            updated_at: U64(0),
+            updated_by: alice.clone(),
+            completed_at: None,
+            track_history: false,
+            list_id: None,
+            assignee: None,
+            parent_id: None,
+            subtask_ids: Vec::new(),
+            checklist: Vec::new(),
+            blocker_ids: Vec::new(),
+            dependent_ids: Vec::new(),
+            recurrence: None,
+            version: 0,
+        }];
         contract.insert_task(String::from("task_a"));
         assert_eq!(contract.get_tasks(), output_tasks);
     }
@@ -140,21 +788,674 @@ mod tests {
         let john: AccountId = accounts(1);
 
         context
-            .account_balance(1000)
+            .account_balance(10 * ONE_NEAR)
             .predecessor_account_id(john.clone())
-            .attached_deposit(1000)
+            .attached_deposit(STORAGE_DEPOSIT)
             .signer_account_id(john.clone());
 
         testing_env!(context.build());
         let mut contract = Contract::default();
-        let mut output_tasks = Vec::new();
-        output_tasks.push(Task {
-            id: format!("{}.{}", john, "task_a"),
+        let output_task = Task {
+            id: 0,
+            owner_id: john.clone(),
             task_name: String::from("task_a"),
-            task_status: String::from("DONE"),
-        });
+            task_status: TaskStatus::Done,
+            description: None,
+            priority: Priority::Normal,
+            due_date: None,
+            tags: Vec::new(),
+            created_at: U64(0),
+            updated_at: U64(0),
+            updated_by: john.clone(),
+            completed_at: Some(U64(0)),
+            track_history: false,
+            list_id: None,
+            assignee: None,
+            parent_id: None,
+            subtask_ids: Vec::new(),
+            checklist: Vec::new(),
+            blocker_ids: Vec::new(),
+            dependent_ids: Vec::new(),
+            recurrence: None,
+            version: 1,
+        };
+        let task_id = contract.insert_task(String::from("task_a"));
+        contract
+            .update_task(task_id, TaskStatus::Done, None)
+            .unwrap();
+        assert_eq!(contract.get_tasks()[0], output_task);
+    }
+
+    #[test]
+    fn task_json_uses_stable_field_names() {
+        let task = Task {
+            id: 7,
+            owner_id: accounts(0),
+            task_name: String::from("task_a"),
+            task_status: TaskStatus::Todo,
+            description: Some(String::from("notes")),
+            priority: Priority::High,
+            due_date: Some(U64(1_700_000_000_000_000_000)),
+            tags: vec![String::from("work")],
+            created_at: U64(1_600_000_000_000_000_000),
+            updated_at: U64(1_650_000_000_000_000_000),
+            updated_by: accounts(1),
+            completed_at: None,
+            track_history: true,
+            list_id: Some(3),
+            assignee: Some(Assignment {
+                account_id: accounts(2),
+                status: AssignmentStatus::Accepted,
+            }),
+            parent_id: Some(6),
+            subtask_ids: vec![8],
+            checklist: vec![ChecklistItem {
+                text: String::from("step"),
+                done: false,
+            }],
+            blocker_ids: vec![5],
+            dependent_ids: vec![9],
+            recurrence: Some(Recurrence::Weekly),
+            version: 4,
+        };
+        assert_eq!(
+            serde_json::to_value(&task).unwrap(),
+            json!({
+                "id": 7,
+                "owner_id": "alice",
+                "task_name": "task_a",
+                "task_status": "TODO",
+                "description": "notes",
+                "priority": "HIGH",
+                "due_date": "1700000000000000000",
+                "tags": ["work"],
+                "created_at": "1600000000000000000",
+                "updated_at": "1650000000000000000",
+                "updated_by": "bob",
+                "completed_at": null,
+                "track_history": true,
+                "list_id": 3,
+                "assignee": {"account_id": "charlie", "status": "ACCEPTED"},
+                "parent_id": 6,
+                "subtask_ids": [8],
+                "checklist": [{"text": "step", "done": false}],
+                "blocker_ids": [5],
+                "dependent_ids": [9],
+                "recurrence": "WEEKLY",
+                "version": 4,
+            })
+        );
+    }
+
+    #[test]
+    fn get_tasks_round_trips_through_json() {
+        let mut context = get_context(false);
+        let alice: AccountId = accounts(0);
+        context.predecessor_account_id(alice.clone());
+        testing_env!(context.build());
+
+        let mut contract = Contract::default();
         contract.insert_task(String::from("task_a"));
-        contract.update_task(String::from("task_a"), String::from("DONE"));
-        assert_eq!(contract.get_tasks()[0], output_tasks[0]);
+        contract.insert_task(String::from("task_b"));
+
+        let tasks = contract.get_tasks();
+        let encoded = serde_json::to_string(&tasks).unwrap();
+        let decoded: Vec<Task> = serde_json::from_str(&encoded).unwrap();
+        assert_eq!(decoded, tasks);
+        assert_eq!(decoded[1].id, 1);
+        assert_eq!(decoded[1].owner_id, alice);
+    }
+
+    #[test]
+    fn get_tasks_for_works_in_view_calls() {
+        let mut context = get_context(false);
+        let alice: AccountId = accounts(0);
+        let bob: AccountId = accounts(1);
+        context.predecessor_account_id(alice.clone());
+        testing_env!(context.build());
+
+        let mut contract = Contract::default();
+        contract.insert_task(String::from("task_a"));
+        contract.insert_task(String::from("task_b"));
+
+        testing_env!(get_context(true).build());
+        let tasks = contract.get_tasks_for(alice.clone(), None, None);
+        assert_eq!(tasks.len(), 2);
+        assert_eq!(tasks[0].task_name, "task_a");
+        assert_eq!(contract.get_task_count_for(alice.clone()), 2);
+        assert!(contract.get_tasks_for(bob.clone(), None, None).is_empty());
+        assert_eq!(contract.get_task_count_for(bob), 0);
+
+        assert_eq!(contract.get_task(1), Some(tasks[1].clone()));
+        assert_eq!(contract.get_task(2), None);
+    }
+
+    #[test]
+    fn update_rejects_illegal_transition() {
+        let mut context = get_context(false);
+        context.predecessor_account_id(accounts(1));
+        testing_env!(context.build());
+
+        let mut contract = Contract::default();
+        let task_id = contract.insert_task(String::from("task_a"));
+        contract
+            .update_task(task_id, TaskStatus::Done, None)
+            .unwrap();
+        let err = contract
+            .update_task(task_id, TaskStatus::Blocked, None)
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Illegal status transition for task 0: DONE -> BLOCKED"
+        );
+        assert_eq!(contract.get_tasks()[0].task_status, TaskStatus::Done);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn update_rejects_unknown_task() {
+        let mut context = get_context(false);
+        context.predecessor_account_id(accounts(1));
+        testing_env!(context.build());
+
+        let mut contract = Contract::default();
+        assert_eq!(
+            contract.update_task(0, TaskStatus::Done, None),
+            Err(ContractError::TaskNotFound(0))
+        );
+        assert!(contract.get_task(0).is_none());
+        assert!(contract.get_tasks().is_empty());
+    }
+
+    #[test]
+    fn update_rejects_other_owners() {
+        let mut context = get_context(false);
+        testing_env!(context.build());
+        let mut contract = Contract::default();
+        let task_id = contract.insert_task(String::from("task_a"));
+
+        context.predecessor_account_id(accounts(1));
+        testing_env!(context.build());
+        assert_eq!(
+            contract.update_task(task_id, TaskStatus::Done, None),
+            Err(ContractError::NotTaskOwner {
+                task_id,
+                account_id: accounts(1),
+            })
+        );
+        assert!(contract
+            .rename_task(task_id, String::from("mine"), None)
+            .is_err());
+    }
+
+    #[test]
+    fn duplicate_names_get_distinct_ids() {
+        testing_env!(get_context(false).build());
+        let mut contract = Contract::default();
+        let first = contract.insert_task(String::from("task_a"));
+        let second = contract.insert_task(String::from("task_a"));
+        assert_ne!(first, second);
+
+        contract
+            .update_task(second, TaskStatus::Done, None)
+            .unwrap();
+        let tasks = contract.get_tasks();
+        assert_eq!(tasks.len(), 2);
+        assert_eq!(tasks[0].task_status, TaskStatus::Todo);
+        assert_eq!(tasks[1].task_status, TaskStatus::Done);
+    }
+
+    #[test]
+    fn rename_keeps_task_id() {
+        testing_env!(get_context(false).build());
+        let mut contract = Contract::default();
+        let task_id = contract.insert_task(String::from("task_a"));
+        contract
+            .rename_task(task_id, String::from("task_b"), None)
+            .unwrap();
+        let task = contract.get_task(task_id).unwrap();
+        assert_eq!(task.task_name, "task_b");
+        assert_eq!(contract.get_task_count_for(accounts(0)), 1);
+    }
+
+    #[test]
+    #[should_panic(expected = "is not registered")]
+    fn insert_requires_storage_deposit() {
+        let mut context = get_context(false);
+        context.attached_deposit(0);
+        testing_env!(context.build());
+        Contract::default().insert_task(String::from("task_a"));
+    }
+
+    #[test]
+    fn delete_removes_task_and_refunds_storage() {
+        let mut context = get_context(false);
+        testing_env!(context.build());
+        let alice = accounts(0);
+        let mut contract = Contract::default();
+        let task_id = contract.insert_task(String::from("task_a"));
+        let balance_before = contract.storage_balance_of(alice.clone()).unwrap();
+
+        context.attached_deposit(0);
+        testing_env!(context.build());
+        let storage_before_delete = env::storage_usage();
+        contract.delete_task(task_id).unwrap();
+        let released = Balance::from(storage_before_delete - env::storage_usage());
+        let balance_after = contract.storage_balance_of(alice).unwrap();
+        assert!(released > 0);
+        assert_eq!(balance_after.total.0, balance_before.total.0);
+        assert_eq!(
+            balance_after.available.0 - balance_before.available.0,
+            released * env::storage_byte_cost()
+        );
+        assert!(contract.get_task(task_id).is_none());
+        assert!(contract.get_tasks().is_empty());
+        assert_eq!(
+            contract.delete_task(task_id),
+            Err(ContractError::TaskNotFound(task_id))
+        );
+    }
+
+    #[test]
+    fn archive_and_restore_task() {
+        testing_env!(get_context(false).build());
+        let alice = accounts(0);
+        let mut contract = Contract::default();
+        let task_a = contract.insert_task(String::from("task_a"));
+        let task_b = contract.insert_task(String::from("task_b"));
+
+        contract.archive_task(task_a).unwrap();
+        assert_eq!(contract.get_task(task_a), None);
+        assert_eq!(contract.get_task_count_for(alice.clone()), 1);
+        let archived = contract.get_archived_tasks_for(alice.clone(), None, None);
+        assert_eq!(archived.len(), 1);
+        assert_eq!(archived[0].id, task_a);
+        assert_eq!(
+            contract.update_task(task_a, TaskStatus::Done, None),
+            Err(ContractError::TaskNotFound(task_a))
+        );
+
+        contract.restore_task(task_a).unwrap();
+        assert!(contract
+            .get_archived_tasks_for(alice.clone(), None, None)
+            .is_empty());
+        let ids: Vec<TaskId> = contract.get_tasks().into_iter().map(|t| t.id).collect();
+        assert_eq!(ids, vec![task_b, task_a]);
+        assert_eq!(
+            contract.restore_task(task_a),
+            Err(ContractError::TaskNotFound(task_a))
+        );
+    }
+
+    #[test]
+    fn delete_archived_task() {
+        testing_env!(get_context(false).build());
+        let alice = accounts(0);
+        let mut contract = Contract::default();
+        let task_id = contract.insert_task(String::from("task_a"));
+        contract.archive_task(task_id).unwrap();
+        contract.delete_task(task_id).unwrap();
+        assert!(contract
+            .get_archived_tasks_for(alice, None, None)
+            .is_empty());
+    }
+
+    #[test]
+    fn only_owner_can_delete_or_archive() {
+        let mut context = get_context(false);
+        testing_env!(context.build());
+        let mut contract = Contract::default();
+        let task_id = contract.insert_task(String::from("task_a"));
+
+        context.predecessor_account_id(accounts(1));
+        testing_env!(context.build());
+        let not_owner = Err(ContractError::NotTaskOwner {
+            task_id,
+            account_id: accounts(1),
+        });
+        assert_eq!(contract.delete_task(task_id), not_owner);
+        assert_eq!(contract.archive_task(task_id), not_owner);
+        assert!(contract.get_task(task_id).is_some());
+    }
+
+    #[test]
+    fn get_tasks_for_is_paginated() {
+        testing_env!(get_context(false).build());
+        let alice = accounts(0);
+        let mut contract = Contract::default();
+        for i in 0..5 {
+            contract.insert_task(format!("task_{}", i));
+        }
+
+        testing_env!(get_context(true).build());
+        let page = contract.get_tasks_for(alice.clone(), Some(1), Some(2));
+        let names: Vec<String> = page.into_iter().map(|t| t.task_name).collect();
+        assert_eq!(names, vec!["task_1", "task_2"]);
+        assert_eq!(
+            contract
+                .get_tasks_for(alice.clone(), Some(4), Some(10))
+                .len(),
+            1
+        );
+        assert!(contract
+            .get_tasks_for(alice.clone(), Some(5), None)
+            .is_empty());
+        assert_eq!(contract.get_tasks_for(alice, None, None).len(), 5);
+    }
+
+    #[test]
+    fn migrates_vec_index_on_next_write() {
+        testing_env!(get_context(false).build());
+        let alice = accounts(0);
+        let mut contract = Contract::default();
+        let task_id = contract.insert_task(String::from("task_a"));
+        // Move the task into the `Vec` based index used before per-account sets.
+        contract.tasks_by_account.remove(&alice, task_id);
+        let mut vec_index: LookupMap<AccountId, Vec<TaskId>> =
+            LookupMap::new(VEC_TASKS_BY_ACCOUNT_PREFIX);
+        vec_index.insert(&alice, &vec![task_id]);
+
+        assert_eq!(contract.get_task_count_for(alice.clone()), 1);
+        contract.archive_task(task_id).unwrap();
+        assert!(!vec_index.contains_key(&alice));
+        assert_eq!(contract.get_task_count_for(alice.clone()), 0);
+        assert_eq!(contract.get_archived_tasks_for(alice, None, None).len(), 1);
+    }
+
+    #[test]
+    fn insert_and_update_emit_events() {
+        testing_env!(get_context(false).build());
+        let mut contract = Contract::default();
+        let task_id = contract.insert_task(String::from("task_a"));
+        assert_eq!(
+            get_events(),
+            vec![json!({
+                "standard": "tasks",
+                "version": EVENT_STANDARD_VERSION,
+                "event": "task_created",
+                "data": [{
+                    "owner_id": "alice",
+                    "task_id": task_id,
+                    "task_name": "task_a",
+                    "task_status": "TODO",
+                    "description": null,
+                    "priority": "NORMAL",
+                    "due_date": null,
+                    "tags": [],
+                    "list_id": null,
+                    "parent_id": null,
+                    "recurrence": null
+                }]
+            })]
+        );
+
+        testing_env!(get_context(false).build());
+        contract
+            .update_task(task_id, TaskStatus::Done, None)
+            .unwrap();
+        assert_eq!(
+            get_events(),
+            vec![json!({
+                "standard": "tasks",
+                "version": EVENT_STANDARD_VERSION,
+                "event": "status_changed",
+                "data": [{
+                    "owner_id": "alice",
+                    "task_id": task_id,
+                    "old_status": "TODO",
+                    "new_status": "DONE"
+                }]
+            })]
+        );
+
+        testing_env!(get_context(false).build());
+        contract
+            .rename_task(task_id, String::from("task_b"), None)
+            .unwrap();
+        assert_eq!(
+            get_events(),
+            vec![json!({
+                "standard": "tasks",
+                "version": EVENT_STANDARD_VERSION,
+                "event": "task_updated",
+                "data": [{
+                    "owner_id": "alice",
+                    "task_id": task_id,
+                    "field": "task_name",
+                    "old_value": "task_a",
+                    "new_value": "task_b"
+                }]
+            })]
+        );
+    }
+
+    #[test]
+    fn archive_restore_and_delete_emit_events() {
+        testing_env!(get_context(false).build());
+        let mut contract = Contract::default();
+        let task_id = contract.insert_task(String::from("task_a"));
+        let data = json!([{
+            "owner_id": "alice",
+            "task_id": task_id,
+            "task_name": "task_a",
+            "task_status": "TODO"
+        }]);
+
+        for (event, call) in [
+            (
+                "task_archived",
+                Contract::archive_task as fn(&mut Contract, TaskId) -> _,
+            ),
+            ("task_restored", Contract::restore_task),
+            ("task_deleted", Contract::delete_task),
+        ] {
+            testing_env!(get_context(false).build());
+            call(&mut contract, task_id).unwrap();
+            let events = get_events();
+            assert_eq!(events.len(), 1);
+            assert_eq!(events[0]["event"], event);
+            assert_eq!(events[0]["data"], data);
+        }
+    }
+
+    #[test]
+    fn failed_update_emits_no_event() {
+        testing_env!(get_context(false).build());
+        let mut contract = Contract::default();
+        let task_id = contract.insert_task(String::from("task_a"));
+
+        testing_env!(get_context(false).build());
+        assert!(contract
+            .update_task(task_id, TaskStatus::Todo, None)
+            .is_err());
+        assert!(get_events().is_empty());
+    }
+
+    #[test]
+    fn create_task_with_details() {
+        let mut context = get_context(false);
+        context.block_timestamp(1_000);
+        testing_env!(context.build());
+        let mut contract = Contract::default();
+        let task_id = contract
+            .create_task(NewTask {
+                task_name: String::from("task_a"),
+                description: Some(String::from(" Weekly planning ")),
+                priority: Priority::Urgent,
+                due_date: Some(U64(2_000)),
+                tags: vec![String::from("Work"), String::from("work")],
+                track_history: false,
+                list_id: None,
+                parent_id: None,
+                recurrence: None,
+            })
+            .unwrap();
+
+        let task = contract.get_task(task_id).unwrap();
+        assert_eq!(task.description.as_deref(), Some("Weekly planning"));
+        assert_eq!(task.priority, Priority::Urgent);
+        assert_eq!(task.due_date, Some(U64(2_000)));
+        assert_eq!(task.tags, vec!["work"]);
+    }
+
+    #[test]
+    fn create_task_rejects_invalid_details() {
+        let mut context = get_context(false);
+        context.block_timestamp(1_000);
+        testing_env!(context.build());
+        let mut contract = Contract::default();
+        let result = contract.create_task(NewTask {
+            due_date: Some(U64(500)),
+            ..NewTask::new(String::from("task_a"))
+        });
+        assert_eq!(
+            result,
+            Err(ContractError::DueDateInPast {
+                due_date: 500,
+                block_timestamp: 1_000
+            })
+        );
+        // The id wasn't used up by the failed call.
+        assert_eq!(contract.insert_task(String::from("task_a")), 0);
+    }
+
+    #[test]
+    fn patch_task_changes_only_given_fields() {
+        testing_env!(get_context(false).build());
+        let mut contract = Contract::default();
+        let task_id = contract
+            .create_task(NewTask {
+                description: Some(String::from("notes")),
+                tags: vec![String::from("work")],
+                ..NewTask::new(String::from("task_a"))
+            })
+            .unwrap();
+
+        testing_env!(get_context(false).build());
+        let patch: TaskPatch = serde_json::from_value(json!({
+            "description": null,
+            "priority": "HIGH",
+            "task_status": "IN_PROGRESS",
+        }))
+        .unwrap();
+        contract.patch_task(task_id, patch).unwrap();
+
+        let task = contract.get_task(task_id).unwrap();
+        assert_eq!(task.task_name, "task_a");
+        assert_eq!(task.task_status, TaskStatus::InProgress);
+        assert_eq!(task.description, None);
+        assert_eq!(task.priority, Priority::High);
+        assert_eq!(task.tags, vec!["work"]);
+
+        let events = get_events();
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0]["event"], "status_changed");
+        assert_eq!(events[1]["event"], "task_updated");
+        assert_eq!(
+            events[1]["data"],
+            json!([
+                {
+                    "owner_id": "alice",
+                    "task_id": task_id,
+                    "field": "description",
+                    "old_value": "notes",
+                    "new_value": null
+                },
+                {
+                    "owner_id": "alice",
+                    "task_id": task_id,
+                    "field": "priority",
+                    "old_value": "NORMAL",
+                    "new_value": "HIGH"
+                }
+            ])
+        );
+    }
+
+    #[test]
+    fn patch_task_is_all_or_nothing() {
+        testing_env!(get_context(false).build());
+        let mut contract = Contract::default();
+        let task_id = contract.insert_task(String::from("task_a"));
+        let before = contract.get_task(task_id);
+
+        let result = contract.patch_task(
+            task_id,
+            TaskPatch {
+                priority: Some(Priority::Low),
+                tags: Some(vec![String::from("not a tag")]),
+                ..Default::default()
+            },
+        );
+        assert_eq!(
+            result,
+            Err(ContractError::InvalidTag(String::from("not a tag")))
+        );
+        assert_eq!(contract.get_task(task_id), before);
+    }
+
+    #[test]
+    fn stale_edits_fail_with_a_version_conflict() {
+        testing_env!(get_context(false).build());
+        let mut contract = Contract::default();
+        let task_id = contract.insert_task(String::from("task_a"));
+        assert_eq!(contract.get_task(task_id).unwrap().version, 0);
+
+        // Two editors read version 0, the second one's change is rejected.
+        contract
+            .update_task(task_id, TaskStatus::InProgress, Some(0))
+            .unwrap();
+        assert_eq!(
+            contract.rename_task(task_id, String::from("task_b"), Some(0)),
+            Err(ContractError::VersionConflict {
+                task_id,
+                expected_version: 0,
+                version: 1
+            })
+        );
+        let patch: TaskPatch = serde_json::from_value(json!({
+            "task_name": "task_b",
+            "expected_version": 1,
+        }))
+        .unwrap();
+        contract.patch_task(task_id, patch).unwrap();
+        assert_eq!(contract.get_task(task_id).unwrap().version, 2);
+
+        // A patch that changes nothing doesn't count as a change.
+        contract
+            .rename_task(task_id, String::from("task_b"), None)
+            .unwrap();
+        assert_eq!(contract.get_task(task_id).unwrap().version, 2);
+    }
+
+    #[test]
+    fn tracks_timestamps_of_changes() {
+        testing_env!(get_context(false).block_timestamp(1_000).build());
+        let mut contract = Contract::default();
+        let task_id = contract.insert_task(String::from("task_a"));
+        let task = contract.get_task(task_id).unwrap();
+        assert_eq!((task.created_at, task.updated_at), (U64(1_000), U64(1_000)));
+        assert_eq!(task.updated_by, accounts(0));
+
+        testing_env!(get_context(false).block_timestamp(2_000).build());
+        contract
+            .update_task(task_id, TaskStatus::Done, None)
+            .unwrap();
+        let task = contract.get_task(task_id).unwrap();
+        assert_eq!((task.created_at, task.updated_at), (U64(1_000), U64(2_000)));
+        assert_eq!(task.completed_at, Some(U64(2_000)));
+
+        // Reopening a task clears its completion time.
+        testing_env!(get_context(false).block_timestamp(3_000).build());
+        contract
+            .update_task(task_id, TaskStatus::Todo, None)
+            .unwrap();
+        let task = contract.get_task(task_id).unwrap();
+        assert_eq!(task.updated_at, U64(3_000));
+        assert_eq!(task.completed_at, None);
+
+        testing_env!(get_context(false).block_timestamp(4_000).build());
+        contract.archive_task(task_id).unwrap();
+        let task = contract.get_archived_tasks_for(accounts(0), None, None)[0].clone();
+        assert_eq!(task.updated_at, U64(4_000));
+    }
+}
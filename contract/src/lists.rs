@@ -0,0 +1,554 @@
+//! Shared task lists.
+//!
+//! A list is owned by the account that created it, which can share it with collaborators.
+//! Tasks created with a `list_id` belong to the list: besides their owner, editors of the list
+//! may change them and admins may delete them. Admins manage viewers and editors, only the
+//! owner manages admins. The storage of the list and its collaborators is paid by the owner.
+//! Collaborators are added without their consent, so the number of lists an account owns or
+//! collaborates on is capped, which bounds the work `storage_unregister` does for it.
+//!
+//! Contract state is public, so `Viewer` doesn't protect anything from being read. It only
+//! makes the list show up in `get_lists_for` of the viewer.
+
+use std::fmt;
+
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::U64;
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::{env, near_bindgen, AccountId};
+
+use crate::events::CollaboratorData;
+use crate::*;
+
+/// Opaque list id, allocated from a contract-wide counter and never reused.
+pub type ListId = u64;
+
+/// Maximum number of collaborators of a single list, the owner not included.
+pub const MAX_COLLABORATORS: usize = 50;
+/// Maximum number of lists an account owns or collaborates on.
+pub const MAX_LISTS_PER_ACCOUNT: usize = 100;
+/// Maximum length of a list name, in characters.
+pub const MAX_LIST_NAME_LENGTH: usize = 100;
+
+/// Role of a collaborator in a list. Every role includes the permissions of the roles before it.
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    BorshDeserialize,
+    BorshSerialize,
+    Serialize,
+    Deserialize,
+)]
+#[serde(crate = "near_sdk::serde", rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum Role {
+    /// Listed as a member of the list.
+    Viewer,
+    /// Creates tasks in the list and changes, archives and restores any of its tasks.
+    Editor,
+    /// Deletes any task of the list and manages viewers and editors.
+    Admin,
+}
+
+impl fmt::Display for Role {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Role::Viewer => "VIEWER",
+            Role::Editor => "EDITOR",
+            Role::Admin => "ADMIN",
+        };
+        f.write_str(name)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, BorshDeserialize, BorshSerialize, Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct Collaborator {
+    pub account_id: AccountId,
+    pub role: Role,
+}
+
+#[derive(Debug, Clone, PartialEq, BorshDeserialize, BorshSerialize, Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct TaskList {
+    pub id: ListId,
+    /// Account that created the list, it has every permission.
+    pub owner_id: AccountId,
+    pub name: String,
+    /// At most `MAX_COLLABORATORS` of them.
+    pub collaborators: Vec<Collaborator>,
+    /// Block timestamp the list was created at, in nanoseconds.
+    pub created_at: U64,
+}
+
+impl TaskList {
+    /// Role of the account in the list, the owner counts as an admin.
+    pub fn role_of(&self, account_id: &AccountId) -> Option<Role> {
+        if *account_id == self.owner_id {
+            return Some(Role::Admin);
+        }
+        self.collaborators
+            .iter()
+            .find(|collaborator| collaborator.account_id == *account_id)
+            .map(|collaborator| collaborator.role)
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    // Public method - returns a single task list by its id
+    pub fn get_list(&self, list_id: ListId) -> Option<TaskList> {
+        self.task_lists.get(&list_id)
+    }
+
+    // Public method - returns a page of the lists the given account owns or collaborates on
+    pub fn get_lists_for(
+        &self,
+        account_id: AccountId,
+        from_index: Option<u64>,
+        limit: Option<u64>,
+    ) -> Vec<TaskList> {
+        self.lists_by_account
+            .page(&account_id, from_index, limit)
+            .into_iter()
+            .map(|list_id| self.task_lists.get(&list_id).unwrap())
+            .collect()
+    }
+
+    // Public method - returns a page of the active tasks of a list
+    pub fn get_list_tasks(
+        &self,
+        list_id: ListId,
+        from_index: Option<u64>,
+        limit: Option<u64>,
+    ) -> Vec<Task> {
+        self.tasks_by_list
+            .page(&list_id, from_index, limit)
+            .into_iter()
+            .map(|t| self.tasks.get(&t).unwrap())
+            .collect()
+    }
+
+    // Public method - returns a page of the archived tasks of a list
+    pub fn get_archived_list_tasks(
+        &self,
+        list_id: ListId,
+        from_index: Option<u64>,
+        limit: Option<u64>,
+    ) -> Vec<Task> {
+        self.archived_by_list
+            .page(&list_id, from_index, limit)
+            .into_iter()
+            .map(|t| self.archived_tasks.get(&t).unwrap())
+            .collect()
+    }
+
+    // Public method - create a list owned by the caller and return its id
+    // The storage is charged against the caller's storage balance, like in `insert_task`.
+    #[payable]
+    #[handle_result]
+    pub fn create_list(&mut self, name: String) -> Result<ListId, ContractError> {
+        let name = validate_list_name(name)?;
+        let owner_id = env::predecessor_account_id();
+        self.ensure_list_capacity(&owner_id)?;
+        let initial_storage_usage = env::storage_usage();
+        let list = TaskList {
+            id: self.next_list_id,
+            owner_id: owner_id.clone(),
+            name,
+            collaborators: Vec::new(),
+            created_at: U64(env::block_timestamp()),
+        };
+        self.next_list_id += 1;
+        self.task_lists.insert(&list.id, &list);
+        self.lists_by_account.insert(&owner_id, list.id);
+        TaskEvent::ListCreated(vec![(&list).into()]).emit();
+        self.settle_storage(initial_storage_usage);
+        Ok(list.id)
+    }
+
+    // Public method - remove an empty list, only its owner may do this
+    #[handle_result]
+    pub fn delete_list(&mut self, list_id: ListId) -> Result<(), ContractError> {
+        let list = self
+            .get_list(list_id)
+            .ok_or(ContractError::ListNotFound(list_id))?;
+        Self::ensure_list_owner(&list)?;
+        if !self.tasks_by_list.is_empty(&list_id) || !self.archived_by_list.is_empty(&list_id) {
+            return Err(ContractError::ListNotEmpty(list_id));
+        }
+        let initial_storage_usage = env::storage_usage();
+        for collaborator in &list.collaborators {
+            self.lists_by_account
+                .remove(&collaborator.account_id, list_id);
+        }
+        self.lists_by_account.remove(&list.owner_id, list_id);
+        self.task_lists.remove(&list_id);
+        TaskEvent::ListDeleted(vec![(&list).into()]).emit();
+        self.settle_storage(initial_storage_usage);
+        Ok(())
+    }
+
+    // Public method - add a collaborator to a list or change their role
+    // Admins add viewers and editors, only the owner adds admins or changes their role.
+    // The storage is charged against the list owner's storage balance, an attached deposit
+    // is added to that balance first.
+    #[payable]
+    #[handle_result]
+    pub fn add_collaborator(
+        &mut self,
+        list_id: ListId,
+        account_id: AccountId,
+        role: Role,
+    ) -> Result<(), ContractError> {
+        let mut list = self
+            .get_list(list_id)
+            .ok_or(ContractError::ListNotFound(list_id))?;
+        if account_id == list.owner_id {
+            return Err(ContractError::OwnerIsNotCollaborator(list_id));
+        }
+        let current_role = list.role_of(&account_id);
+        if role == Role::Admin || current_role == Some(Role::Admin) {
+            Self::ensure_list_owner(&list)?;
+        } else {
+            Self::ensure_role(&list, Role::Admin)?;
+        }
+        let initial_storage_usage = env::storage_usage();
+        match list
+            .collaborators
+            .iter_mut()
+            .find(|collaborator| collaborator.account_id == account_id)
+        {
+            Some(collaborator) => collaborator.role = role,
+            None => {
+                if list.collaborators.len() >= MAX_COLLABORATORS {
+                    return Err(ContractError::TooManyCollaborators {
+                        list_id,
+                        max_count: MAX_COLLABORATORS,
+                    });
+                }
+                self.ensure_list_capacity(&account_id)?;
+                list.collaborators.push(Collaborator {
+                    account_id: account_id.clone(),
+                    role,
+                });
+                self.lists_by_account.insert(&account_id, list_id);
+            }
+        }
+        self.task_lists.insert(&list_id, &list);
+        TaskEvent::CollaboratorAdded(vec![CollaboratorData {
+            list_id,
+            account_id,
+            role: Some(role),
+        }])
+        .emit();
+        self.settle_storage_of(&list.owner_id, initial_storage_usage);
+        Ok(())
+    }
+
+    // Public method - remove a collaborator from a list
+    // Admins remove viewers and editors, only the owner removes admins. Every collaborator
+    // may remove themselves. The released storage is credited to the list owner.
+    #[handle_result]
+    pub fn remove_collaborator(
+        &mut self,
+        list_id: ListId,
+        account_id: AccountId,
+    ) -> Result<(), ContractError> {
+        let mut list = self
+            .get_list(list_id)
+            .ok_or(ContractError::ListNotFound(list_id))?;
+        if account_id == list.owner_id {
+            return Err(ContractError::OwnerIsNotCollaborator(list_id));
+        }
+        let role = list
+            .role_of(&account_id)
+            .ok_or_else(|| ContractError::NotCollaborator {
+                list_id,
+                account_id: account_id.clone(),
+            })?;
+        if account_id != env::predecessor_account_id() {
+            if role == Role::Admin {
+                Self::ensure_list_owner(&list)?;
+            } else {
+                Self::ensure_role(&list, Role::Admin)?;
+            }
+        }
+        let initial_storage_usage = env::storage_usage();
+        list.collaborators
+            .retain(|collaborator| collaborator.account_id != account_id);
+        self.task_lists.insert(&list_id, &list);
+        self.lists_by_account.remove(&account_id, list_id);
+        TaskEvent::CollaboratorRemoved(vec![CollaboratorData {
+            list_id,
+            account_id,
+            role: None,
+        }])
+        .emit();
+        self.settle_storage_of(&list.owner_id, initial_storage_usage);
+        Ok(())
+    }
+}
+
+impl Contract {
+    /// Checks that the predecessor has at least the `required` role in the list.
+    pub(crate) fn ensure_role(list: &TaskList, required: Role) -> Result<(), ContractError> {
+        let account_id = env::predecessor_account_id();
+        if list.role_of(&account_id) >= Some(required) {
+            return Ok(());
+        }
+        Err(ContractError::NotPermitted {
+            list_id: list.id,
+            account_id,
+            required,
+        })
+    }
+
+    fn ensure_list_owner(list: &TaskList) -> Result<(), ContractError> {
+        let account_id = env::predecessor_account_id();
+        if list.owner_id != account_id {
+            return Err(ContractError::NotListOwner {
+                list_id: list.id,
+                account_id,
+            });
+        }
+        Ok(())
+    }
+
+    /// Whether the account owns a list, in which case it can't unregister its storage.
+    /// Fails if the account can't own or collaborate on another list.
+    fn ensure_list_capacity(&self, account_id: &AccountId) -> Result<(), ContractError> {
+        if self.lists_by_account.len(account_id) >= MAX_LISTS_PER_ACCOUNT as u64 {
+            return Err(ContractError::TooManyLists {
+                account_id: account_id.clone(),
+                max_count: MAX_LISTS_PER_ACCOUNT,
+            });
+        }
+        Ok(())
+    }
+
+    pub(crate) fn owns_lists(&self, account_id: &AccountId) -> bool {
+        self.lists_by_account
+            .ids(account_id)
+            .into_iter()
+            .any(|list_id| {
+                self.task_lists
+                    .get(&list_id)
+                    .is_some_and(|list| list.owner_id == *account_id)
+            })
+    }
+}
+
+fn validate_list_name(name: String) -> Result<String, ContractError> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err(ContractError::EmptyListName);
+    }
+    let length = name.chars().count();
+    if length > MAX_LIST_NAME_LENGTH {
+        return Err(ContractError::ListNameTooLong {
+            length,
+            max_length: MAX_LIST_NAME_LENGTH,
+        });
+    }
+    Ok(name.to_owned())
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use super::*;
+    use near_sdk::test_utils::{accounts, VMContextBuilder};
+    use near_sdk::{testing_env, ONE_NEAR};
+
+    fn set_predecessor(account_id: AccountId) {
+        let mut context = VMContextBuilder::new();
+        context
+            .predecessor_account_id(account_id)
+            .account_balance(10 * ONE_NEAR)
+            .attached_deposit(ONE_NEAR / 100);
+        testing_env!(context.build());
+    }
+
+    // alice owns a list with bob as editor and charlie as viewer
+    fn setup() -> (Contract, ListId) {
+        set_predecessor(accounts(0));
+        let mut contract = Contract::default();
+        let list_id = contract.create_list(String::from("team")).unwrap();
+        contract
+            .add_collaborator(list_id, accounts(1), Role::Editor)
+            .unwrap();
+        contract
+            .add_collaborator(list_id, accounts(2), Role::Viewer)
+            .unwrap();
+        (contract, list_id)
+    }
+
+    fn create_list_task(contract: &mut Contract, list_id: ListId) -> Result<TaskId, ContractError> {
+        contract.create_task(NewTask {
+            list_id: Some(list_id),
+            ..NewTask::new(String::from("task_a"))
+        })
+    }
+
+    #[test]
+    fn editors_work_on_tasks_of_the_list() {
+        let (mut contract, list_id) = setup();
+
+        set_predecessor(accounts(1));
+        let task_id = create_list_task(&mut contract, list_id).unwrap();
+        set_predecessor(accounts(0));
+        contract
+            .update_task(task_id, TaskStatus::Done, None)
+            .unwrap();
+
+        let task = contract.get_task(task_id).unwrap();
+        assert_eq!(task.owner_id, accounts(1));
+        assert_eq!(task.updated_by, accounts(0));
+        assert_eq!(task.list_id, Some(list_id));
+        assert_eq!(contract.get_list_tasks(list_id, None, None), vec![task]);
+        let lists = contract.get_lists_for(accounts(2), None, None);
+        assert_eq!(lists.len(), 1);
+        assert_eq!(lists[0].name, "team");
+    }
+
+    #[test]
+    fn viewers_cant_change_tasks() {
+        let (mut contract, list_id) = setup();
+        let task_id = create_list_task(&mut contract, list_id).unwrap();
+
+        set_predecessor(accounts(2));
+        let not_permitted = |required| ContractError::NotPermitted {
+            list_id,
+            account_id: accounts(2),
+            required,
+        };
+        assert_eq!(
+            create_list_task(&mut contract, list_id),
+            Err(not_permitted(Role::Editor))
+        );
+        assert_eq!(
+            contract.update_task(task_id, TaskStatus::Done, None),
+            Err(not_permitted(Role::Editor))
+        );
+        assert_eq!(
+            contract.delete_task(task_id),
+            Err(not_permitted(Role::Admin))
+        );
+        assert_eq!(
+            contract.add_collaborator(list_id, accounts(3), Role::Viewer),
+            Err(not_permitted(Role::Admin))
+        );
+    }
+
+    #[test]
+    fn only_admins_delete_tasks_of_others() {
+        let (mut contract, list_id) = setup();
+        let task_id = create_list_task(&mut contract, list_id).unwrap();
+
+        set_predecessor(accounts(1));
+        assert!(contract.delete_task(task_id).is_err());
+        // Everyone may delete their own tasks.
+        let own_task_id = create_list_task(&mut contract, list_id).unwrap();
+        contract.delete_task(own_task_id).unwrap();
+
+        set_predecessor(accounts(0));
+        contract
+            .add_collaborator(list_id, accounts(1), Role::Admin)
+            .unwrap();
+        set_predecessor(accounts(1));
+        contract.delete_task(task_id).unwrap();
+        assert!(contract.get_list_tasks(list_id, None, None).is_empty());
+    }
+
+    #[test]
+    fn only_the_owner_manages_admins() {
+        let (mut contract, list_id) = setup();
+        contract
+            .add_collaborator(list_id, accounts(3), Role::Admin)
+            .unwrap();
+
+        set_predecessor(accounts(3));
+        contract
+            .add_collaborator(list_id, accounts(4), Role::Editor)
+            .unwrap();
+        assert_eq!(
+            contract.add_collaborator(list_id, accounts(1), Role::Admin),
+            Err(ContractError::NotListOwner {
+                list_id,
+                account_id: accounts(3)
+            })
+        );
+        assert_eq!(
+            contract.add_collaborator(list_id, accounts(0), Role::Viewer),
+            Err(ContractError::OwnerIsNotCollaborator(list_id))
+        );
+        contract.remove_collaborator(list_id, accounts(1)).unwrap();
+
+        // Collaborators may always leave a list.
+        set_predecessor(accounts(2));
+        contract.remove_collaborator(list_id, accounts(2)).unwrap();
+        assert!(contract.get_lists_for(accounts(2), None, None).is_empty());
+        assert_eq!(
+            contract.remove_collaborator(list_id, accounts(2)),
+            Err(ContractError::NotCollaborator {
+                list_id,
+                account_id: accounts(2)
+            })
+        );
+        let roles: Vec<Role> = contract
+            .get_list(list_id)
+            .unwrap()
+            .collaborators
+            .into_iter()
+            .map(|collaborator| collaborator.role)
+            .collect();
+        assert_eq!(roles, vec![Role::Admin, Role::Editor]);
+    }
+
+    #[test]
+    fn only_empty_lists_are_deleted() {
+        let (mut contract, list_id) = setup();
+        let task_id = create_list_task(&mut contract, list_id).unwrap();
+        contract.archive_task(task_id).unwrap();
+        assert_eq!(
+            contract.delete_list(list_id),
+            Err(ContractError::ListNotEmpty(list_id))
+        );
+
+        contract.delete_task(task_id).unwrap();
+        contract.delete_list(list_id).unwrap();
+        assert_eq!(contract.get_list(list_id), None);
+        assert!(contract.get_lists_for(accounts(1), None, None).is_empty());
+    }
+
+    #[test]
+    fn memberships_per_account_are_capped() {
+        let (mut contract, list_id) = setup();
+        // dan already owns or collaborates on the maximum number of other lists.
+        for other_list_id in 0..MAX_LISTS_PER_ACCOUNT as ListId {
+            contract
+                .lists_by_account
+                .insert(&accounts(3), list_id + 1 + other_list_id);
+        }
+        assert_eq!(
+            contract.add_collaborator(list_id, accounts(3), Role::Viewer),
+            Err(ContractError::TooManyLists {
+                account_id: accounts(3),
+                max_count: MAX_LISTS_PER_ACCOUNT
+            })
+        );
+        // Changing the role of an existing collaborator still works.
+        contract
+            .add_collaborator(list_id, accounts(2), Role::Editor)
+            .unwrap();
+        set_predecessor(accounts(3));
+        assert!(matches!(
+            contract.create_list(String::from("own")),
+            Err(ContractError::TooManyLists { .. })
+        ));
+    }
+}
@@ -0,0 +1,203 @@
+//! Recurring tasks.
+//!
+//! A task created with a `recurrence` rule spawns its next occurrence when it is moved to `DONE`.
+//! The occurrence copies the details of the task, starts as `TODO` with its checklist unchecked,
+//! and is due one interval after the block timestamp of the completion. The rule moves on to the
+//! new occurrence, so reopening and finishing the completed task again doesn't spawn another one.
+
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::env;
+use near_sdk::json_types::U64;
+use near_sdk::serde::{Deserialize, Serialize};
+
+use crate::*;
+
+/// Longest interval of a recurrence rule, in days.
+pub const MAX_RECURRENCE_DAYS: u16 = 365;
+
+const NANOS_PER_DAY: u64 = 24 * 60 * 60 * 1_000_000_000;
+
+/// How often a task recurs, serialized as `"DAILY"`, `"WEEKLY"` or `{"EVERY_N_DAYS": 3}`.
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, BorshDeserialize, BorshSerialize, Serialize, Deserialize,
+)]
+#[serde(crate = "near_sdk::serde", rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum Recurrence {
+    Daily,
+    Weekly,
+    /// Between 1 and `MAX_RECURRENCE_DAYS` days.
+    EveryNDays(u16),
+}
+
+impl Recurrence {
+    pub fn interval_days(self) -> u16 {
+        match self {
+            Recurrence::Daily => 1,
+            Recurrence::Weekly => 7,
+            Recurrence::EveryNDays(days) => days,
+        }
+    }
+
+    /// Block timestamp the occurrence after one completed at `timestamp` is due at.
+    fn next_due_date(self, timestamp: u64) -> U64 {
+        U64(timestamp.saturating_add(u64::from(self.interval_days()) * NANOS_PER_DAY))
+    }
+}
+
+pub(crate) fn validate_recurrence(
+    recurrence: Option<Recurrence>,
+) -> Result<Option<Recurrence>, ContractError> {
+    if let Some(Recurrence::EveryNDays(days)) = recurrence {
+        if days == 0 || days > MAX_RECURRENCE_DAYS {
+            return Err(ContractError::InvalidRecurrence {
+                days,
+                max_days: MAX_RECURRENCE_DAYS,
+            });
+        }
+    }
+    Ok(recurrence)
+}
+
+impl Contract {
+    /// Stores the next occurrence of a recurring task that was just moved to `DONE` and hands
+    /// the rule over to it. The caller emits the `task_created` event of the occurrence.
+    pub(crate) fn spawn_next_occurrence(&mut self, task: &mut Task) -> Option<Task> {
+        let recurrence = task.recurrence.take()?;
+        let now = env::block_timestamp();
+        let task_id = self.next_task_id;
+        self.next_task_id += 1;
+        let next = Task {
+            id: task_id,
+            owner_id: task.owner_id.clone(),
+            task_name: task.task_name.clone(),
+            task_status: TaskStatus::Todo,
+            description: task.description.clone(),
+            priority: task.priority,
+            due_date: Some(recurrence.next_due_date(now)),
+            tags: task.tags.clone(),
+            created_at: U64(now),
+            updated_at: U64(now),
+            updated_by: env::predecessor_account_id(),
+            completed_at: None,
+            track_history: task.track_history,
+            list_id: task.list_id,
+            assignee: None,
+            parent_id: None,
+            subtask_ids: Vec::new(),
+            checklist: task
+                .checklist
+                .iter()
+                .map(|item| ChecklistItem {
+                    text: item.text.clone(),
+                    done: false,
+                })
+                .collect(),
+            blocker_ids: Vec::new(),
+            dependent_ids: Vec::new(),
+            recurrence: Some(recurrence),
+            version: 0,
+        };
+        self.tasks.insert(&task_id, &next);
+        self.index_task(&next);
+        self.record_history(&next, vec![TaskChange::Created]);
+        Some(next)
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use super::*;
+    use near_sdk::serde_json::{self, json};
+    use near_sdk::test_utils::{accounts, VMContextBuilder};
+    use near_sdk::{testing_env, ONE_NEAR};
+
+    fn set_block_timestamp(block_timestamp: u64) {
+        let mut context = VMContextBuilder::new();
+        context
+            .predecessor_account_id(accounts(0))
+            .account_balance(10 * ONE_NEAR)
+            .attached_deposit(ONE_NEAR / 100)
+            .block_timestamp(block_timestamp);
+        testing_env!(context.build());
+    }
+
+    fn create_recurring_task(contract: &mut Contract, recurrence: Recurrence) -> TaskId {
+        contract
+            .create_task(NewTask {
+                recurrence: Some(recurrence),
+                ..NewTask::new(String::from("water plants"))
+            })
+            .unwrap()
+    }
+
+    #[test]
+    fn done_spawns_the_next_occurrence() {
+        set_block_timestamp(1_000);
+        let mut contract = Contract::default();
+        let task_id = create_recurring_task(&mut contract, Recurrence::Weekly);
+        contract
+            .add_checklist_item(task_id, String::from("kitchen"))
+            .unwrap();
+        contract.check_checklist_item(task_id, 0, true).unwrap();
+
+        set_block_timestamp(3 * NANOS_PER_DAY);
+        contract
+            .update_task(task_id, TaskStatus::Done, None)
+            .unwrap();
+        let next = contract.get_task(task_id + 1).unwrap();
+        assert_eq!(next.task_name, "water plants");
+        assert_eq!(next.task_status, TaskStatus::Todo);
+        assert_eq!(next.due_date, Some(U64(10 * NANOS_PER_DAY)));
+        assert_eq!(next.created_at, U64(3 * NANOS_PER_DAY));
+        assert_eq!(next.recurrence, Some(Recurrence::Weekly));
+        assert!(!next.checklist[0].done);
+        assert_eq!(contract.get_task(task_id).unwrap().recurrence, None);
+
+        // The rule moved on, finishing the old task again spawns nothing.
+        contract
+            .update_task(task_id, TaskStatus::Todo, None)
+            .unwrap();
+        contract
+            .update_task(task_id, TaskStatus::Done, None)
+            .unwrap();
+        assert_eq!(contract.get_task_count_for(accounts(0)), 2);
+    }
+
+    #[test]
+    fn every_n_days_is_bounded() {
+        set_block_timestamp(1_000);
+        let mut contract = Contract::default();
+        let task_id = create_recurring_task(&mut contract, Recurrence::EveryNDays(3));
+        set_block_timestamp(NANOS_PER_DAY);
+        contract.complete_task(task_id, false).unwrap();
+        assert_eq!(
+            contract.get_task(task_id + 1).unwrap().due_date,
+            Some(U64(4 * NANOS_PER_DAY))
+        );
+
+        for days in [0, MAX_RECURRENCE_DAYS + 1] {
+            assert_eq!(
+                contract.create_task(NewTask {
+                    recurrence: Some(Recurrence::EveryNDays(days)),
+                    ..NewTask::new(String::from("task_a"))
+                }),
+                Err(ContractError::InvalidRecurrence {
+                    days,
+                    max_days: MAX_RECURRENCE_DAYS
+                })
+            );
+        }
+    }
+
+    #[test]
+    fn recurrence_json() {
+        assert_eq!(
+            serde_json::to_value(Recurrence::Daily).unwrap(),
+            json!("DAILY")
+        );
+        assert_eq!(
+            serde_json::to_value(Recurrence::EveryNDays(3)).unwrap(),
+            json!({ "EVERY_N_DAYS": 3 })
+        );
+    }
+}
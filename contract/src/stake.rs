@@ -0,0 +1,302 @@
+//! Commitment stakes: NEAR locked on finishing a task by a deadline.
+//!
+//! The owner of an open task locks the attached deposit with `stake_on_task`. Moving the task to
+//! `DONE` before the deadline transfers the stake back to the owner. Once the deadline has passed,
+//! anyone can call `slash_expired`, which transfers the stake to the beneficiary that was
+//! configured with `set_stake_beneficiary` when the stake was locked. Cancelling or deleting the
+//! task doesn't release the stake, and unregistering the owner forfeits it right away. Failed
+//! transfers are kept like failed bounty transfers, see `withdraw_unclaimed_bounty`.
+
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::{U128, U64};
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::{env, near_bindgen, AccountId};
+
+use crate::details::validate_due_date;
+use crate::events::StakeData;
+use crate::*;
+
+#[derive(Debug, Clone, PartialEq, BorshDeserialize, BorshSerialize, Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct Stake {
+    /// Owner of the task, who gets the stake back when finishing in time.
+    pub staker_id: AccountId,
+    /// Locked yoctoNEAR.
+    pub amount: U128,
+    /// Block timestamp in nanoseconds the task has to be done by.
+    pub deadline: U64,
+    /// Account a missed stake is transferred to.
+    pub beneficiary_id: AccountId,
+}
+
+#[near_bindgen]
+impl Contract {
+    // Public method - returns the stake locked on a task
+    pub fn get_stake(&self, task_id: TaskId) -> Option<Stake> {
+        self.stakes.get(&task_id)
+    }
+
+    // Public method - returns the ids of the tasks the given account has stakes locked on
+    pub fn get_staked_task_ids(
+        &self,
+        account_id: AccountId,
+        from_index: Option<u64>,
+        limit: Option<u64>,
+    ) -> Vec<TaskId> {
+        self.stakes_by_account.page(&account_id, from_index, limit)
+    }
+
+    // Public method - returns the account missed stakes are transferred to
+    pub fn get_stake_beneficiary(&self) -> Option<AccountId> {
+        self.stake_beneficiary.clone()
+    }
+
+    // Private method - configure the account that receives missed stakes
+    // Stakes that are already locked keep their beneficiary.
+    #[private]
+    pub fn set_stake_beneficiary(&mut self, account_id: AccountId) {
+        self.stake_beneficiary = Some(account_id);
+    }
+
+    // Public method - lock the attached deposit on finishing one of the caller's open tasks by
+    // `deadline`, which defaults to the due date of the task
+    // A task has at most one stake. Its storage is charged against the caller's storage
+    // balance, the attached deposit is not.
+    #[payable]
+    #[handle_result]
+    pub fn stake_on_task(
+        &mut self,
+        task_id: TaskId,
+        deadline: Option<U64>,
+    ) -> Result<Stake, ContractError> {
+        let amount = env::attached_deposit();
+        if amount == 0 {
+            return Err(ContractError::EmptyStake);
+        }
+        let beneficiary_id = self
+            .stake_beneficiary
+            .clone()
+            .ok_or(ContractError::StakingNotConfigured)?;
+        let account_id = env::predecessor_account_id();
+        let task = self.fundable_task(task_id, &account_id)?;
+        if self.stakes.get(&task_id).is_some() {
+            return Err(ContractError::AlreadyStaked(task_id));
+        }
+        let deadline = validate_due_date(deadline.or(task.due_date))?
+            .ok_or(ContractError::NoDeadline(task_id))?;
+        let initial_storage_usage = env::storage_usage();
+        let stake = Stake {
+            staker_id: account_id.clone(),
+            amount: U128(amount),
+            deadline,
+            beneficiary_id,
+        };
+        self.stakes.insert(&task_id, &stake);
+        self.stakes_by_account.insert(&account_id, task_id);
+        TaskEvent::StakeLocked(vec![StakeData::new(task_id, &stake, &account_id)]).emit();
+        self.charge_storage(&account_id, initial_storage_usage);
+        Ok(stake)
+    }
+
+    // Public method - transfer the stake of a task whose deadline has passed to its beneficiary,
+    // anyone may call this
+    #[handle_result]
+    pub fn slash_expired(&mut self, task_id: TaskId) -> Result<(), ContractError> {
+        let stake = self
+            .stakes
+            .get(&task_id)
+            .ok_or(ContractError::NoStake(task_id))?;
+        if env::block_timestamp() <= stake.deadline.0 {
+            return Err(ContractError::StakeNotExpired(task_id));
+        }
+        let initial_storage_usage = env::storage_usage();
+        self.release_stake(task_id, &stake.beneficiary_id);
+        self.charge_storage(&stake.staker_id, initial_storage_usage);
+        Ok(())
+    }
+}
+
+impl Contract {
+    /// Returns the stake to its owner if the task was just moved to `DONE` before the deadline.
+    pub(crate) fn settle_stake(&mut self, task: &Task) {
+        if task.task_status != TaskStatus::Done {
+            return;
+        }
+        if let Some(stake) = self.stakes.get(&task.id) {
+            if env::block_timestamp() <= stake.deadline.0 {
+                self.release_stake(task.id, &stake.staker_id);
+            }
+        }
+    }
+
+    /// Forfeits the stakes of an account that unregisters.
+    pub(crate) fn slash_stakes_of(&mut self, account_id: &AccountId) {
+        for task_id in self.stakes_by_account.ids(account_id) {
+            if let Some(stake) = self.stakes.get(&task_id) {
+                self.release_stake(task_id, &stake.beneficiary_id);
+            }
+        }
+    }
+
+    /// Takes the stake out of escrow and transfers it to `receiver_id`, the staker or the
+    /// beneficiary.
+    fn release_stake(&mut self, task_id: TaskId, receiver_id: &AccountId) {
+        let stake = match self.stakes.remove(&task_id) {
+            Some(stake) => stake,
+            None => return,
+        };
+        self.stakes_by_account.remove(&stake.staker_id, task_id);
+        let data = vec![StakeData::new(task_id, &stake, receiver_id)];
+        if *receiver_id == stake.staker_id {
+            TaskEvent::StakeReclaimed(data).emit();
+        } else {
+            TaskEvent::StakeSlashed(data).emit();
+        }
+        Self::transfer_bounty(Some(task_id), receiver_id.clone(), stake.amount.0);
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use super::*;
+    use near_contract_standards::storage_management::StorageManagement;
+    use near_sdk::mock::VmAction;
+    use near_sdk::test_utils::{accounts, get_created_receipts, VMContextBuilder};
+    use near_sdk::{testing_env, Balance, ONE_NEAR};
+
+    fn set_context(account_id: AccountId, deposit: Balance, block_timestamp: u64) {
+        let mut context = VMContextBuilder::new();
+        context
+            .current_account_id(accounts(5))
+            .predecessor_account_id(account_id)
+            .account_balance(10 * ONE_NEAR)
+            .attached_deposit(deposit)
+            .block_timestamp(block_timestamp);
+        testing_env!(context.build());
+    }
+
+    // Transfers made by the last call
+    fn transfers() -> Vec<(AccountId, Balance)> {
+        get_created_receipts()
+            .into_iter()
+            .flat_map(|receipt| {
+                let receiver_id = receipt.receiver_id;
+                receipt
+                    .actions
+                    .into_iter()
+                    .filter_map(move |action| match action {
+                        VmAction::Transfer { deposit } => Some((receiver_id.clone(), deposit)),
+                        _ => None,
+                    })
+            })
+            .collect()
+    }
+
+    // alice stakes 2 NEAR on finishing a task by 5_000, charity is the beneficiary
+    fn setup() -> (Contract, TaskId) {
+        set_context(accounts(5), 0, 1_000);
+        let mut contract = Contract::default();
+        contract.set_stake_beneficiary(accounts(3));
+        set_context(accounts(0), ONE_NEAR / 100, 1_000);
+        let task_id = contract.insert_task(String::from("task_a"));
+        set_context(accounts(0), 2 * ONE_NEAR, 1_000);
+        contract.stake_on_task(task_id, Some(U64(5_000))).unwrap();
+        (contract, task_id)
+    }
+
+    #[test]
+    fn finishing_in_time_returns_the_stake() {
+        let (mut contract, task_id) = setup();
+        assert_eq!(
+            contract.get_staked_task_ids(accounts(0), None, None),
+            [task_id]
+        );
+        assert_eq!(
+            contract.stake_on_task(task_id, None),
+            Err(ContractError::AlreadyStaked(task_id))
+        );
+
+        set_context(accounts(0), 0, 5_000);
+        contract
+            .update_task(task_id, TaskStatus::Done, None)
+            .unwrap();
+        assert_eq!(transfers(), vec![(accounts(0), 2 * ONE_NEAR)]);
+        assert_eq!(contract.get_stake(task_id), None);
+        assert_eq!(
+            contract.slash_expired(task_id),
+            Err(ContractError::NoStake(task_id))
+        );
+    }
+
+    #[test]
+    fn missed_deadline_slashes_the_stake() {
+        let (mut contract, task_id) = setup();
+        set_context(accounts(2), 0, 5_000);
+        assert_eq!(
+            contract.slash_expired(task_id),
+            Err(ContractError::StakeNotExpired(task_id))
+        );
+
+        // Finishing late or cancelling doesn't give the stake back.
+        set_context(accounts(0), 0, 5_001);
+        contract
+            .update_task(task_id, TaskStatus::Done, None)
+            .unwrap();
+        assert!(transfers().is_empty());
+        set_context(accounts(2), 0, 5_001);
+        contract.slash_expired(task_id).unwrap();
+        assert_eq!(transfers(), vec![(accounts(3), 2 * ONE_NEAR)]);
+        assert!(contract
+            .get_staked_task_ids(accounts(0), None, None)
+            .is_empty());
+    }
+
+    #[test]
+    fn deleted_tasks_keep_their_stake_until_slashed() {
+        let (mut contract, task_id) = setup();
+        set_context(accounts(0), 0, 1_000);
+        contract.delete_task(task_id).unwrap();
+        assert!(transfers().is_empty());
+        assert!(contract.get_stake(task_id).is_some());
+
+        set_context(accounts(2), 0, 5_001);
+        contract.slash_expired(task_id).unwrap();
+        assert_eq!(transfers(), vec![(accounts(3), 2 * ONE_NEAR)]);
+        assert_eq!(contract.get_stake(task_id), None);
+        assert!(contract
+            .get_staked_task_ids(accounts(0), None, None)
+            .is_empty());
+        assert!(contract.stakes_by_account.is_empty(&accounts(0)));
+    }
+
+    #[test]
+    fn unregistering_forfeits_the_stakes() {
+        let (mut contract, task_id) = setup();
+        set_context(accounts(0), 1, 1_000);
+        assert!(contract.storage_unregister(Some(true)));
+        assert!(transfers().contains(&(accounts(3), 2 * ONE_NEAR)));
+        assert_eq!(contract.get_stake(task_id), None);
+    }
+
+    #[test]
+    fn staking_requires_a_beneficiary_and_a_deadline() {
+        set_context(accounts(0), ONE_NEAR / 100, 1_000);
+        let mut contract = Contract::default();
+        let task_id = contract.insert_task(String::from("task_a"));
+        set_context(accounts(0), ONE_NEAR, 1_000);
+        assert_eq!(
+            contract.stake_on_task(task_id, Some(U64(5_000))),
+            Err(ContractError::StakingNotConfigured)
+        );
+        contract.stake_beneficiary = Some(accounts(3));
+        assert_eq!(
+            contract.stake_on_task(task_id, None),
+            Err(ContractError::NoDeadline(task_id))
+        );
+        set_context(accounts(1), ONE_NEAR, 1_000);
+        assert!(matches!(
+            contract.stake_on_task(task_id, Some(U64(5_000))),
+            Err(ContractError::NotTaskOwner { .. })
+        ));
+    }
+}
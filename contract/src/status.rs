@@ -0,0 +1,111 @@
+use std::fmt;
+
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::serde::{Deserialize, Serialize};
+
+/// Lifecycle state of a task.
+///
+/// Serialized to JSON as upper snake case (`"TODO"`, `"IN_PROGRESS"`, ...), which keeps
+/// the `"TODO"`/`"DONE"` values the frontend already sends and displays.
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, BorshDeserialize, BorshSerialize, Serialize, Deserialize,
+)]
+#[serde(crate = "near_sdk::serde", rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum TaskStatus {
+    Todo,
+    InProgress,
+    Blocked,
+    Done,
+    Cancelled,
+}
+
+impl TaskStatus {
+    /// Returns whether a task in this status may be moved to `next`.
+    ///
+    /// Open tasks can move freely between each other, be finished or be cancelled.
+    /// A blocked task has to be unblocked before it can be finished, and finished or
+    /// cancelled tasks can only be reopened as `Todo`.
+    pub fn can_transition_to(self, next: TaskStatus) -> bool {
+        use TaskStatus::*;
+        matches!(
+            (self, next),
+            (Todo, InProgress | Blocked | Done | Cancelled)
+                | (InProgress, Todo | Blocked | Done | Cancelled)
+                | (Blocked, Todo | InProgress | Cancelled)
+                | (Done | Cancelled, Todo)
+        )
+    }
+
+    /// Parses a free-form status string written by earlier versions of the contract.
+    pub(crate) fn from_legacy(status: &str) -> Option<TaskStatus> {
+        let normalized = status.trim().to_ascii_uppercase().replace(['-', ' '], "_");
+        match normalized.as_str() {
+            "TODO" | "TO_DO" | "OPEN" => Some(TaskStatus::Todo),
+            "IN_PROGRESS" | "INPROGRESS" | "DOING" => Some(TaskStatus::InProgress),
+            "BLOCKED" => Some(TaskStatus::Blocked),
+            "DONE" | "COMPLETED" => Some(TaskStatus::Done),
+            "CANCELLED" | "CANCELED" => Some(TaskStatus::Cancelled),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for TaskStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            TaskStatus::Todo => "TODO",
+            TaskStatus::InProgress => "IN_PROGRESS",
+            TaskStatus::Blocked => "BLOCKED",
+            TaskStatus::Done => "DONE",
+            TaskStatus::Cancelled => "CANCELLED",
+        };
+        f.write_str(name)
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use super::*;
+    use near_sdk::serde_json;
+
+    #[test]
+    fn json_uses_upper_snake_case() {
+        assert_eq!(
+            serde_json::to_string(&TaskStatus::InProgress).unwrap(),
+            "\"IN_PROGRESS\""
+        );
+        assert_eq!(
+            serde_json::from_str::<TaskStatus>("\"DONE\"").unwrap(),
+            TaskStatus::Done
+        );
+        assert_eq!(TaskStatus::Cancelled.to_string(), "CANCELLED");
+    }
+
+    #[test]
+    fn transitions() {
+        use TaskStatus::*;
+        assert!(Todo.can_transition_to(InProgress));
+        assert!(InProgress.can_transition_to(Done));
+        assert!(Blocked.can_transition_to(InProgress));
+        assert!(Done.can_transition_to(Todo));
+        assert!(!Blocked.can_transition_to(Done));
+        assert!(!Done.can_transition_to(Cancelled));
+        assert!(!Cancelled.can_transition_to(InProgress));
+        assert!(!Todo.can_transition_to(Todo));
+    }
+
+    #[test]
+    fn parses_legacy_strings() {
+        assert_eq!(TaskStatus::from_legacy("TODO"), Some(TaskStatus::Todo));
+        assert_eq!(
+            TaskStatus::from_legacy("in progress"),
+            Some(TaskStatus::InProgress)
+        );
+        assert_eq!(TaskStatus::from_legacy("Done"), Some(TaskStatus::Done));
+        assert_eq!(
+            TaskStatus::from_legacy("canceled"),
+            Some(TaskStatus::Cancelled)
+        );
+        assert_eq!(TaskStatus::from_legacy("whatever"), None);
+    }
+}
@@ -0,0 +1,506 @@
+//! NEP-145 storage management: every account pays for the storage of its own tasks.
+//!
+//! Deposits are tracked per account together with the number of bytes the account's data
+//! occupies. Task mutations measure `env::storage_usage()` before and after and charge the
+//! difference against the deposit of the task's owner; released storage is credited back. This
+//! includes the task's entries in the list and assignment indexes, also when a collaborator or
+//! the assignee makes the change. Creating a list and changing its collaborators is charged to
+//! the owner of the list.
+
+use near_contract_standards::storage_management::{
+    StorageBalance, StorageBalanceBounds, StorageManagement,
+};
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::U128;
+use near_sdk::{
+    assert_one_yocto, env, log, near_bindgen, require, AccountId, Balance, Promise, StorageUsage,
+};
+
+use crate::*;
+
+/// Upper bound for the storage taken by a registration record: the key prefix, a
+/// maximum-length account id and the record itself, plus the per-entry trie overhead.
+const REGISTRATION_STORAGE: StorageUsage = 250;
+
+/// Storage deposit of a registered account.
+#[derive(Debug, Clone, BorshDeserialize, BorshSerialize)]
+pub struct StorageAccount {
+    /// Total yoctoNEAR deposited by the account.
+    pub deposit: Balance,
+    /// Bytes of contract storage the account's data currently occupies.
+    pub used_bytes: StorageUsage,
+}
+
+impl StorageAccount {
+    fn used_balance(&self) -> Balance {
+        Balance::from(self.used_bytes) * env::storage_byte_cost()
+    }
+
+    fn available(&self) -> Balance {
+        self.deposit.saturating_sub(self.used_balance())
+    }
+
+    fn to_storage_balance(&self) -> StorageBalance {
+        StorageBalance {
+            total: U128(self.deposit),
+            available: U128(self.available()),
+        }
+    }
+}
+
+fn min_storage_balance() -> Balance {
+    Balance::from(REGISTRATION_STORAGE) * env::storage_byte_cost()
+}
+
+#[near_bindgen]
+impl StorageManagement for Contract {
+    #[payable]
+    fn storage_deposit(
+        &mut self,
+        account_id: Option<AccountId>,
+        registration_only: Option<bool>,
+    ) -> StorageBalance {
+        let amount = env::attached_deposit();
+        let account_id = account_id.unwrap_or_else(env::predecessor_account_id);
+        let registration_only = registration_only.unwrap_or(false);
+        if self.storage_accounts.contains_key(&account_id) {
+            if registration_only {
+                log!(
+                    "The account {} is already registered, refunding the deposit",
+                    account_id
+                );
+                if amount > 0 {
+                    Promise::new(env::predecessor_account_id()).transfer(amount);
+                }
+            } else {
+                self.deposit_storage(&account_id, amount);
+            }
+        } else {
+            let min_balance = min_storage_balance();
+            require!(
+                amount >= min_balance,
+                "The attached deposit is less than the minimum storage balance"
+            );
+            self.register_storage_account(&account_id);
+            let deposit = if registration_only {
+                let refund = amount - min_balance;
+                if refund > 0 {
+                    Promise::new(env::predecessor_account_id()).transfer(refund);
+                }
+                min_balance
+            } else {
+                amount
+            };
+            self.deposit_storage(&account_id, deposit);
+        }
+        self.storage_accounts
+            .get(&account_id)
+            .unwrap()
+            .to_storage_balance()
+    }
+
+    #[payable]
+    fn storage_withdraw(&mut self, amount: Option<U128>) -> StorageBalance {
+        assert_one_yocto();
+        let account_id = env::predecessor_account_id();
+        let mut account = self
+            .storage_accounts
+            .get(&account_id)
+            .unwrap_or_else(|| panic!("The account {} is not registered", account_id));
+        let available = account.available();
+        let amount = amount.map_or(available, |amount| amount.0);
+        require!(
+            amount <= available,
+            "The amount is greater than the available storage balance"
+        );
+        if amount > 0 {
+            account.deposit -= amount;
+            self.storage_accounts.insert(&account_id, &account);
+            Promise::new(account_id).transfer(amount);
+        }
+        account.to_storage_balance()
+    }
+
+    // With `force`, all active and archived tasks of the account and its completion badges
+    // are removed first, and its stakes are forfeited to their beneficiaries.
+    // Lists owned by the account have to be deleted before. The account's own deposit is
+    // refunded in full; bytes freed in records paid for by other accounts, e.g. the blocker
+    // edges on their tasks, aren't credited to them and stay with the contract.
+    #[payable]
+    fn storage_unregister(&mut self, force: Option<bool>) -> bool {
+        assert_one_yocto();
+        let account_id = env::predecessor_account_id();
+        let account = match self.storage_accounts.get(&account_id) {
+            Some(account) => account,
+            None => {
+                log!("The account {} is not registered", account_id);
+                return false;
+            }
+        };
+        require!(
+            !self.owns_lists(&account_id),
+            "Can't unregister the account while it owns task lists, delete them first"
+        );
+        let has_tasks = !self.tasks_by_account.is_empty(&account_id)
+            || !self.archived_by_account.is_empty(&account_id)
+            || !self.badges_by_account.is_empty(&account_id)
+            || !self.stakes_by_account.is_empty(&account_id);
+        if has_tasks {
+            require!(
+                force.unwrap_or(false),
+                "Can't unregister the account with tasks without force"
+            );
+            self.remove_all_tasks_of(&account_id);
+        }
+        self.greetings.remove(&account_id);
+        self.bounty_tokens.remove(&account_id);
+        self.storage_accounts.remove(&account_id);
+        if account.deposit > 0 {
+            Promise::new(account_id.clone()).transfer(account.deposit);
+        }
+        log!("Unregistered account {}", account_id);
+        true
+    }
+
+    fn storage_balance_bounds(&self) -> StorageBalanceBounds {
+        StorageBalanceBounds {
+            min: U128(min_storage_balance()),
+            max: None,
+        }
+    }
+
+    fn storage_balance_of(&self, account_id: AccountId) -> Option<StorageBalance> {
+        self.storage_accounts
+            .get(&account_id)
+            .map(|account| account.to_storage_balance())
+    }
+}
+
+impl Contract {
+    /// Adds `amount` to the account's storage deposit, registering the account if needed.
+    pub(crate) fn deposit_storage(&mut self, account_id: &AccountId, amount: Balance) {
+        let mut account = match self.storage_accounts.get(account_id) {
+            Some(account) => account,
+            None => {
+                require!(
+                    amount >= min_storage_balance(),
+                    format!(
+                        "The account {} is not registered, attach at least {} yoctoNEAR",
+                        account_id,
+                        min_storage_balance()
+                    )
+                );
+                self.register_storage_account(account_id)
+            }
+        };
+        account.deposit += amount;
+        self.storage_accounts.insert(account_id, &account);
+    }
+
+    /// Charges the account for the storage used since `initial_storage_usage`, or credits it
+    /// with the storage that was released. Panics if the deposit doesn't cover the growth.
+    pub(crate) fn charge_storage(
+        &mut self,
+        account_id: &AccountId,
+        initial_storage_usage: StorageUsage,
+    ) {
+        let mut account = self.storage_accounts.get(account_id).unwrap_or_else(|| {
+            panic!(
+                "The account {} is not registered, call storage_deposit first",
+                account_id
+            )
+        });
+        let current_storage_usage = env::storage_usage();
+        if current_storage_usage >= initial_storage_usage {
+            account.used_bytes += current_storage_usage - initial_storage_usage;
+            let used_balance = account.used_balance();
+            require!(
+                account.deposit >= used_balance,
+                format!(
+                    "Not enough storage balance, {} yoctoNEAR more is required",
+                    used_balance.saturating_sub(account.deposit)
+                )
+            );
+        } else {
+            account.used_bytes = account
+                .used_bytes
+                .saturating_sub(initial_storage_usage - current_storage_usage);
+        }
+        self.storage_accounts.insert(account_id, &account);
+    }
+
+    /// Handles the deposit attached to a task mutation and charges the resulting storage to
+    /// the caller. `initial_storage_usage` has to be taken before the attached deposit is
+    /// processed.
+    pub(crate) fn settle_storage(&mut self, initial_storage_usage: StorageUsage) {
+        self.settle_storage_of(&env::predecessor_account_id(), initial_storage_usage);
+    }
+
+    /// Like `settle_storage`, but the attached deposit goes to and the storage is charged to
+    /// `account_id`, which pays for the changed task or list.
+    pub(crate) fn settle_storage_of(
+        &mut self,
+        account_id: &AccountId,
+        initial_storage_usage: StorageUsage,
+    ) {
+        let attached_deposit = env::attached_deposit();
+        let storage_usage_before_deposit = env::storage_usage();
+        if attached_deposit > 0 {
+            self.deposit_storage(account_id, attached_deposit);
+        }
+        // A registration made by the deposit is already accounted for in the new record.
+        let registration_storage = env::storage_usage() - storage_usage_before_deposit;
+        self.charge_storage(account_id, initial_storage_usage + registration_storage);
+    }
+
+    fn register_storage_account(&mut self, account_id: &AccountId) -> StorageAccount {
+        let initial_storage_usage = env::storage_usage();
+        let mut account = StorageAccount {
+            deposit: 0,
+            used_bytes: 0,
+        };
+        self.storage_accounts.insert(account_id, &account);
+        account.used_bytes = env::storage_usage() - initial_storage_usage;
+        self.storage_accounts.insert(account_id, &account);
+        log!("Registered account {}", account_id);
+        account
+    }
+
+    fn remove_all_tasks_of(&mut self, account_id: &AccountId) {
+        for task_id in self.tasks_by_account.remove_all(account_id) {
+            if let Some(task) = self.tasks.remove(&task_id) {
+                if let Some(list_id) = task.list_id {
+                    self.tasks_by_list.remove(&list_id, task_id);
+                }
+                if let Some(assignee) = &task.assignee {
+                    self.assigned_by_account
+                        .remove(&assignee.account_id, task_id);
+                }
+                self.detach_subtask(&task);
+                self.detach_dependencies(&task);
+                self.refund_bounty(&task);
+            }
+            self.remove_history(task_id);
+        }
+        for task_id in self.archived_by_account.remove_all(account_id) {
+            if let Some(task) = self.archived_tasks.remove(&task_id) {
+                if let Some(list_id) = task.list_id {
+                    self.archived_by_list.remove(&list_id, task_id);
+                }
+                self.detach_subtask(&task);
+                self.detach_dependencies(&task);
+                self.refund_bounty(&task);
+            }
+            self.remove_history(task_id);
+        }
+        self.tasks_by_due_date.remove_all(account_id);
+        self.slash_stakes_of(account_id);
+        self.burn_badges_of(account_id);
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use super::*;
+    use near_sdk::test_utils::{accounts, VMContextBuilder};
+    use near_sdk::{testing_env, ONE_NEAR};
+
+    fn context_with_deposit(deposit: Balance) -> VMContextBuilder {
+        let mut context = VMContextBuilder::new();
+        context
+            .current_account_id(accounts(0))
+            .predecessor_account_id(accounts(1))
+            .account_balance(10 * ONE_NEAR)
+            .attached_deposit(deposit);
+        context
+    }
+
+    #[test]
+    fn deposit_registers_and_tops_up() {
+        testing_env!(context_with_deposit(ONE_NEAR / 10).build());
+        let bob = accounts(1);
+        let mut contract = Contract::default();
+        assert!(contract.storage_balance_of(bob.clone()).is_none());
+
+        let balance = contract.storage_deposit(None, None);
+        assert_eq!(balance.total.0, ONE_NEAR / 10);
+        assert!(balance.available.0 < balance.total.0);
+
+        let balance = contract.storage_deposit(Some(bob.clone()), None);
+        assert_eq!(balance.total.0, 2 * ONE_NEAR / 10);
+        assert_eq!(
+            contract.storage_balance_of(bob).unwrap().total.0,
+            2 * ONE_NEAR / 10
+        );
+    }
+
+    #[test]
+    fn registration_only_keeps_the_minimum() {
+        testing_env!(context_with_deposit(ONE_NEAR).build());
+        let mut contract = Contract::default();
+        let balance = contract.storage_deposit(None, Some(true));
+        assert_eq!(balance.total.0, min_storage_balance());
+        assert_eq!(
+            contract.storage_balance_bounds().min.0,
+            min_storage_balance()
+        );
+
+        let balance = contract.storage_deposit(None, Some(true));
+        assert_eq!(balance.total.0, min_storage_balance());
+    }
+
+    #[test]
+    #[should_panic(expected = "less than the minimum storage balance")]
+    fn deposit_below_minimum_fails() {
+        testing_env!(context_with_deposit(1).build());
+        Contract::default().storage_deposit(None, None);
+    }
+
+    #[test]
+    fn tasks_are_charged_against_the_deposit() {
+        let mut context = context_with_deposit(ONE_NEAR / 10);
+        testing_env!(context.build());
+        let bob = accounts(1);
+        let mut contract = Contract::default();
+        contract.storage_deposit(None, None);
+        let available_before = contract
+            .storage_balance_of(bob.clone())
+            .unwrap()
+            .available
+            .0;
+
+        context.attached_deposit(0);
+        testing_env!(context.build());
+        let initial_storage_usage = env::storage_usage();
+        let task_id = contract.insert_task(String::from("task_a"));
+        let used = Balance::from(env::storage_usage() - initial_storage_usage);
+        let balance = contract.storage_balance_of(bob.clone()).unwrap();
+        assert_eq!(balance.total.0, ONE_NEAR / 10);
+        assert_eq!(
+            available_before - balance.available.0,
+            used * env::storage_byte_cost()
+        );
+
+        // Changes that don't grow the task cost nothing.
+        contract
+            .update_task(task_id, TaskStatus::InProgress, None)
+            .unwrap();
+        assert_eq!(
+            contract.storage_balance_of(bob).unwrap().available.0,
+            balance.available.0
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Not enough storage balance")]
+    fn insert_fails_without_enough_balance() {
+        let mut context = context_with_deposit(min_storage_balance());
+        testing_env!(context.build());
+        let mut contract = Contract::default();
+        contract.storage_deposit(None, Some(true));
+
+        context.attached_deposit(0);
+        testing_env!(context.build());
+        let _ = contract.create_task(NewTask {
+            description: Some("a very long task description ".repeat(20)),
+            ..NewTask::new(String::from("task_a"))
+        });
+    }
+
+    #[test]
+    fn withdraw_available_balance() {
+        let mut context = context_with_deposit(ONE_NEAR);
+        testing_env!(context.build());
+        let mut contract = Contract::default();
+        contract.storage_deposit(None, None);
+
+        context.attached_deposit(1);
+        testing_env!(context.build());
+        let balance = contract.storage_withdraw(Some(U128(ONE_NEAR / 2)));
+        assert_eq!(balance.total.0, ONE_NEAR / 2);
+        let balance = contract.storage_withdraw(None);
+        assert_eq!(balance.available.0, 0);
+        let used_balance = contract
+            .storage_accounts
+            .get(&accounts(1))
+            .unwrap()
+            .used_balance();
+        assert_eq!(balance.total.0, used_balance);
+    }
+
+    #[test]
+    #[should_panic(expected = "Can't unregister the account with tasks without force")]
+    fn unregister_with_tasks_requires_force() {
+        let mut context = context_with_deposit(ONE_NEAR / 10);
+        testing_env!(context.build());
+        let mut contract = Contract::default();
+        contract.insert_task(String::from("task_a"));
+
+        context.attached_deposit(1);
+        testing_env!(context.build());
+        contract.storage_unregister(None);
+    }
+
+    #[test]
+    fn force_unregister_removes_tasks() {
+        let mut context = context_with_deposit(ONE_NEAR / 10);
+        testing_env!(context.build());
+        let bob = accounts(1);
+        let mut contract = Contract::default();
+        let task_a = contract.insert_task(String::from("task_a"));
+        let task_b = contract.insert_task(String::from("task_b"));
+        contract.archive_task(task_b).unwrap();
+
+        context.attached_deposit(1);
+        testing_env!(context.build());
+        assert!(contract.storage_unregister(Some(true)));
+        assert!(contract.storage_balance_of(bob.clone()).is_none());
+        assert!(contract.get_task(task_a).is_none());
+        assert!(contract.get_tasks_for(bob.clone(), None, None).is_empty());
+        assert!(contract.get_archived_tasks_for(bob, None, None).is_empty());
+        assert!(!contract.storage_unregister(None));
+    }
+
+    #[test]
+    fn list_tasks_are_charged_to_their_owner() {
+        let mut context = context_with_deposit(ONE_NEAR / 10);
+        testing_env!(context.build());
+        let bob = accounts(1);
+        let mut contract = Contract::default();
+        let list_id = contract.create_list(String::from("team")).unwrap();
+        contract
+            .add_collaborator(list_id, accounts(2), Role::Editor)
+            .unwrap();
+        let task_id = contract
+            .create_task(NewTask {
+                list_id: Some(list_id),
+                ..NewTask::new(String::from("task_a"))
+            })
+            .unwrap();
+        let available_before = contract.storage_balance_of(bob.clone()).unwrap().available;
+
+        // The editor isn't registered and pays nothing for growing bob's task.
+        context
+            .predecessor_account_id(accounts(2))
+            .attached_deposit(0);
+        testing_env!(context.build());
+        contract
+            .rename_task(task_id, String::from("a longer task name"), None)
+            .unwrap();
+        assert!(contract.storage_balance_of(accounts(2)).is_none());
+        assert!(contract.storage_balance_of(bob).unwrap().available.0 < available_before.0);
+    }
+
+    #[test]
+    #[should_panic(expected = "Can't unregister the account while it owns task lists")]
+    fn unregister_requires_deleting_lists() {
+        let mut context = context_with_deposit(ONE_NEAR / 10);
+        testing_env!(context.build());
+        let mut contract = Contract::default();
+        contract.create_list(String::from("team")).unwrap();
+
+        context.attached_deposit(1);
+        testing_env!(context.build());
+        contract.storage_unregister(Some(true));
+    }
+}
@@ -0,0 +1,396 @@
+//! Subtasks and checklists.
+//!
+//! A task created with a `parent_id` becomes a subtask of that task, in the same list. Subtasks
+//! can have subtasks of their own, up to `MAX_SUBTASK_DEPTH` levels below a top-level task, and
+//! every task can carry a short checklist. A task with open subtasks or unchecked items can only
+//! be moved to `DONE` with `complete_task(task_id, true)`; `get_task_progress` derives how far
+//! along it is from its subtasks and checklist.
+
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::{env, near_bindgen};
+
+use crate::details::TaskChanges;
+use crate::*;
+
+/// Levels of subtasks below a top-level task, bounds the parent chain walked when nesting.
+pub const MAX_SUBTASK_DEPTH: usize = 3;
+/// Maximum number of direct subtasks of a task.
+pub const MAX_SUBTASKS: usize = 50;
+/// Maximum number of checklist items of a task.
+pub const MAX_CHECKLIST_ITEMS: usize = 50;
+/// Maximum length of a checklist item, in characters.
+pub const MAX_CHECKLIST_ITEM_LENGTH: usize = 200;
+
+#[derive(Debug, Clone, PartialEq, BorshDeserialize, BorshSerialize, Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct ChecklistItem {
+    pub text: String,
+    pub done: bool,
+}
+
+/// How far along a task is. Subtasks count as done once `DONE`, cancelled ones don't count.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct TaskProgress {
+    /// Finished subtasks and checked items.
+    pub done: u32,
+    /// Subtasks and checklist items that count.
+    pub total: u32,
+    /// `done` out of `total` in whole percent, for a task without either 100 once it is done.
+    pub percent: u8,
+}
+
+impl Task {
+    pub(crate) fn is_open(&self) -> bool {
+        !matches!(self.task_status, TaskStatus::Done | TaskStatus::Cancelled)
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    // Public method - returns the active and archived subtasks of a task
+    pub fn get_subtasks(&self, task_id: TaskId) -> Vec<Task> {
+        self.any_task(task_id)
+            .map(|task| self.subtasks_of(&task))
+            .unwrap_or_default()
+    }
+
+    // Public method - returns the progress of a task derived from its subtasks and checklist
+    pub fn get_task_progress(&self, task_id: TaskId) -> Option<TaskProgress> {
+        let task = self.any_task(task_id)?;
+        let subtasks: Vec<Task> = self
+            .subtasks_of(&task)
+            .into_iter()
+            .filter(|subtask| subtask.task_status != TaskStatus::Cancelled)
+            .collect();
+        let done = subtasks
+            .iter()
+            .filter(|subtask| subtask.task_status == TaskStatus::Done)
+            .count()
+            + task.checklist.iter().filter(|item| item.done).count();
+        let total = subtasks.len() + task.checklist.len();
+        let percent = match total {
+            0 if task.task_status == TaskStatus::Done => 100,
+            0 => 0,
+            _ => done * 100 / total,
+        };
+        Some(TaskProgress {
+            done: done as u32,
+            total: total as u32,
+            percent: percent as u8,
+        })
+    }
+
+    // Public method - move a task to `DONE`
+    // Fails while subtasks or checklist items are open, unless `force` is set.
+    // Storage is settled like in `update_task`.
+    #[payable]
+    #[handle_result]
+    pub fn complete_task(&mut self, task_id: TaskId, force: bool) -> Result<(), ContractError> {
+        self.change_task(
+            task_id,
+            TaskPatch {
+                task_status: Some(TaskStatus::Done),
+                ..Default::default()
+            },
+            force,
+        )
+    }
+
+    // Public method - append an item to the checklist of a task
+    // Storage is charged against the task owner's storage balance, like in `rename_task`.
+    #[payable]
+    #[handle_result]
+    pub fn add_checklist_item(
+        &mut self,
+        task_id: TaskId,
+        text: String,
+    ) -> Result<(), ContractError> {
+        let text = validate_checklist_item(text)?;
+        let task = self.editable_task(task_id)?;
+        if task.checklist.len() >= MAX_CHECKLIST_ITEMS {
+            return Err(ContractError::TooManyChecklistItems {
+                task_id,
+                max_count: MAX_CHECKLIST_ITEMS,
+            });
+        }
+        self.change_checklist(task, |checklist| {
+            checklist.push(ChecklistItem { text, done: false });
+        });
+        Ok(())
+    }
+
+    // Public method - check or uncheck a checklist item, by its index
+    // Accepted assignees may do this as well.
+    #[payable]
+    #[handle_result]
+    pub fn check_checklist_item(
+        &mut self,
+        task_id: TaskId,
+        index: u32,
+        done: bool,
+    ) -> Result<(), ContractError> {
+        let task = self
+            .tasks
+            .get(&task_id)
+            .ok_or(ContractError::TaskNotFound(task_id))?;
+        if !task.is_accepted_by(&env::predecessor_account_id()) {
+            self.ensure_access(&task, Role::Editor)?;
+        }
+        if task.checklist.get(index as usize).is_none() {
+            return Err(ContractError::ChecklistItemNotFound { task_id, index });
+        }
+        self.change_checklist(task, |checklist| checklist[index as usize].done = done);
+        Ok(())
+    }
+
+    // Public method - remove a checklist item, the following items move up by one
+    #[payable]
+    #[handle_result]
+    pub fn remove_checklist_item(
+        &mut self,
+        task_id: TaskId,
+        index: u32,
+    ) -> Result<(), ContractError> {
+        let task = self.editable_task(task_id)?;
+        if task.checklist.get(index as usize).is_none() {
+            return Err(ContractError::ChecklistItemNotFound { task_id, index });
+        }
+        self.change_checklist(task, |checklist| {
+            checklist.remove(index as usize);
+        });
+        Ok(())
+    }
+}
+
+impl Contract {
+    fn subtasks_of(&self, task: &Task) -> Vec<Task> {
+        task.subtask_ids
+            .iter()
+            .filter_map(|&subtask_id| self.any_task(subtask_id))
+            .collect()
+    }
+
+    /// Loads the task a new subtask is added to and checks the limits.
+    pub(crate) fn subtask_parent(&self, parent_id: TaskId) -> Result<Task, ContractError> {
+        let parent = self.editable_task(parent_id)?;
+        if !parent.is_open() {
+            return Err(ContractError::TaskClosed(parent_id));
+        }
+        if parent.subtask_ids.len() >= MAX_SUBTASKS {
+            return Err(ContractError::TooManySubtasks {
+                task_id: parent_id,
+                max_count: MAX_SUBTASKS,
+            });
+        }
+        // The depth of the parent is the number of its ancestors.
+        let mut depth = 0;
+        let mut ancestor_id = parent.parent_id;
+        while let Some(task_id) = ancestor_id {
+            depth += 1;
+            if depth >= MAX_SUBTASK_DEPTH {
+                return Err(ContractError::SubtaskTooDeep {
+                    task_id: parent_id,
+                    max_depth: MAX_SUBTASK_DEPTH,
+                });
+            }
+            ancestor_id = self.any_task(task_id).and_then(|task| task.parent_id);
+        }
+        Ok(parent)
+    }
+
+    /// Fails if subtasks or checklist items of the task are still open.
+    pub(crate) fn ensure_subtasks_done(&self, task: &Task) -> Result<(), ContractError> {
+        let open_count = self
+            .subtasks_of(task)
+            .iter()
+            .filter(|subtask| subtask.is_open())
+            .count()
+            + task.checklist.iter().filter(|item| !item.done).count();
+        if open_count > 0 {
+            return Err(ContractError::OpenSubtasks {
+                task_id: task.id,
+                open_count,
+            });
+        }
+        Ok(())
+    }
+
+    /// Removes a deleted task from the subtasks of its parent, if the parent still exists.
+    pub(crate) fn detach_subtask(&mut self, task: &Task) {
+        if let Some(parent_id) = task.parent_id {
+            self.update_any_task(parent_id, |parent| {
+                parent
+                    .subtask_ids
+                    .retain(|&subtask_id| subtask_id != task.id);
+                parent.touch();
+            });
+        }
+    }
+
+    /// Applies `change` to the checklist of the task, records it and settles the storage.
+    fn change_checklist(&mut self, mut task: Task, change: impl FnOnce(&mut Vec<ChecklistItem>)) {
+        let initial_storage_usage = env::storage_usage();
+        let old_checklist = task.checklist.clone();
+        change(&mut task.checklist);
+        let mut changes = TaskChanges::default();
+        changes.record(&task, "checklist", old_checklist, &task.checklist);
+        task.touch();
+        self.tasks.insert(&task.id, &task);
+        self.record_history(&task, std::mem::take(&mut changes.history));
+        changes.emit();
+        self.settle_storage_of(&task.owner_id, initial_storage_usage);
+    }
+}
+
+fn validate_checklist_item(text: String) -> Result<String, ContractError> {
+    let text = text.trim();
+    if text.is_empty() {
+        return Err(ContractError::EmptyChecklistItem);
+    }
+    let length = text.chars().count();
+    if length > MAX_CHECKLIST_ITEM_LENGTH {
+        return Err(ContractError::ChecklistItemTooLong {
+            length,
+            max_length: MAX_CHECKLIST_ITEM_LENGTH,
+        });
+    }
+    Ok(text.to_owned())
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use super::*;
+    use near_sdk::test_utils::{accounts, VMContextBuilder};
+    use near_sdk::{testing_env, ONE_NEAR};
+
+    fn set_predecessor(account_id: AccountId) {
+        let mut context = VMContextBuilder::new();
+        context
+            .predecessor_account_id(account_id)
+            .account_balance(10 * ONE_NEAR)
+            .attached_deposit(ONE_NEAR / 100);
+        testing_env!(context.build());
+    }
+
+    fn create_subtask(contract: &mut Contract, parent_id: TaskId) -> Result<TaskId, ContractError> {
+        contract.create_task(NewTask {
+            parent_id: Some(parent_id),
+            ..NewTask::new(format!("subtask_of_{}", parent_id))
+        })
+    }
+
+    #[test]
+    fn parents_wait_for_their_subtasks() {
+        set_predecessor(accounts(0));
+        let mut contract = Contract::default();
+        let parent_id = contract.insert_task(String::from("parent"));
+        let first_id = create_subtask(&mut contract, parent_id).unwrap();
+        let second_id = create_subtask(&mut contract, parent_id).unwrap();
+        assert_eq!(
+            contract.get_task(first_id).unwrap().parent_id,
+            Some(parent_id)
+        );
+        assert_eq!(contract.get_subtasks(parent_id).len(), 2);
+        assert_eq!(contract.get_task(parent_id).unwrap().version, 2);
+
+        assert_eq!(
+            contract.update_task(parent_id, TaskStatus::Done, None),
+            Err(ContractError::OpenSubtasks {
+                task_id: parent_id,
+                open_count: 2
+            })
+        );
+        contract
+            .update_task(first_id, TaskStatus::Done, None)
+            .unwrap();
+        contract
+            .update_task(second_id, TaskStatus::Cancelled, None)
+            .unwrap();
+        assert_eq!(
+            contract.get_task_progress(parent_id),
+            Some(TaskProgress {
+                done: 1,
+                total: 1,
+                percent: 100
+            })
+        );
+        contract.complete_task(parent_id, false).unwrap();
+    }
+
+    #[test]
+    fn forcing_completes_with_open_items() {
+        set_predecessor(accounts(0));
+        let mut contract = Contract::default();
+        let task_id = contract.insert_task(String::from("task_a"));
+        for text in ["one", "two", "three"] {
+            contract
+                .add_checklist_item(task_id, String::from(text))
+                .unwrap();
+        }
+        contract.check_checklist_item(task_id, 0, true).unwrap();
+        contract.remove_checklist_item(task_id, 2).unwrap();
+        assert_eq!(
+            contract.check_checklist_item(task_id, 2, true),
+            Err(ContractError::ChecklistItemNotFound { task_id, index: 2 })
+        );
+        assert_eq!(
+            contract.get_task_progress(task_id),
+            Some(TaskProgress {
+                done: 1,
+                total: 2,
+                percent: 50
+            })
+        );
+
+        assert!(contract.complete_task(task_id, false).is_err());
+        contract.complete_task(task_id, true).unwrap();
+        assert_eq!(
+            contract.get_task(task_id).unwrap().task_status,
+            TaskStatus::Done
+        );
+    }
+
+    #[test]
+    fn nesting_is_limited() {
+        set_predecessor(accounts(0));
+        let mut contract = Contract::default();
+        let mut task_id = contract.insert_task(String::from("root"));
+        for _ in 0..MAX_SUBTASK_DEPTH {
+            task_id = create_subtask(&mut contract, task_id).unwrap();
+        }
+        assert_eq!(
+            create_subtask(&mut contract, task_id),
+            Err(ContractError::SubtaskTooDeep {
+                task_id,
+                max_depth: MAX_SUBTASK_DEPTH
+            })
+        );
+
+        set_predecessor(accounts(1));
+        assert!(matches!(
+            create_subtask(&mut contract, task_id),
+            Err(ContractError::NotTaskOwner { .. })
+        ));
+    }
+
+    #[test]
+    fn deleting_a_subtask_detaches_it() {
+        set_predecessor(accounts(0));
+        let mut contract = Contract::default();
+        let parent_id = contract.insert_task(String::from("parent"));
+        let subtask_id = create_subtask(&mut contract, parent_id).unwrap();
+        assert_eq!(
+            contract.delete_task(parent_id),
+            Err(ContractError::HasSubtasks(parent_id))
+        );
+
+        contract.archive_task(parent_id).unwrap();
+        assert_eq!(contract.any_task(parent_id).unwrap().version, 2);
+        contract.delete_task(subtask_id).unwrap();
+        assert!(contract.get_subtasks(parent_id).is_empty());
+        assert_eq!(contract.any_task(parent_id).unwrap().version, 3);
+        contract.delete_task(parent_id).unwrap();
+    }
+}
@@ -0,0 +1,510 @@
+//! NEP-141 fungible token bounties.
+//!
+//! Besides NEAR, a task can be funded with a NEP-141 token: its owner calls `ft_transfer_call`
+//! on the token contract with this contract as receiver and a message naming the task, like
+//! `{"task_id": 3}`, optionally with `expires_at`. Any contract can call `ft_on_transfer` and
+//! name any sender, so only tokens the owner allowed with `allow_bounty_token` are accepted; the
+//! storage of their bounties is charged to the owner. Every token is escrowed in a single entry
+//! per task and is paid out, refunded and expires together with the NEAR bounty, see `bounty`.
+//! Payouts use
+//! `ft_transfer`; a transfer that fails, e.g. because the receiver isn't registered with the
+//! token, is kept as unclaimed and can be withdrawn with `withdraw_unclaimed_token_bounty`.
+
+use near_contract_standards::fungible_token::core::ext_ft_core;
+use near_contract_standards::fungible_token::receiver::FungibleTokenReceiver;
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::{U128, U64};
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::serde_json;
+use near_sdk::{env, near_bindgen, AccountId, Balance, Gas, Promise, PromiseError, PromiseOrValue};
+
+use crate::details::validate_due_date;
+use crate::events::BountyData;
+use crate::*;
+
+/// Gas attached to `ft_transfer` calls paying out a bounty.
+const GAS_FOR_FT_TRANSFER: Gas = Gas(10 * Gas::ONE_TERA.0);
+/// Gas attached to the callback resolving a token bounty transfer.
+const GAS_FOR_RESOLVE_TOKEN_BOUNTY: Gas = Gas(10 * Gas::ONE_TERA.0);
+
+/// Maximum number of tokens an account allows as bounties, and of token bounties on a task.
+pub const MAX_BOUNTY_TOKENS: usize = 10;
+
+#[derive(Debug, Clone, PartialEq, BorshDeserialize, BorshSerialize, Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct TokenBounty {
+    /// NEP-141 contract of the escrowed token.
+    pub token_id: AccountId,
+    /// Account that funded the bounty and gets it back on a refund.
+    pub funder_id: AccountId,
+    /// Escrowed amount in the smallest unit of the token.
+    pub amount: U128,
+    /// Block timestamp in nanoseconds after which the bounty can be refunded.
+    pub expires_at: Option<U64>,
+}
+
+/// `msg` of the `ft_transfer_call` funding a task.
+#[derive(Deserialize)]
+#[serde(crate = "near_sdk::serde", deny_unknown_fields)]
+struct FundTaskMessage {
+    task_id: TaskId,
+    #[serde(default)]
+    expires_at: Option<U64>,
+}
+
+#[near_bindgen]
+impl FungibleTokenReceiver for Contract {
+    // Public method - escrow the transferred tokens as bounty of the task named in `msg`
+    // Fails on an invalid message or task, which makes the token contract refund the transfer.
+    fn ft_on_transfer(
+        &mut self,
+        sender_id: AccountId,
+        amount: U128,
+        msg: String,
+    ) -> PromiseOrValue<U128> {
+        self.fund_task_with_token(env::predecessor_account_id(), sender_id, amount, &msg)
+            .unwrap_or_else(|err| panic!("{}", err));
+        PromiseOrValue::Value(U128(0))
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    // Public method - returns the token bounties escrowed on a task
+    pub fn get_token_bounties(&self, task_id: TaskId) -> Vec<TokenBounty> {
+        self.token_bounties.get(&task_id).unwrap_or_default()
+    }
+
+    // Public method - returns the tokens the given account accepts as bounties on its tasks
+    pub fn get_bounty_tokens(&self, account_id: AccountId) -> Vec<AccountId> {
+        self.bounty_tokens.get(&account_id).unwrap_or_default()
+    }
+
+    // Public method - accept transfers of the given token as bounties on the caller's tasks
+    // Storage is handled like in `insert_task`.
+    #[payable]
+    #[handle_result]
+    pub fn allow_bounty_token(&mut self, token_id: AccountId) -> Result<(), ContractError> {
+        let account_id = env::predecessor_account_id();
+        let initial_storage_usage = env::storage_usage();
+        let mut tokens = self.bounty_tokens.get(&account_id).unwrap_or_default();
+        if !tokens.contains(&token_id) {
+            if tokens.len() >= MAX_BOUNTY_TOKENS {
+                return Err(ContractError::TooManyBountyTokens {
+                    count: tokens.len() + 1,
+                    max_count: MAX_BOUNTY_TOKENS,
+                });
+            }
+            tokens.push(token_id);
+            self.bounty_tokens.insert(&account_id, &tokens);
+        }
+        self.settle_storage(initial_storage_usage);
+        Ok(())
+    }
+
+    // Public method - stop accepting the given token as bounty, bounties already escrowed in it
+    // stay on their tasks
+    pub fn disallow_bounty_token(&mut self, token_id: AccountId) {
+        let account_id = env::predecessor_account_id();
+        let initial_storage_usage = env::storage_usage();
+        let mut tokens = self.bounty_tokens.get(&account_id).unwrap_or_default();
+        if !tokens.contains(&token_id) {
+            return;
+        }
+        tokens.retain(|token| *token != token_id);
+        if tokens.is_empty() {
+            self.bounty_tokens.remove(&account_id);
+        } else {
+            self.bounty_tokens.insert(&account_id, &tokens);
+        }
+        self.charge_storage(&account_id, initial_storage_usage);
+    }
+
+    // Public method - returns the bounties in the given token that couldn't be transferred to
+    // the given account
+    pub fn get_unclaimed_token_bounty(&self, account_id: AccountId, token_id: AccountId) -> U128 {
+        U128(
+            self.unclaimed_token_bounties
+                .get(&(account_id, token_id))
+                .unwrap_or(0),
+        )
+    }
+
+    // Public method - retry the transfer of the caller's unclaimed bounties in the given token
+    // Resolves to `false` if the transfer failed again, the bounties stay unclaimed then.
+    #[handle_result]
+    pub fn withdraw_unclaimed_token_bounty(
+        &mut self,
+        token_id: AccountId,
+    ) -> Result<Promise, ContractError> {
+        let account_id = env::predecessor_account_id();
+        let amount = self
+            .unclaimed_token_bounties
+            .remove(&(account_id.clone(), token_id.clone()))
+            .ok_or_else(|| ContractError::NoUnclaimedBounty(account_id.clone()))?;
+        Ok(Self::transfer_token_bounty(
+            None, token_id, account_id, amount,
+        ))
+    }
+
+    // Private method - keeps the token bounty as unclaimed if its transfer failed
+    // The receiver may not be registered, so the contract pays for this record itself.
+    #[private]
+    pub fn resolve_token_bounty(
+        &mut self,
+        task_id: Option<TaskId>,
+        token_id: AccountId,
+        account_id: AccountId,
+        amount: U128,
+        #[callback_result] result: Result<(), PromiseError>,
+    ) -> bool {
+        if result.is_ok() {
+            return true;
+        }
+        let key = (account_id.clone(), token_id.clone());
+        let unclaimed = self.unclaimed_token_bounties.get(&key).unwrap_or(0);
+        self.unclaimed_token_bounties
+            .insert(&key, &(unclaimed + amount.0));
+        TaskEvent::BountyUnclaimed(vec![BountyData {
+            task_id,
+            account_id,
+            amount,
+            token_id: Some(token_id),
+        }])
+        .emit();
+        false
+    }
+}
+
+impl Contract {
+    /// Escrows `amount` of `token_id` sent by `sender_id` on the task named in `msg`. The
+    /// sender has to own the task and allow the token, the storage of the bounty is charged
+    /// against its storage balance.
+    fn fund_task_with_token(
+        &mut self,
+        token_id: AccountId,
+        sender_id: AccountId,
+        amount: U128,
+        msg: &str,
+    ) -> Result<(), ContractError> {
+        let message: FundTaskMessage = serde_json::from_str(msg)
+            .map_err(|err| ContractError::InvalidBountyMessage(err.to_string()))?;
+        if amount.0 == 0 {
+            return Err(ContractError::EmptyBounty);
+        }
+        let task = self.fundable_task(message.task_id, &sender_id)?;
+        if !self
+            .get_bounty_tokens(sender_id.clone())
+            .contains(&token_id)
+        {
+            return Err(ContractError::TokenNotAllowed {
+                account_id: sender_id,
+                token_id,
+            });
+        }
+        let expires_at = validate_due_date(message.expires_at)?;
+        let initial_storage_usage = env::storage_usage();
+        let mut bounties = self.token_bounties.get(&task.id).unwrap_or_default();
+        match bounties
+            .iter_mut()
+            .find(|bounty| bounty.token_id == token_id)
+        {
+            Some(bounty) => {
+                bounty.amount = U128(bounty.amount.0 + amount.0);
+                bounty.expires_at = expires_at.or(bounty.expires_at);
+            }
+            None => {
+                let count = bounties.len() + 1;
+                if count > MAX_BOUNTY_TOKENS {
+                    return Err(ContractError::TooManyBountyTokens {
+                        count,
+                        max_count: MAX_BOUNTY_TOKENS,
+                    });
+                }
+                bounties.push(TokenBounty {
+                    token_id: token_id.clone(),
+                    funder_id: sender_id.clone(),
+                    amount,
+                    expires_at: expires_at.or(task.due_date),
+                })
+            }
+        }
+        self.token_bounties.insert(&task.id, &bounties);
+        TaskEvent::BountyFunded(vec![BountyData {
+            task_id: Some(task.id),
+            account_id: sender_id.clone(),
+            amount,
+            token_id: Some(token_id),
+        }])
+        .emit();
+        self.charge_storage(&sender_id, initial_storage_usage);
+        Ok(())
+    }
+
+    /// Takes the token bounties of a task matching `filter` out of escrow and transfers them to
+    /// `receiver_id`, or back to their funders if it is `None`. Returns whether any was paid.
+    pub(crate) fn pay_token_bounties(
+        &mut self,
+        task_id: TaskId,
+        receiver_id: Option<&AccountId>,
+        filter: impl Fn(&TokenBounty) -> bool,
+    ) -> bool {
+        let bounties = match self.token_bounties.get(&task_id) {
+            Some(bounties) => bounties,
+            None => return false,
+        };
+        let (paid, kept): (Vec<_>, Vec<_>) =
+            bounties.into_iter().partition(|bounty| filter(bounty));
+        if kept.is_empty() {
+            self.token_bounties.remove(&task_id);
+        } else {
+            self.token_bounties.insert(&task_id, &kept);
+        }
+        for bounty in &paid {
+            let receiver_id = receiver_id.unwrap_or(&bounty.funder_id).clone();
+            let data = vec![BountyData {
+                task_id: Some(task_id),
+                account_id: receiver_id.clone(),
+                amount: bounty.amount,
+                token_id: Some(bounty.token_id.clone()),
+            }];
+            if receiver_id == bounty.funder_id {
+                TaskEvent::BountyRefunded(data).emit();
+            } else {
+                TaskEvent::BountyReleased(data).emit();
+            }
+            Self::transfer_token_bounty(
+                Some(task_id),
+                bounty.token_id.clone(),
+                receiver_id,
+                bounty.amount.0,
+            );
+        }
+        !paid.is_empty()
+    }
+
+    fn transfer_token_bounty(
+        task_id: Option<TaskId>,
+        token_id: AccountId,
+        account_id: AccountId,
+        amount: Balance,
+    ) -> Promise {
+        let memo = task_id.map(|task_id| format!("Bounty of task {}", task_id));
+        ext_ft_core::ext(token_id.clone())
+            .with_attached_deposit(1)
+            .with_static_gas(GAS_FOR_FT_TRANSFER)
+            .ft_transfer(account_id.clone(), U128(amount), memo)
+            .then(
+                Self::ext(env::current_account_id())
+                    .with_static_gas(GAS_FOR_RESOLVE_TOKEN_BOUNTY)
+                    .resolve_token_bounty(task_id, token_id, account_id, U128(amount)),
+            )
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use super::*;
+    use near_sdk::mock::VmAction;
+    use near_sdk::serde_json::{json, Value};
+    use near_sdk::test_utils::{accounts, get_created_receipts, VMContextBuilder};
+    use near_sdk::{testing_env, ONE_NEAR};
+
+    fn set_context(account_id: AccountId, block_timestamp: u64) {
+        let mut context = VMContextBuilder::new();
+        context
+            .current_account_id(accounts(5))
+            .predecessor_account_id(account_id)
+            .account_balance(10 * ONE_NEAR)
+            .attached_deposit(ONE_NEAR / 100)
+            .block_timestamp(block_timestamp);
+        testing_env!(context.build());
+    }
+
+    // `ft_transfer` calls made by the last call: token, receiver and amount
+    fn ft_transfers() -> Vec<(AccountId, Value)> {
+        get_created_receipts()
+            .into_iter()
+            .flat_map(|receipt| {
+                let receiver_id = receipt.receiver_id;
+                receipt
+                    .actions
+                    .into_iter()
+                    .filter_map(move |action| match action {
+                        VmAction::FunctionCall {
+                            function_name,
+                            args,
+                            deposit,
+                            ..
+                        } if function_name == "ft_transfer" => {
+                            assert_eq!(deposit, 1);
+                            let args: Value = serde_json::from_slice(&args).unwrap();
+                            Some((
+                                receiver_id.clone(),
+                                json!([args["receiver_id"], args["amount"]]),
+                            ))
+                        }
+                        _ => None,
+                    })
+            })
+            .collect()
+    }
+
+    // alice owns a task accepted by bob and allows the tokens accounts(3) and accounts(4)
+    fn setup() -> (Contract, TaskId) {
+        set_context(accounts(0), 1_000);
+        let mut contract = Contract::default();
+        let task_id = contract.insert_task(String::from("task_a"));
+        contract.allow_bounty_token(accounts(3)).unwrap();
+        contract.allow_bounty_token(accounts(4)).unwrap();
+        contract.assign_task(task_id, accounts(1)).unwrap();
+        set_context(accounts(1), 1_000);
+        contract.accept_task(task_id).unwrap();
+
+        set_context(accounts(3), 1_000);
+        let msg = json!({ "task_id": task_id, "expires_at": "2000" }).to_string();
+        contract.ft_on_transfer(accounts(0), U128(100), msg);
+        (contract, task_id)
+    }
+
+    #[test]
+    fn owner_finishing_pays_tokens_to_the_assignee() {
+        let (mut contract, task_id) = setup();
+        let msg = json!({ "task_id": task_id }).to_string();
+        contract.ft_on_transfer(accounts(0), U128(50), msg);
+        assert_eq!(
+            contract.get_token_bounties(task_id),
+            vec![TokenBounty {
+                token_id: accounts(3),
+                funder_id: accounts(0),
+                amount: U128(150),
+                expires_at: Some(U64(2_000)),
+            }]
+        );
+
+        set_context(accounts(0), 1_000);
+        contract
+            .update_task(task_id, TaskStatus::Done, None)
+            .unwrap();
+        assert_eq!(
+            ft_transfers(),
+            vec![(accounts(3), json!([accounts(1), "150"]))]
+        );
+        assert!(contract.get_token_bounties(task_id).is_empty());
+    }
+
+    #[test]
+    fn only_the_owner_funds_open_tasks() {
+        let (mut contract, task_id) = setup();
+        let msg = json!({ "task_id": task_id }).to_string();
+        assert_eq!(
+            contract.fund_task_with_token(accounts(3), accounts(1), U128(10), &msg),
+            Err(ContractError::NotTaskOwner {
+                task_id,
+                account_id: accounts(1)
+            })
+        );
+        assert!(matches!(
+            contract.fund_task_with_token(accounts(3), accounts(0), U128(10), "{}"),
+            Err(ContractError::InvalidBountyMessage(_))
+        ));
+
+        set_context(accounts(0), 1_000);
+        contract
+            .update_task(task_id, TaskStatus::Cancelled, None)
+            .unwrap();
+        assert_eq!(
+            ft_transfers(),
+            vec![(accounts(3), json!([accounts(0), "100"]))]
+        );
+        assert_eq!(
+            contract.fund_task_with_token(accounts(3), accounts(0), U128(10), &msg),
+            Err(ContractError::TaskClosed(task_id))
+        );
+    }
+
+    #[test]
+    fn rogue_tokens_cannot_fund_tasks_of_others() {
+        let (mut contract, task_id) = setup();
+        let storage_balance = contract.storage_accounts.get(&accounts(0)).unwrap();
+        // accounts(2) isn't a token alice allowed, but claims she sent the tokens.
+        set_context(accounts(2), 1_000);
+        let msg = json!({ "task_id": task_id }).to_string();
+        assert_eq!(
+            contract.fund_task_with_token(accounts(2), accounts(0), U128(1), &msg),
+            Err(ContractError::TokenNotAllowed {
+                account_id: accounts(0),
+                token_id: accounts(2)
+            })
+        );
+        assert_eq!(contract.get_token_bounties(task_id).len(), 1);
+        assert_eq!(
+            contract
+                .storage_accounts
+                .get(&accounts(0))
+                .unwrap()
+                .used_bytes,
+            storage_balance.used_bytes
+        );
+
+        // Once disallowed, a token can't add to its bounty either.
+        set_context(accounts(0), 1_000);
+        contract.disallow_bounty_token(accounts(3));
+        assert_eq!(contract.get_bounty_tokens(accounts(0)), [accounts(4)]);
+        assert!(matches!(
+            contract.fund_task_with_token(accounts(3), accounts(0), U128(1), &msg),
+            Err(ContractError::TokenNotAllowed { .. })
+        ));
+    }
+
+    #[test]
+    fn expired_token_bounties_are_refunded() {
+        let (mut contract, task_id) = setup();
+        // A second token without expiry stays in escrow.
+        set_context(accounts(4), 1_000);
+        let msg = json!({ "task_id": task_id }).to_string();
+        contract.ft_on_transfer(accounts(0), U128(7), msg);
+
+        set_context(accounts(2), 2_000);
+        contract.refund_expired_bounty(task_id).unwrap();
+        assert_eq!(
+            ft_transfers(),
+            vec![(accounts(3), json!([accounts(0), "100"]))]
+        );
+        assert_eq!(contract.get_token_bounties(task_id).len(), 1);
+        assert_eq!(
+            contract.refund_expired_bounty(task_id),
+            Err(ContractError::BountyNotExpired(task_id))
+        );
+    }
+
+    #[test]
+    fn failed_token_transfers_stay_claimable() {
+        let (mut contract, _) = setup();
+        set_context(accounts(5), 1_000);
+        assert!(!contract.resolve_token_bounty(
+            Some(0),
+            accounts(3),
+            accounts(1),
+            U128(100),
+            Err(PromiseError::Failed)
+        ));
+        assert_eq!(
+            contract.get_unclaimed_token_bounty(accounts(1), accounts(3)),
+            U128(100)
+        );
+
+        set_context(accounts(1), 1_000);
+        contract
+            .withdraw_unclaimed_token_bounty(accounts(3))
+            .unwrap();
+        assert_eq!(
+            ft_transfers(),
+            vec![(accounts(3), json!([accounts(1), "100"]))]
+        );
+        assert_eq!(
+            contract.withdraw_unclaimed_token_bounty(accounts(3)).err(),
+            Some(ContractError::NoUnclaimedBounty(accounts(1)))
+        );
+    }
+}
@@ -0,0 +1,1129 @@
+//! Versioning of the contract state and of the stored task records.
+//!
+//! Every layout the contract state has been deployed with is listed in `VersionedState`, and
+//! `migrate` turns whichever of them is stored into the current `Contract`. Task records are
+//! stored as `VersionedTask` and upgraded to the current `Task` when they are read, so changing
+//! `Task` doesn't require rewriting every task during the upgrade.
+
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::collections::LookupMap;
+use near_sdk::json_types::U64;
+use near_sdk::{env, log, near_bindgen, AccountId};
+
+use crate::legacy::LegacyContract;
+use crate::*;
+
+/// Version of the current state layout, stored in `Contract::state_version`, which has to stay
+/// the last field of the state.
+pub const STATE_VERSION: u32 = 13;
+
+/// Task record as it is stored on-chain.
+///
+/// When `Task` changes, keep its previous layout as a frozen struct in a new variant and
+/// convert it to the current `Task` in `From<VersionedTask>`.
+#[derive(BorshDeserialize, BorshSerialize)]
+pub enum VersionedTask {
+    V1(TaskV1),
+    V2(TaskV2),
+    V3(TaskV3),
+    V4(TaskV4),
+    V5(TaskV5),
+    V6(TaskV6),
+    V7(TaskV7),
+    V8(TaskV8),
+    V9(Task),
+}
+
+impl From<VersionedTask> for Task {
+    fn from(task: VersionedTask) -> Self {
+        match task {
+            VersionedTask::V1(task) => task.into(),
+            VersionedTask::V2(task) => task.into(),
+            VersionedTask::V3(task) => task.into(),
+            VersionedTask::V4(task) => task.into(),
+            VersionedTask::V5(task) => task.into(),
+            VersionedTask::V6(task) => task.into(),
+            VersionedTask::V7(task) => task.into(),
+            VersionedTask::V8(task) => task.into(),
+            VersionedTask::V9(task) => task,
+        }
+    }
+}
+
+impl From<Task> for VersionedTask {
+    fn from(task: Task) -> Self {
+        VersionedTask::V9(task)
+    }
+}
+
+/// Task record before descriptions, priorities, due dates and tags.
+#[derive(Debug, Clone, PartialEq, BorshDeserialize, BorshSerialize)]
+pub struct TaskV1 {
+    pub id: TaskId,
+    pub owner_id: AccountId,
+    pub task_name: String,
+    pub task_status: TaskStatus,
+}
+
+impl From<TaskV1> for Task {
+    fn from(task: TaskV1) -> Self {
+        TaskV2::from(task).into()
+    }
+}
+
+/// Task record before timestamps and the history.
+#[derive(Debug, Clone, PartialEq, BorshDeserialize, BorshSerialize)]
+pub struct TaskV2 {
+    pub id: TaskId,
+    pub owner_id: AccountId,
+    pub task_name: String,
+    pub task_status: TaskStatus,
+    pub description: Option<String>,
+    pub priority: Priority,
+    pub due_date: Option<U64>,
+    pub tags: Vec<String>,
+}
+
+impl From<TaskV1> for TaskV2 {
+    fn from(task: TaskV1) -> Self {
+        TaskV2 {
+            id: task.id,
+            owner_id: task.owner_id,
+            task_name: task.task_name,
+            task_status: task.task_status,
+            description: None,
+            priority: Priority::default(),
+            due_date: None,
+            tags: Vec::new(),
+        }
+    }
+}
+
+impl From<TaskV2> for Task {
+    fn from(task: TaskV2) -> Self {
+        TaskV3::from(task).into()
+    }
+}
+
+/// Task record before shared lists.
+#[derive(Debug, Clone, PartialEq, BorshDeserialize, BorshSerialize)]
+pub struct TaskV3 {
+    pub id: TaskId,
+    pub owner_id: AccountId,
+    pub task_name: String,
+    pub task_status: TaskStatus,
+    pub description: Option<String>,
+    pub priority: Priority,
+    pub due_date: Option<U64>,
+    pub tags: Vec<String>,
+    pub created_at: U64,
+    pub updated_at: U64,
+    pub updated_by: AccountId,
+    pub completed_at: Option<U64>,
+    pub track_history: bool,
+}
+
+/// Tasks of this layout don't know when they were created, so their timestamps are zero and
+/// the owner is taken as their last editor.
+impl From<TaskV2> for TaskV3 {
+    fn from(task: TaskV2) -> Self {
+        TaskV3 {
+            id: task.id,
+            updated_by: task.owner_id.clone(),
+            owner_id: task.owner_id,
+            task_name: task.task_name,
+            task_status: task.task_status,
+            description: task.description,
+            priority: task.priority,
+            due_date: task.due_date,
+            tags: task.tags,
+            created_at: U64(0),
+            updated_at: U64(0),
+            completed_at: None,
+            track_history: false,
+        }
+    }
+}
+
+impl From<TaskV3> for Task {
+    fn from(task: TaskV3) -> Self {
+        TaskV4::from(task).into()
+    }
+}
+
+/// Task record before assignments.
+#[derive(Debug, Clone, PartialEq, BorshDeserialize, BorshSerialize)]
+pub struct TaskV4 {
+    pub id: TaskId,
+    pub owner_id: AccountId,
+    pub task_name: String,
+    pub task_status: TaskStatus,
+    pub description: Option<String>,
+    pub priority: Priority,
+    pub due_date: Option<U64>,
+    pub tags: Vec<String>,
+    pub created_at: U64,
+    pub updated_at: U64,
+    pub updated_by: AccountId,
+    pub completed_at: Option<U64>,
+    pub track_history: bool,
+    pub list_id: Option<ListId>,
+}
+
+impl From<TaskV3> for TaskV4 {
+    fn from(task: TaskV3) -> Self {
+        TaskV4 {
+            id: task.id,
+            owner_id: task.owner_id,
+            task_name: task.task_name,
+            task_status: task.task_status,
+            description: task.description,
+            priority: task.priority,
+            due_date: task.due_date,
+            tags: task.tags,
+            created_at: task.created_at,
+            updated_at: task.updated_at,
+            updated_by: task.updated_by,
+            completed_at: task.completed_at,
+            track_history: task.track_history,
+            list_id: None,
+        }
+    }
+}
+
+impl From<TaskV4> for Task {
+    fn from(task: TaskV4) -> Self {
+        TaskV5::from(task).into()
+    }
+}
+
+/// Task record before subtasks and checklists.
+#[derive(Debug, Clone, PartialEq, BorshDeserialize, BorshSerialize)]
+pub struct TaskV5 {
+    pub id: TaskId,
+    pub owner_id: AccountId,
+    pub task_name: String,
+    pub task_status: TaskStatus,
+    pub description: Option<String>,
+    pub priority: Priority,
+    pub due_date: Option<U64>,
+    pub tags: Vec<String>,
+    pub created_at: U64,
+    pub updated_at: U64,
+    pub updated_by: AccountId,
+    pub completed_at: Option<U64>,
+    pub track_history: bool,
+    pub list_id: Option<ListId>,
+    pub assignee: Option<Assignment>,
+}
+
+impl From<TaskV4> for TaskV5 {
+    fn from(task: TaskV4) -> Self {
+        TaskV5 {
+            id: task.id,
+            owner_id: task.owner_id,
+            task_name: task.task_name,
+            task_status: task.task_status,
+            description: task.description,
+            priority: task.priority,
+            due_date: task.due_date,
+            tags: task.tags,
+            created_at: task.created_at,
+            updated_at: task.updated_at,
+            updated_by: task.updated_by,
+            completed_at: task.completed_at,
+            track_history: task.track_history,
+            list_id: task.list_id,
+            assignee: None,
+        }
+    }
+}
+
+impl From<TaskV5> for Task {
+    fn from(task: TaskV5) -> Self {
+        TaskV6::from(task).into()
+    }
+}
+
+/// Task record before dependencies between tasks.
+#[derive(Debug, Clone, PartialEq, BorshDeserialize, BorshSerialize)]
+pub struct TaskV6 {
+    pub id: TaskId,
+    pub owner_id: AccountId,
+    pub task_name: String,
+    pub task_status: TaskStatus,
+    pub description: Option<String>,
+    pub priority: Priority,
+    pub due_date: Option<U64>,
+    pub tags: Vec<String>,
+    pub created_at: U64,
+    pub updated_at: U64,
+    pub updated_by: AccountId,
+    pub completed_at: Option<U64>,
+    pub track_history: bool,
+    pub list_id: Option<ListId>,
+    pub assignee: Option<Assignment>,
+    pub parent_id: Option<TaskId>,
+    pub subtask_ids: Vec<TaskId>,
+    pub checklist: Vec<ChecklistItem>,
+}
+
+impl From<TaskV5> for TaskV6 {
+    fn from(task: TaskV5) -> Self {
+        TaskV6 {
+            id: task.id,
+            owner_id: task.owner_id,
+            task_name: task.task_name,
+            task_status: task.task_status,
+            description: task.description,
+            priority: task.priority,
+            due_date: task.due_date,
+            tags: task.tags,
+            created_at: task.created_at,
+            updated_at: task.updated_at,
+            updated_by: task.updated_by,
+            completed_at: task.completed_at,
+            track_history: task.track_history,
+            list_id: task.list_id,
+            assignee: task.assignee,
+            parent_id: None,
+            subtask_ids: Vec::new(),
+            checklist: Vec::new(),
+        }
+    }
+}
+
+impl From<TaskV6> for Task {
+    fn from(task: TaskV6) -> Self {
+        TaskV7::from(task).into()
+    }
+}
+
+/// Task record before recurring tasks.
+#[derive(Debug, Clone, PartialEq, BorshDeserialize, BorshSerialize)]
+pub struct TaskV7 {
+    pub id: TaskId,
+    pub owner_id: AccountId,
+    pub task_name: String,
+    pub task_status: TaskStatus,
+    pub description: Option<String>,
+    pub priority: Priority,
+    pub due_date: Option<U64>,
+    pub tags: Vec<String>,
+    pub created_at: U64,
+    pub updated_at: U64,
+    pub updated_by: AccountId,
+    pub completed_at: Option<U64>,
+    pub track_history: bool,
+    pub list_id: Option<ListId>,
+    pub assignee: Option<Assignment>,
+    pub parent_id: Option<TaskId>,
+    pub subtask_ids: Vec<TaskId>,
+    pub checklist: Vec<ChecklistItem>,
+    pub blocker_ids: Vec<TaskId>,
+    pub dependent_ids: Vec<TaskId>,
+}
+
+impl From<TaskV6> for TaskV7 {
+    fn from(task: TaskV6) -> Self {
+        TaskV7 {
+            id: task.id,
+            owner_id: task.owner_id,
+            task_name: task.task_name,
+            task_status: task.task_status,
+            description: task.description,
+            priority: task.priority,
+            due_date: task.due_date,
+            tags: task.tags,
+            created_at: task.created_at,
+            updated_at: task.updated_at,
+            updated_by: task.updated_by,
+            completed_at: task.completed_at,
+            track_history: task.track_history,
+            list_id: task.list_id,
+            assignee: task.assignee,
+            parent_id: task.parent_id,
+            subtask_ids: task.subtask_ids,
+            checklist: task.checklist,
+            blocker_ids: Vec::new(),
+            dependent_ids: Vec::new(),
+        }
+    }
+}
+
+impl From<TaskV7> for Task {
+    fn from(task: TaskV7) -> Self {
+        TaskV8::from(task).into()
+    }
+}
+
+/// Task record before version numbers.
+#[derive(Debug, Clone, PartialEq, BorshDeserialize, BorshSerialize)]
+pub struct TaskV8 {
+    pub id: TaskId,
+    pub owner_id: AccountId,
+    pub task_name: String,
+    pub task_status: TaskStatus,
+    pub description: Option<String>,
+    pub priority: Priority,
+    pub due_date: Option<U64>,
+    pub tags: Vec<String>,
+    pub created_at: U64,
+    pub updated_at: U64,
+    pub updated_by: AccountId,
+    pub completed_at: Option<U64>,
+    pub track_history: bool,
+    pub list_id: Option<ListId>,
+    pub assignee: Option<Assignment>,
+    pub parent_id: Option<TaskId>,
+    pub subtask_ids: Vec<TaskId>,
+    pub checklist: Vec<ChecklistItem>,
+    pub blocker_ids: Vec<TaskId>,
+    pub dependent_ids: Vec<TaskId>,
+    pub recurrence: Option<Recurrence>,
+}
+
+impl From<TaskV7> for TaskV8 {
+    fn from(task: TaskV7) -> Self {
+        TaskV8 {
+            id: task.id,
+            owner_id: task.owner_id,
+            task_name: task.task_name,
+            task_status: task.task_status,
+            description: task.description,
+            priority: task.priority,
+            due_date: task.due_date,
+            tags: task.tags,
+            created_at: task.created_at,
+            updated_at: task.updated_at,
+            updated_by: task.updated_by,
+            completed_at: task.completed_at,
+            track_history: task.track_history,
+            list_id: task.list_id,
+            assignee: task.assignee,
+            parent_id: task.parent_id,
+            subtask_ids: task.subtask_ids,
+            checklist: task.checklist,
+            blocker_ids: task.blocker_ids,
+            dependent_ids: task.dependent_ids,
+            recurrence: None,
+        }
+    }
+}
+
+impl From<TaskV8> for Task {
+    fn from(task: TaskV8) -> Self {
+        Task {
+            id: task.id,
+            owner_id: task.owner_id,
+            task_name: task.task_name,
+            task_status: task.task_status,
+            description: task.description,
+            priority: task.priority,
+            due_date: task.due_date,
+            tags: task.tags,
+            created_at: task.created_at,
+            updated_at: task.updated_at,
+            updated_by: task.updated_by,
+            completed_at: task.completed_at,
+            track_history: task.track_history,
+            list_id: task.list_id,
+            assignee: task.assignee,
+            parent_id: task.parent_id,
+            subtask_ids: task.subtask_ids,
+            checklist: task.checklist,
+            blocker_ids: task.blocker_ids,
+            dependent_ids: task.dependent_ids,
+            recurrence: task.recurrence,
+            version: 0,
+        }
+    }
+}
+
+/// Task records keyed by id.
+///
+/// Records written before tasks were versioned are stored as a bare `TaskV1` under
+/// `unversioned_prefix`. They are still served by `get` and are moved to a `VersionedTask`
+/// the next time the task is written.
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct TaskStore {
+    tasks: LookupMap<TaskId, VersionedTask>,
+    unversioned: Option<LookupMap<TaskId, TaskV1>>,
+}
+
+impl TaskStore {
+    pub fn new(prefix: &[u8]) -> Self {
+        Self {
+            tasks: LookupMap::new(prefix),
+            unversioned: None,
+        }
+    }
+
+    /// Serves and upgrades records stored as a bare `TaskV1` under `unversioned_prefix`.
+    pub fn with_unversioned(mut self, unversioned_prefix: &[u8]) -> Self {
+        self.unversioned = Some(LookupMap::new(unversioned_prefix));
+        self
+    }
+
+    pub fn get(&self, task_id: &TaskId) -> Option<Task> {
+        self.tasks.get(task_id).map(Task::from).or_else(|| {
+            self.unversioned
+                .as_ref()
+                .and_then(|unversioned| unversioned.get(task_id))
+                .map(Task::from)
+        })
+    }
+
+    pub fn contains_key(&self, task_id: &TaskId) -> bool {
+        self.tasks.contains_key(task_id)
+            || self
+                .unversioned
+                .as_ref()
+                .is_some_and(|unversioned| unversioned.contains_key(task_id))
+    }
+
+    pub fn insert(&mut self, task_id: &TaskId, task: &Task) {
+        self.tasks
+            .insert(task_id, &VersionedTask::from(task.clone()));
+        if let Some(unversioned) = self.unversioned.as_mut() {
+            unversioned.remove(task_id);
+        }
+    }
+
+    pub fn remove(&mut self, task_id: &TaskId) -> Option<Task> {
+        let unversioned = self
+            .unversioned
+            .as_mut()
+            .and_then(|unversioned| unversioned.remove(task_id))
+            .map(Task::from);
+        self.tasks.remove(task_id).map(Task::from).or(unversioned)
+    }
+}
+
+/// Contract state with numeric task ids and `Vec` based per-account indexes.
+#[derive(BorshDeserialize, BorshSerialize)]
+struct ContractV1 {
+    tasks_by_account: LookupMap<AccountId, Vec<TaskId>>,
+    tasks: LookupMap<TaskId, TaskV1>,
+    next_task_id: TaskId,
+    legacy_task_ids: LookupMap<String, TaskId>,
+    archived_by_account: LookupMap<AccountId, Vec<TaskId>>,
+    archived_tasks: LookupMap<TaskId, TaskV1>,
+    storage_accounts: LookupMap<AccountId, StorageAccount>,
+}
+
+/// Contract state with per-account task sets, before tasks and the state were versioned.
+#[derive(BorshDeserialize, BorshSerialize)]
+struct ContractV2 {
+    tasks_by_account: TaskIndex,
+    tasks: LookupMap<TaskId, TaskV1>,
+    next_task_id: TaskId,
+    legacy_task_ids: LookupMap<String, TaskId>,
+    archived_by_account: TaskIndex,
+    archived_tasks: LookupMap<TaskId, TaskV1>,
+    storage_accounts: LookupMap<AccountId, StorageAccount>,
+}
+
+/// Versioned contract state, before per-account greetings.
+#[derive(BorshDeserialize, BorshSerialize)]
+struct ContractV3 {
+    tasks_by_account: TaskIndex,
+    tasks: TaskStore,
+    next_task_id: TaskId,
+    legacy_task_ids: LookupMap<String, TaskId>,
+    archived_by_account: TaskIndex,
+    archived_tasks: TaskStore,
+    storage_accounts: LookupMap<AccountId, StorageAccount>,
+    state_version: u32,
+}
+
+/// Contract state before the per-task history.
+#[derive(BorshDeserialize, BorshSerialize)]
+struct ContractV4 {
+    tasks_by_account: TaskIndex,
+    tasks: TaskStore,
+    next_task_id: TaskId,
+    legacy_task_ids: LookupMap<String, TaskId>,
+    archived_by_account: TaskIndex,
+    archived_tasks: TaskStore,
+    storage_accounts: LookupMap<AccountId, StorageAccount>,
+    greetings: LookupMap<AccountId, String>,
+    state_version: u32,
+}
+
+/// Contract state before shared task lists.
+#[derive(BorshDeserialize, BorshSerialize)]
+struct ContractV5 {
+    tasks_by_account: TaskIndex,
+    tasks: TaskStore,
+    next_task_id: TaskId,
+    legacy_task_ids: LookupMap<String, TaskId>,
+    archived_by_account: TaskIndex,
+    archived_tasks: TaskStore,
+    storage_accounts: LookupMap<AccountId, StorageAccount>,
+    greetings: LookupMap<AccountId, String>,
+    task_history: LookupMap<TaskId, Vec<TaskHistoryEntry>>,
+    state_version: u32,
+}
+
+/// Contract state before task assignments.
+#[derive(BorshDeserialize, BorshSerialize)]
+struct ContractV6 {
+    tasks_by_account: TaskIndex,
+    tasks: TaskStore,
+    next_task_id: TaskId,
+    legacy_task_ids: LookupMap<String, TaskId>,
+    archived_by_account: TaskIndex,
+    archived_tasks: TaskStore,
+    storage_accounts: LookupMap<AccountId, StorageAccount>,
+    greetings: LookupMap<AccountId, String>,
+    task_history: LookupMap<TaskId, Vec<TaskHistoryEntry>>,
+    task_lists: LookupMap<ListId, TaskList>,
+    next_list_id: ListId,
+    lists_by_account: TaskIndex,
+    tasks_by_list: TaskIndex<ListId>,
+    archived_by_list: TaskIndex<ListId>,
+    state_version: u32,
+}
+
+/// Contract state before task bounties.
+#[derive(BorshDeserialize, BorshSerialize)]
+struct ContractV7 {
+    tasks_by_account: TaskIndex,
+    tasks: TaskStore,
+    next_task_id: TaskId,
+    legacy_task_ids: LookupMap<String, TaskId>,
+    archived_by_account: TaskIndex,
+    archived_tasks: TaskStore,
+    storage_accounts: LookupMap<AccountId, StorageAccount>,
+    greetings: LookupMap<AccountId, String>,
+    task_history: LookupMap<TaskId, Vec<TaskHistoryEntry>>,
+    task_lists: LookupMap<ListId, TaskList>,
+    next_list_id: ListId,
+    lists_by_account: TaskIndex,
+    tasks_by_list: TaskIndex<ListId>,
+    archived_by_list: TaskIndex<ListId>,
+    assigned_by_account: TaskIndex,
+    state_version: u32,
+}
+
+/// Contract state before fungible token bounties.
+#[derive(BorshDeserialize, BorshSerialize)]
+struct ContractV8 {
+    tasks_by_account: TaskIndex,
+    tasks: TaskStore,
+    next_task_id: TaskId,
+    legacy_task_ids: LookupMap<String, TaskId>,
+    archived_by_account: TaskIndex,
+    archived_tasks: TaskStore,
+    storage_accounts: LookupMap<AccountId, StorageAccount>,
+    greetings: LookupMap<AccountId, String>,
+    task_history: LookupMap<TaskId, Vec<TaskHistoryEntry>>,
+    task_lists: LookupMap<ListId, TaskList>,
+    next_list_id: ListId,
+    lists_by_account: TaskIndex,
+    tasks_by_list: TaskIndex<ListId>,
+    archived_by_list: TaskIndex<ListId>,
+    assigned_by_account: TaskIndex,
+    bounties: LookupMap<TaskId, Bounty>,
+    unclaimed_bounties: LookupMap<AccountId, Balance>,
+    state_version: u32,
+}
+
+/// Contract state before completion badges.
+#[derive(BorshDeserialize, BorshSerialize)]
+struct ContractV9 {
+    tasks_by_account: TaskIndex,
+    tasks: TaskStore,
+    next_task_id: TaskId,
+    legacy_task_ids: LookupMap<String, TaskId>,
+    archived_by_account: TaskIndex,
+    archived_tasks: TaskStore,
+    storage_accounts: LookupMap<AccountId, StorageAccount>,
+    greetings: LookupMap<AccountId, String>,
+    task_history: LookupMap<TaskId, Vec<TaskHistoryEntry>>,
+    task_lists: LookupMap<ListId, TaskList>,
+    next_list_id: ListId,
+    lists_by_account: TaskIndex,
+    tasks_by_list: TaskIndex<ListId>,
+    archived_by_list: TaskIndex<ListId>,
+    assigned_by_account: TaskIndex,
+    bounties: LookupMap<TaskId, Bounty>,
+    unclaimed_bounties: LookupMap<AccountId, Balance>,
+    token_bounties: LookupMap<TaskId, Vec<TokenBounty>>,
+    unclaimed_token_bounties: LookupMap<(AccountId, AccountId), Balance>,
+    state_version: u32,
+}
+
+/// Contract state before the due date index.
+#[derive(BorshDeserialize, BorshSerialize)]
+struct ContractV10 {
+    tasks_by_account: TaskIndex,
+    tasks: TaskStore,
+    next_task_id: TaskId,
+    legacy_task_ids: LookupMap<String, TaskId>,
+    archived_by_account: TaskIndex,
+    archived_tasks: TaskStore,
+    storage_accounts: LookupMap<AccountId, StorageAccount>,
+    greetings: LookupMap<AccountId, String>,
+    task_history: LookupMap<TaskId, Vec<TaskHistoryEntry>>,
+    task_lists: LookupMap<ListId, TaskList>,
+    next_list_id: ListId,
+    lists_by_account: TaskIndex,
+    tasks_by_list: TaskIndex<ListId>,
+    archived_by_list: TaskIndex<ListId>,
+    assigned_by_account: TaskIndex,
+    bounties: LookupMap<TaskId, Bounty>,
+    unclaimed_bounties: LookupMap<AccountId, Balance>,
+    token_bounties: LookupMap<TaskId, Vec<TokenBounty>>,
+    unclaimed_token_bounties: LookupMap<(AccountId, AccountId), Balance>,
+    badges: LookupMap<TaskId, Badge>,
+    badges_by_account: TaskIndex,
+    state_version: u32,
+}
+
+/// Contract state before commitment stakes.
+#[derive(BorshDeserialize, BorshSerialize)]
+struct ContractV11 {
+    tasks_by_account: TaskIndex,
+    tasks: TaskStore,
+    next_task_id: TaskId,
+    legacy_task_ids: LookupMap<String, TaskId>,
+    archived_by_account: TaskIndex,
+    archived_tasks: TaskStore,
+    storage_accounts: LookupMap<AccountId, StorageAccount>,
+    greetings: LookupMap<AccountId, String>,
+    task_history: LookupMap<TaskId, Vec<TaskHistoryEntry>>,
+    task_lists: LookupMap<ListId, TaskList>,
+    next_list_id: ListId,
+    lists_by_account: TaskIndex,
+    tasks_by_list: TaskIndex<ListId>,
+    archived_by_list: TaskIndex<ListId>,
+    assigned_by_account: TaskIndex,
+    bounties: LookupMap<TaskId, Bounty>,
+    unclaimed_bounties: LookupMap<AccountId, Balance>,
+    token_bounties: LookupMap<TaskId, Vec<TokenBounty>>,
+    unclaimed_token_bounties: LookupMap<(AccountId, AccountId), Balance>,
+    badges: LookupMap<TaskId, Badge>,
+    badges_by_account: TaskIndex,
+    tasks_by_due_date: DueDateIndex,
+    state_version: u32,
+}
+
+/// Contract state before the bounty token allowlists.
+#[derive(BorshDeserialize, BorshSerialize)]
+struct ContractV12 {
+    tasks_by_account: TaskIndex,
+    tasks: TaskStore,
+    next_task_id: TaskId,
+    legacy_task_ids: LookupMap<String, TaskId>,
+    archived_by_account: TaskIndex,
+    archived_tasks: TaskStore,
+    storage_accounts: LookupMap<AccountId, StorageAccount>,
+    greetings: LookupMap<AccountId, String>,
+    task_history: LookupMap<TaskId, Vec<TaskHistoryEntry>>,
+    task_lists: LookupMap<ListId, TaskList>,
+    next_list_id: ListId,
+    lists_by_account: TaskIndex,
+    tasks_by_list: TaskIndex<ListId>,
+    archived_by_list: TaskIndex<ListId>,
+    assigned_by_account: TaskIndex,
+    bounties: LookupMap<TaskId, Bounty>,
+    unclaimed_bounties: LookupMap<AccountId, Balance>,
+    token_bounties: LookupMap<TaskId, Vec<TokenBounty>>,
+    unclaimed_token_bounties: LookupMap<(AccountId, AccountId), Balance>,
+    badges: LookupMap<TaskId, Badge>,
+    badges_by_account: TaskIndex,
+    tasks_by_due_date: DueDateIndex,
+    stakes: LookupMap<TaskId, Stake>,
+    stakes_by_account: TaskIndex,
+    stake_beneficiary: Option<AccountId>,
+    state_version: u32,
+}
+
+/// Every layout the contract state has been deployed with, oldest first.
+// Only read once per upgrade, so the size of the variants doesn't matter.
+#[allow(clippy::large_enum_variant)]
+enum VersionedState {
+    /// Tasks keyed by `<owner>.<task_name>` strings.
+    V0,
+    /// Numeric task ids with `Vec` based per-account indexes.
+    V1(ContractV1),
+    /// Per-account task sets.
+    V2(ContractV2),
+    /// Versioned state and task records.
+    V3(ContractV3),
+    /// Per-account greetings.
+    V4(ContractV4),
+    /// Per-task history.
+    V5(ContractV5),
+    /// Shared task lists.
+    V6(ContractV6),
+    /// Task assignments.
+    V7(ContractV7),
+    /// Task bounties.
+    V8(ContractV8),
+    /// Fungible token bounties.
+    V9(ContractV9),
+    /// Completion badges.
+    V10(ContractV10),
+    /// Due date index.
+    V11(ContractV11),
+    /// Commitment stakes.
+    V12(ContractV12),
+    /// Bounty token allowlists.
+    V13(Contract),
+}
+
+impl VersionedState {
+    /// Reads the stored state. Since `V3` the version is the last field of the state, so it is
+    /// read from the last four bytes. The layouts before carry no version and are recognized by
+    /// trying to decode them from the newest to the oldest.
+    fn read() -> Option<Self> {
+        let state = env::storage_read(b"STATE")?;
+        let version = state
+            .len()
+            .checked_sub(4)
+            .map(|at| u32::from_le_bytes(state[at..].try_into().unwrap()));
+        let tagged = match version {
+            Some(STATE_VERSION) => Contract::try_from_slice(&state).map(VersionedState::V13),
+            Some(12) => ContractV12::try_from_slice(&state).map(VersionedState::V12),
+            Some(11) => ContractV11::try_from_slice(&state).map(VersionedState::V11),
+            Some(10) => ContractV10::try_from_slice(&state).map(VersionedState::V10),
+            Some(9) => ContractV9::try_from_slice(&state).map(VersionedState::V9),
+            Some(8) => ContractV8::try_from_slice(&state).map(VersionedState::V8),
+            Some(7) => ContractV7::try_from_slice(&state).map(VersionedState::V7),
+            Some(6) => ContractV6::try_from_slice(&state).map(VersionedState::V6),
+            Some(5) => ContractV5::try_from_slice(&state).map(VersionedState::V5),
+            Some(4) => ContractV4::try_from_slice(&state).map(VersionedState::V4),
+            Some(3) => ContractV3::try_from_slice(&state).map(VersionedState::V3),
+            _ => Err(borsh::maybestd::io::ErrorKind::InvalidData.into()),
+        };
+        tagged
+            .or_else(|_| ContractV2::try_from_slice(&state).map(VersionedState::V2))
+            .or_else(|_| ContractV1::try_from_slice(&state).map(VersionedState::V1))
+            .or_else(|_| LegacyContract::try_from_slice(&state).map(|_| VersionedState::V0))
+            .ok()
+    }
+
+    fn version(&self) -> u32 {
+        match self {
+            VersionedState::V0 => 0,
+            VersionedState::V1(_) => 1,
+            VersionedState::V2(_) => 2,
+            VersionedState::V3(state) => state.state_version,
+            VersionedState::V4(state) => state.state_version,
+            VersionedState::V5(state) => state.state_version,
+            VersionedState::V6(state) => state.state_version,
+            VersionedState::V7(state) => state.state_version,
+            VersionedState::V8(state) => state.state_version,
+            VersionedState::V9(state) => state.state_version,
+            VersionedState::V10(state) => state.state_version,
+            VersionedState::V11(state) => state.state_version,
+            VersionedState::V12(state) => state.state_version,
+            VersionedState::V13(contract) => contract.state_version,
+        }
+    }
+
+    /// Every collection of the older layouts is either kept under its prefix or read through
+    /// the fallbacks set up by `Contract::default`, so only the id counters, the due date
+    /// index and the stake beneficiary are carried over.
+    fn into_current(self) -> Contract {
+        let (next_task_id, next_list_id) = match self {
+            VersionedState::V0 => (0, 0),
+            VersionedState::V1(state) => (state.next_task_id, 0),
+            VersionedState::V2(state) => (state.next_task_id, 0),
+            VersionedState::V3(state) => (state.next_task_id, 0),
+            VersionedState::V4(state) => (state.next_task_id, 0),
+            VersionedState::V5(state) => (state.next_task_id, 0),
+            VersionedState::V6(state) => (state.next_task_id, state.next_list_id),
+            VersionedState::V7(state) => (state.next_task_id, state.next_list_id),
+            VersionedState::V8(state) => (state.next_task_id, state.next_list_id),
+            VersionedState::V9(state) => (state.next_task_id, state.next_list_id),
+            VersionedState::V10(state) => (state.next_task_id, state.next_list_id),
+            VersionedState::V11(state) => {
+                return Contract {
+                    next_task_id: state.next_task_id,
+                    next_list_id: state.next_list_id,
+                    tasks_by_due_date: state.tasks_by_due_date,
+                    ..Contract::default()
+                }
+            }
+            VersionedState::V12(state) => {
+                return Contract {
+                    next_task_id: state.next_task_id,
+                    next_list_id: state.next_list_id,
+                    tasks_by_due_date: state.tasks_by_due_date,
+                    stake_beneficiary: state.stake_beneficiary,
+                    ..Contract::default()
+                }
+            }
+            VersionedState::V13(contract) => return contract,
+        };
+        Contract {
+            next_task_id,
+            next_list_id,
+            // Tasks of the older layouts are added to the index on their owner's next write.
+            tasks_by_due_date: DueDateIndex::new(
+                DUE_DATES_BY_ACCOUNT_PREFIX,
+                DUE_DATE_TREES_PREFIX,
+            )
+            .with_backfill(DUE_DATES_BACKFILLED_PREFIX),
+            ..Contract::default()
+        }
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    // Private method - upgrades the stored contract state to the current layout.
+    // Call it in the same transaction that deploys the new code. Task records and indexes
+    // themselves are upgraded lazily, on read and on the owner's next write.
+    #[private]
+    #[init(ignore_state)]
+    pub fn migrate() -> Self {
+        let state = VersionedState::read()
+            .unwrap_or_else(|| env::panic_str("No contract state to migrate"));
+        log!(
+            "Migrate contract state from version {} to {}",
+            state.version(),
+            STATE_VERSION
+        );
+        state.into_current()
+    }
+
+    // Public method - returns the version of the contract state layout
+    pub fn get_state_version(&self) -> u32 {
+        self.state_version
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use super::*;
+    use near_contract_standards::storage_management::StorageManagement;
+    use near_sdk::test_utils::{accounts, VMContextBuilder};
+    use near_sdk::{testing_env, ONE_NEAR};
+
+    fn setup() {
+        let mut context = VMContextBuilder::new();
+        context
+            .current_account_id(accounts(0))
+            .predecessor_account_id(accounts(0))
+            .account_balance(10 * ONE_NEAR)
+            .attached_deposit(ONE_NEAR / 100);
+        testing_env!(context.build());
+    }
+
+    fn task_v1(task_id: TaskId, owner_id: &AccountId) -> TaskV1 {
+        TaskV1 {
+            id: task_id,
+            owner_id: owner_id.clone(),
+            task_name: format!("task_{}", task_id),
+            task_status: TaskStatus::Todo,
+        }
+    }
+
+    fn task(task_id: TaskId, owner_id: &AccountId) -> Task {
+        task_v1(task_id, owner_id).into()
+    }
+
+    #[test]
+    fn migrate_accepts_string_id_state() {
+        setup();
+        let legacy = (
+            LookupMap::<AccountId, Vec<String>>::new(b"ta".to_vec()),
+            LookupMap::<String, String>::new(b"t".to_vec()),
+        );
+        env::state_write(&legacy);
+        let contract = Contract::migrate();
+        assert_eq!(contract.next_task_id, 0);
+        assert_eq!(contract.get_state_version(), STATE_VERSION);
+    }
+
+    #[test]
+    fn migrate_keeps_tasks_of_vec_index_layout() {
+        setup();
+        let alice = accounts(0);
+        let mut v1 = ContractV1 {
+            tasks_by_account: LookupMap::new(VEC_TASKS_BY_ACCOUNT_PREFIX),
+            tasks: LookupMap::new(UNVERSIONED_TASKS_PREFIX),
+            next_task_id: 2,
+            legacy_task_ids: LookupMap::new(LEGACY_TASK_IDS_PREFIX),
+            archived_by_account: LookupMap::new(VEC_ARCHIVED_BY_ACCOUNT_PREFIX),
+            archived_tasks: LookupMap::new(UNVERSIONED_ARCHIVED_TASKS_PREFIX),
+            storage_accounts: LookupMap::new(STORAGE_ACCOUNTS_PREFIX),
+        };
+        for task_id in 0..2 {
+            v1.tasks.insert(&task_id, &task_v1(task_id, &alice));
+        }
+        v1.tasks_by_account.insert(&alice, &vec![0, 1]);
+        env::state_write(&v1);
+
+        let mut contract = Contract::migrate();
+        assert_eq!(contract.get_task_count_for(alice.clone()), 2);
+        contract.storage_deposit(None, None);
+        assert_eq!(contract.insert_task(String::from("task_2")), 2);
+        let ids: Vec<TaskId> = contract
+            .get_tasks_for(alice, None, None)
+            .into_iter()
+            .map(|t| t.id)
+            .collect();
+        assert_eq!(ids, vec![0, 1, 2]);
+    }
+
+    #[test]
+    fn migrate_upgrades_unversioned_tasks_lazily() {
+        setup();
+        let alice = accounts(0);
+        let mut v2 = ContractV2 {
+            tasks_by_account: TaskIndex::new(TASKS_BY_ACCOUNT_PREFIX, TASK_SETS_PREFIX)
+                .with_legacy(VEC_TASKS_BY_ACCOUNT_PREFIX),
+            tasks: LookupMap::new(UNVERSIONED_TASKS_PREFIX),
+            next_task_id: 1,
+            legacy_task_ids: LookupMap::new(LEGACY_TASK_IDS_PREFIX),
+            archived_by_account: TaskIndex::new(ARCHIVED_BY_ACCOUNT_PREFIX, ARCHIVED_SETS_PREFIX)
+                .with_legacy(VEC_ARCHIVED_BY_ACCOUNT_PREFIX),
+            archived_tasks: LookupMap::new(UNVERSIONED_ARCHIVED_TASKS_PREFIX),
+            storage_accounts: LookupMap::new(STORAGE_ACCOUNTS_PREFIX),
+        };
+        v2.tasks.insert(&0, &task_v1(0, &alice));
+        v2.tasks_by_account.insert(&alice, 0);
+        env::state_write(&v2);
+
+        let mut contract = Contract::migrate();
+        assert_eq!(contract.get_task(0), Some(task(0, &alice)));
+        contract.storage_deposit(None, None);
+        contract.update_task(0, TaskStatus::Done, None).unwrap();
+        assert!(!v2.tasks.contains_key(&0));
+        assert_eq!(contract.get_task(0).unwrap().task_status, TaskStatus::Done);
+
+        // Migrating an up to date state keeps it as it is.
+        env::state_write(&contract);
+        let contract = Contract::migrate();
+        assert_eq!(contract.next_task_id, 1);
+        assert_eq!(contract.get_task(0).unwrap().task_status, TaskStatus::Done);
+    }
+
+    #[test]
+    fn migrate_adds_greetings_to_versioned_state() {
+        setup();
+        let alice = accounts(0);
+        let mut v3 = ContractV3 {
+            tasks_by_account: TaskIndex::new(TASKS_BY_ACCOUNT_PREFIX, TASK_SETS_PREFIX)
+                .with_legacy(VEC_TASKS_BY_ACCOUNT_PREFIX),
+            tasks: TaskStore::new(TASKS_PREFIX).with_unversioned(UNVERSIONED_TASKS_PREFIX),
+            next_task_id: 1,
+            legacy_task_ids: LookupMap::new(LEGACY_TASK_IDS_PREFIX),
+            archived_by_account: TaskIndex::new(ARCHIVED_BY_ACCOUNT_PREFIX, ARCHIVED_SETS_PREFIX)
+                .with_legacy(VEC_ARCHIVED_BY_ACCOUNT_PREFIX),
+            archived_tasks: TaskStore::new(ARCHIVED_TASKS_PREFIX)
+                .with_unversioned(UNVERSIONED_ARCHIVED_TASKS_PREFIX),
+            storage_accounts: LookupMap::new(STORAGE_ACCOUNTS_PREFIX),
+            state_version: 3,
+        };
+        v3.tasks.insert(&0, &task(0, &alice));
+        v3.tasks_by_account.insert(&alice, 0);
+        env::state_write(&v3);
+
+        let contract = Contract::migrate();
+        assert_eq!(contract.get_state_version(), STATE_VERSION);
+        assert_eq!(contract.next_task_id, 1);
+        assert_eq!(contract.get_tasks_for(alice.clone(), None, None).len(), 1);
+        assert_eq!(contract.get_greeting_for(alice), "Hello");
+    }
+
+    #[test]
+    fn migrate_keeps_task_lists() {
+        setup();
+        let alice = accounts(0);
+        let mut v6 = ContractV6 {
+            tasks_by_account: TaskIndex::new(TASKS_BY_ACCOUNT_PREFIX, TASK_SETS_PREFIX)
+                .with_legacy(VEC_TASKS_BY_ACCOUNT_PREFIX),
+            tasks: TaskStore::new(TASKS_PREFIX).with_unversioned(UNVERSIONED_TASKS_PREFIX),
+            next_task_id: 0,
+            legacy_task_ids: LookupMap::new(LEGACY_TASK_IDS_PREFIX),
+            archived_by_account: TaskIndex::new(ARCHIVED_BY_ACCOUNT_PREFIX, ARCHIVED_SETS_PREFIX)
+                .with_legacy(VEC_ARCHIVED_BY_ACCOUNT_PREFIX),
+            archived_tasks: TaskStore::new(ARCHIVED_TASKS_PREFIX)
+                .with_unversioned(UNVERSIONED_ARCHIVED_TASKS_PREFIX),
+            storage_accounts: LookupMap::new(STORAGE_ACCOUNTS_PREFIX),
+            greetings: LookupMap::new(GREETINGS_PREFIX),
+            task_history: LookupMap::new(TASK_HISTORY_PREFIX),
+            task_lists: LookupMap::new(TASK_LISTS_PREFIX),
+            next_list_id: 1,
+            lists_by_account: TaskIndex::new(LISTS_BY_ACCOUNT_PREFIX, LIST_SETS_PREFIX),
+            tasks_by_list: TaskIndex::new(TASKS_BY_LIST_PREFIX, LIST_TASK_SETS_PREFIX),
+            archived_by_list: TaskIndex::new(
+                ARCHIVED_BY_LIST_PREFIX,
+                ARCHIVED_LIST_TASK_SETS_PREFIX,
+            ),
+            state_version: 6,
+        };
+        let list = TaskList {
+            id: 0,
+            owner_id: alice.clone(),
+            name: String::from("team"),
+            collaborators: Vec::new(),
+            created_at: U64(0),
+        };
+        v6.task_lists.insert(&0, &list);
+        v6.lists_by_account.insert(&alice, 0);
+        env::state_write(&v6);
+
+        let mut contract = Contract::migrate();
+        assert_eq!(contract.get_lists_for(alice, None, None), vec![list]);
+        contract.storage_deposit(None, None);
+        assert_eq!(contract.create_list(String::from("other")), Ok(1));
+    }
+
+    #[test]
+    fn task_store_removes_unversioned_records() {
+        setup();
+        let alice = accounts(0);
+        let mut unversioned: LookupMap<TaskId, TaskV1> = LookupMap::new(b"u");
+        unversioned.insert(&0, &task_v1(0, &alice));
+        let mut store = TaskStore::new(b"v").with_unversioned(b"u");
+        store.insert(&1, &task(1, &alice));
+
+        assert!(store.contains_key(&0));
+        assert_eq!(store.remove(&0), Some(task(0, &alice)));
+        assert!(!store.contains_key(&0));
+        assert_eq!(store.remove(&1), Some(task(1, &alice)));
+        assert_eq!(store.get(&1), None);
+    }
+
+    #[test]
+    fn task_store_reads_every_task_layout() {
+        setup();
+        let alice = accounts(0);
+        let mut tasks: LookupMap<TaskId, VersionedTask> = LookupMap::new(b"v");
+        tasks.insert(&0, &VersionedTask::V1(task_v1(0, &alice)));
+        tasks.insert(&1, &VersionedTask::V2(task_v1(1, &alice).into()));
+        let task_v3 = |task_id| TaskV3::from(TaskV2::from(task_v1(task_id, &alice)));
+        tasks.insert(&2, &VersionedTask::V3(task_v3(2)));
+        tasks.insert(&3, &VersionedTask::V4(task_v3(3).into()));
+        tasks.insert(&4, &VersionedTask::V5(TaskV4::from(task_v3(4)).into()));
+        let task_v5 = |task_id| TaskV5::from(TaskV4::from(task_v3(task_id)));
+        tasks.insert(&5, &VersionedTask::V6(task_v5(5).into()));
+        tasks.insert(&6, &VersionedTask::V7(TaskV6::from(task_v5(6)).into()));
+        let task_v7 = |task_id| TaskV7::from(TaskV6::from(task_v5(task_id)));
+        tasks.insert(&7, &VersionedTask::V8(task_v7(7).into()));
+        let store = TaskStore::new(b"v");
+
+        for task_id in 0..8 {
+            let task = store.get(&task_id).unwrap();
+            assert_eq!(task.task_name, format!("task_{}", task_id));
+            assert_eq!(task.created_at, U64(0));
+            assert_eq!(task.updated_by, alice);
+            assert!(!task.track_history);
+            assert_eq!(task.list_id, None);
+            assert_eq!(task.assignee, None);
+            assert_eq!(task.parent_id, None);
+            assert!(task.checklist.is_empty());
+            assert!(task.blocker_ids.is_empty());
+            assert_eq!(task.recurrence, None);
+            assert_eq!(task.version, 0);
+        }
+    }
+}
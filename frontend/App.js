@@ -33,7 +33,7 @@ export default function App() {
     setUiPleaseWait(true);
     const { greetingInput } = e.target.elements;
     setGreetingOnContract(greetingInput.value)
-      .then(getGreetingFromContract)
+      .then(() => getGreetingFromContract())
       .then(setValueFromBlockchain)
       .catch(alert)
       .finally(() => {
@@ -1,8 +1,12 @@
-import { connect, Contract, keyStores, WalletConnection } from 'near-api-js';
+import { connect, Contract, keyStores, utils, WalletConnection } from 'near-api-js';
 import { getConfig } from './near-config';
 
 const nearConfig = getConfig(process.env.NODE_ENV || 'development');
 
+// Deposit attached to calls that may grow the contract storage, it is added to the caller's
+// storage balance and can be taken back with `storage_withdraw`
+const STORAGE_DEPOSIT = utils.format.parseNearAmount('0.01');
+
 // Initialize contract & set global variables
 export async function initContract() {
   // Initialize connection to the NEAR testnet
@@ -18,9 +22,9 @@ export async function initContract() {
   // Initializing our contract APIs by contract name and configuration
   window.contract = await new Contract(window.walletConnection.account(), nearConfig.contractName, {
     // View methods are read only. They don't modify the state, but usually return some value.
-    viewMethods: ['get_greeting', 'get_tasks'],
+    viewMethods: ['get_greeting_for', 'get_default_greeting', 'get_tasks_for', 'get_task', 'get_task_history', 'get_list', 'get_lists_for', 'get_list_tasks', 'get_archived_list_tasks', 'get_assigned_tasks', 'get_assigned_task_count', 'get_subtasks', 'get_task_progress', 'get_blockers', 'get_dependents', 'get_bounty', 'get_unclaimed_bounty', 'get_token_bounties', 'get_bounty_tokens', 'get_unclaimed_token_bounty', 'get_stake', 'get_staked_task_ids', 'get_stake_beneficiary', 'nft_token', 'nft_tokens_for_owner', 'get_overdue_tasks', 'get_tasks_due_within', 'get_task_count_for', 'get_archived_tasks_for', 'storage_balance_of', 'storage_balance_bounds'],
     // Change methods can modify the state. But you don't receive the returned value when called.
-    changeMethods: ['set_greeting', 'insert_task', 'update_task'],
+    changeMethods: ['set_greeting', 'reset_greeting', 'insert_task', 'create_task', 'insert_tasks', 'update_task', 'update_tasks', 'rename_task', 'patch_task', 'delete_task', 'delete_tasks', 'archive_task', 'restore_task', 'create_list', 'delete_list', 'add_collaborator', 'remove_collaborator', 'assign_task', 'unassign_task', 'accept_task', 'decline_task', 'complete_task', 'add_checklist_item', 'check_checklist_item', 'remove_checklist_item', 'add_blocker', 'remove_blocker', 'fund_task', 'release_bounty', 'refund_expired_bounty', 'withdraw_unclaimed_bounty', 'withdraw_unclaimed_token_bounty', 'allow_bounty_token', 'disallow_bounty_token', 'stake_on_task', 'slash_expired', 'claim_completion_badge', 'migrate_legacy_tasks', 'storage_deposit', 'storage_withdraw', 'storage_unregister'],
   });
 }
 
@@ -40,31 +44,389 @@ export function signInWithNearWallet() {
 
 export async function setGreetingOnContract(message) {
   let response = await window.contract.set_greeting({
-    args: { message: message }
+    args: { message: message },
+    amount: STORAGE_DEPOSIT
   });
   return response;
 }
 
-export async function getGreetingFromContract() {
-  let greeting = await window.contract.get_greeting();
+export async function getGreetingFromContract(accountId = window.accountId) {
+  // Signed out visitors see the greeting every account starts with
+  if (!accountId) {
+    return await window.contract.get_default_greeting();
+  }
+  let greeting = await window.contract.get_greeting_for({ account_id: accountId });
   return greeting;
 }
 
-export async function getTasks() {
-  let tasks = await window.contract.get_tasks();
+export async function getTasks(accountId = window.accountId, fromIndex = 0, limit = 50) {
+  let tasks = await window.contract.get_tasks_for({ account_id: accountId, from_index: fromIndex, limit: limit });
   return tasks;
 }
 
 export async function insertTask(taskName) {
   let response = await window.contract.insert_task({
-    args: { task_name: taskName }
+    args: { task_name: taskName },
+    amount: STORAGE_DEPOSIT
+  });
+  return response;
+}
+
+// `task` holds `task_name` and optionally `description`, `priority`, `due_date`, `tags`,
+// the `list_id` of a shared list, a `parent_id` and a `recurrence` like 'WEEKLY' or { EVERY_N_DAYS: 3 }
+export async function createTask(task) {
+  let response = await window.contract.create_task({
+    args: { task: task },
+    amount: STORAGE_DEPOSIT
   });
   return response;
 }
 
-export async function updateTask(taskName, taskStatus) {
+// Only the fields present in `patch` are changed. Add `expected_version: task.version` to the
+// patch to fail instead of overwriting a change made since `task` was read.
+export async function patchTask(taskId, patch) {
+  let response = await window.contract.patch_task({
+    args: { task_id: taskId, patch: patch }
+  });
+  return response;
+}
+
+// `expectedVersion` is the `version` of the task as it was read, see `patchTask`
+export async function updateTask(taskId, taskStatus, expectedVersion = null) {
   let response = await window.contract.update_task({
-    args: { task_name: taskName, task_status: taskStatus }
+    args: { task_id: taskId, task_status: taskStatus, expected_version: expectedVersion }
+  });
+  return response;
+}
+
+export async function renameTask(taskId, taskName, expectedVersion = null) {
+  let response = await window.contract.rename_task({
+    args: { task_id: taskId, task_name: taskName, expected_version: expectedVersion },
+    amount: STORAGE_DEPOSIT
   });
   return response;
-}
\ No newline at end of file
+}
+
+// Open tasks that are past their due date, earliest first
+export async function getOverdueTasks(accountId = window.accountId, fromIndex = 0, limit = 50) {
+  let tasks = await window.contract.get_overdue_tasks({ account_id: accountId, from_index: fromIndex, limit: limit });
+  return tasks;
+}
+
+// Open tasks due within `windowMs` milliseconds from now, earliest first
+export async function getTasksDueWithin(windowMs, accountId = window.accountId, fromIndex = 0, limit = 50) {
+  let tasks = await window.contract.get_tasks_due_within({
+    account_id: accountId,
+    window: (BigInt(windowMs) * 1000000n).toString(),
+    from_index: fromIndex,
+    limit: limit
+  });
+  return tasks;
+}
+
+export async function getArchivedTasks(accountId = window.accountId, fromIndex = 0, limit = 50) {
+  let tasks = await window.contract.get_archived_tasks_for({ account_id: accountId, from_index: fromIndex, limit: limit });
+  return tasks;
+}
+
+export async function deleteTask(taskId) {
+  let response = await window.contract.delete_task({
+    args: { task_id: taskId }
+  });
+  return response;
+}
+
+// Batches of up to 20 tasks, applied completely or not at all
+const BATCH_GAS = '300000000000000';
+
+export async function insertTasks(tasks) {
+  let response = await window.contract.insert_tasks({
+    args: { tasks: tasks },
+    gas: BATCH_GAS,
+    amount: (BigInt(STORAGE_DEPOSIT) * BigInt(tasks.length)).toString()
+  });
+  return response;
+}
+
+// `updates` are `patch_task` fields with a `task_id`, e.g. { task_id: 3, task_status: 'DONE' }
+export async function updateTasks(updates) {
+  let response = await window.contract.update_tasks({
+    args: { updates: updates },
+    gas: BATCH_GAS
+  });
+  return response;
+}
+
+export async function deleteTasks(taskIds) {
+  let response = await window.contract.delete_tasks({
+    args: { task_ids: taskIds },
+    gas: BATCH_GAS
+  });
+  return response;
+}
+
+export async function archiveTask(taskId) {
+  let response = await window.contract.archive_task({
+    args: { task_id: taskId },
+    amount: STORAGE_DEPOSIT
+  });
+  return response;
+}
+
+export async function restoreTask(taskId) {
+  let response = await window.contract.restore_task({
+    args: { task_id: taskId },
+    amount: STORAGE_DEPOSIT
+  });
+  return response;
+}
+
+export async function getLists(accountId = window.accountId, fromIndex = 0, limit = 50) {
+  let lists = await window.contract.get_lists_for({ account_id: accountId, from_index: fromIndex, limit: limit });
+  return lists;
+}
+
+export async function getListTasks(listId, fromIndex = 0, limit = 50) {
+  let tasks = await window.contract.get_list_tasks({ list_id: listId, from_index: fromIndex, limit: limit });
+  return tasks;
+}
+
+export async function createList(name) {
+  let response = await window.contract.create_list({
+    args: { name: name },
+    amount: STORAGE_DEPOSIT
+  });
+  return response;
+}
+
+// `role` is one of 'VIEWER', 'EDITOR' or 'ADMIN'
+export async function addCollaborator(listId, accountId, role) {
+  let response = await window.contract.add_collaborator({
+    args: { list_id: listId, account_id: accountId, role: role },
+    amount: STORAGE_DEPOSIT
+  });
+  return response;
+}
+
+export async function removeCollaborator(listId, accountId) {
+  let response = await window.contract.remove_collaborator({
+    args: { list_id: listId, account_id: accountId }
+  });
+  return response;
+}
+
+export async function getAssignedTasks(accountId = window.accountId, fromIndex = 0, limit = 50) {
+  let tasks = await window.contract.get_assigned_tasks({ account_id: accountId, from_index: fromIndex, limit: limit });
+  return tasks;
+}
+
+export async function assignTask(taskId, accountId) {
+  let response = await window.contract.assign_task({
+    args: { task_id: taskId, account_id: accountId },
+    amount: STORAGE_DEPOSIT
+  });
+  return response;
+}
+
+export async function acceptTask(taskId) {
+  let response = await window.contract.accept_task({
+    args: { task_id: taskId }
+  });
+  return response;
+}
+
+export async function declineTask(taskId) {
+  let response = await window.contract.decline_task({
+    args: { task_id: taskId }
+  });
+  return response;
+}
+
+export async function getSubtasks(taskId) {
+  let subtasks = await window.contract.get_subtasks({ task_id: taskId });
+  return subtasks;
+}
+
+export async function getTaskProgress(taskId) {
+  let progress = await window.contract.get_task_progress({ task_id: taskId });
+  return progress;
+}
+
+// `force` completes the task even with open subtasks or unchecked items
+export async function completeTask(taskId, force = false) {
+  let response = await window.contract.complete_task({
+    args: { task_id: taskId, force: force },
+    amount: STORAGE_DEPOSIT
+  });
+  return response;
+}
+
+export async function addChecklistItem(taskId, text) {
+  let response = await window.contract.add_checklist_item({
+    args: { task_id: taskId, text: text },
+    amount: STORAGE_DEPOSIT
+  });
+  return response;
+}
+
+export async function checkChecklistItem(taskId, index, done) {
+  let response = await window.contract.check_checklist_item({
+    args: { task_id: taskId, index: index, done: done }
+  });
+  return response;
+}
+
+export async function removeChecklistItem(taskId, index) {
+  let response = await window.contract.remove_checklist_item({
+    args: { task_id: taskId, index: index }
+  });
+  return response;
+}
+
+export async function getBlockers(taskId) {
+  let blockers = await window.contract.get_blockers({ task_id: taskId });
+  return blockers;
+}
+
+export async function getDependents(taskId) {
+  let dependents = await window.contract.get_dependents({ task_id: taskId });
+  return dependents;
+}
+
+// `taskId` can't be started or finished before `blockerId` is done
+export async function addBlocker(taskId, blockerId) {
+  let response = await window.contract.add_blocker({
+    args: { task_id: taskId, blocker_id: blockerId },
+    amount: STORAGE_DEPOSIT
+  });
+  return response;
+}
+
+export async function removeBlocker(taskId, blockerId) {
+  let response = await window.contract.remove_blocker({
+    args: { task_id: taskId, blocker_id: blockerId }
+  });
+  return response;
+}
+
+export async function getBounty(taskId) {
+  let bounty = await window.contract.get_bounty({ task_id: taskId });
+  return bounty;
+}
+
+// `amount` in NEAR, `expiresAt` a block timestamp in nanoseconds, defaults to the due date
+export async function fundTask(taskId, amount, expiresAt = null) {
+  let response = await window.contract.fund_task({
+    args: { task_id: taskId, expires_at: expiresAt },
+    amount: utils.format.parseNearAmount(amount)
+  });
+  return response;
+}
+
+export async function releaseBounty(taskId) {
+  let response = await window.contract.release_bounty({
+    args: { task_id: taskId }
+  });
+  return response;
+}
+
+export async function withdrawUnclaimedBounty() {
+  let response = await window.contract.withdraw_unclaimed_bounty({
+    args: {}
+  });
+  return response;
+}
+
+export async function getTokenBounties(taskId) {
+  let bounties = await window.contract.get_token_bounties({ task_id: taskId });
+  return bounties;
+}
+
+export async function getBountyTokens(accountId = window.accountId) {
+  let tokens = await window.contract.get_bounty_tokens({ account_id: accountId });
+  return tokens;
+}
+
+// A token has to be allowed before it can fund the caller's tasks
+export async function allowBountyToken(tokenId) {
+  let response = await window.contract.allow_bounty_token({
+    args: { token_id: tokenId },
+    amount: STORAGE_DEPOSIT
+  });
+  return response;
+}
+
+export async function disallowBountyToken(tokenId) {
+  let response = await window.contract.disallow_bounty_token({
+    args: { token_id: tokenId }
+  });
+  return response;
+}
+
+// Funding with a token is a call to the token contract, which forwards `msg` to `ft_on_transfer`.
+// `amount` in the smallest unit of the token.
+export async function fundTaskWithToken(tokenId, taskId, amount) {
+  let response = await window.walletConnection.account().functionCall({
+    contractId: tokenId,
+    methodName: 'ft_transfer_call',
+    args: { receiver_id: window.contract.contractId, amount: amount, msg: JSON.stringify({ task_id: taskId }) },
+    gas: '100000000000000',
+    attachedDeposit: '1'
+  });
+  return response;
+}
+
+export async function getStake(taskId) {
+  let stake = await window.contract.get_stake({ task_id: taskId });
+  return stake;
+}
+
+// `amount` in NEAR, `deadline` a block timestamp in nanoseconds, defaults to the due date
+export async function stakeOnTask(taskId, amount, deadline = null) {
+  let response = await window.contract.stake_on_task({
+    args: { task_id: taskId, deadline: deadline },
+    amount: utils.format.parseNearAmount(amount)
+  });
+  return response;
+}
+
+export async function slashExpired(taskId) {
+  let response = await window.contract.slash_expired({
+    args: { task_id: taskId }
+  });
+  return response;
+}
+
+export async function getBadges(accountId = window.accountId) {
+  let badges = await window.contract.nft_tokens_for_owner({ account_id: accountId });
+  return badges;
+}
+
+export async function claimCompletionBadge(taskId) {
+  let response = await window.contract.claim_completion_badge({
+    args: { task_id: taskId },
+    amount: STORAGE_DEPOSIT
+  });
+  return response;
+}
+
+export async function getStorageBalance(accountId = window.accountId) {
+  let balance = await window.contract.storage_balance_of({ account_id: accountId });
+  return balance;
+}
+
+export async function depositStorage(amount) {
+  let response = await window.contract.storage_deposit({
+    args: {},
+    amount: utils.format.parseNearAmount(amount)
+  });
+  return response;
+}
+
+export async function withdrawStorage(amount) {
+  let response = await window.contract.storage_withdraw({
+    args: amount ? { amount: utils.format.parseNearAmount(amount) } : {},
+    amount: '1'
+  });
+  return response;
+}
@@ -1,17 +1,26 @@
 use std::{env, fs};
 use near_units::parse_near;
-use serde_json::json;
+use serde_json::{json, Value};
+use workspaces::operations::Function;
 use workspaces::prelude::*;
 use workspaces::{network::Sandbox, Account, Contract, Worker};
 
+// NEP-141 token used to fund bounties, built from `../test-token`
+const TEST_TOKEN_WASM: &str = "../test-token/target/wasm32-unknown-unknown/release/test_token.wasm";
+
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
     let wasm_arg: &str = &(env::args().nth(1).unwrap());
     let wasm_filepath = fs::canonicalize(env::current_dir()?.join(wasm_arg))?;
+    // wasm of the previously released contract, used to test upgrading its state
+    let old_wasm_arg: &str = &(env::args().nth(2).unwrap());
+    let old_wasm_filepath = fs::canonicalize(env::current_dir()?.join(old_wasm_arg))?;
 
     let worker = workspaces::sandbox().await?;
     let wasm = std::fs::read(wasm_filepath)?;
+    let old_wasm = std::fs::read(old_wasm_filepath)?;
     let contract = worker.dev_deploy(&wasm).await?;
+    let token = worker.dev_deploy(&fs::read(TEST_TOKEN_WASM)?).await?;
 
     // create accounts
     let account = worker.dev_create_account().await?;
@@ -21,10 +30,21 @@ async fn main() -> anyhow::Result<()> {
         .transact()
         .await?
         .into_result()?;
+    let bob = account
+        .create_subaccount(&worker, "bob")
+        .initial_balance(parse_near!("30 N"))
+        .transact()
+        .await?
+        .into_result()?;
 
     // begin tests
     test_default_message(&alice, &contract, &worker).await?;
     test_changes_message(&alice, &contract, &worker).await?;
+    test_token_bounty(&alice, &bob, &contract, &token, &worker).await?;
+    test_completion_badge(&alice, &bob, &contract, &worker).await?;
+    test_commitment_stake(&alice, &bob, &contract, &worker).await?;
+    test_batch_operations(&alice, &contract, &worker).await?;
+    test_upgrade_from_old_wasm(&alice, &old_wasm, &wasm, &worker).await?;
     Ok(())
 }
 
@@ -52,6 +72,7 @@ async fn test_changes_message(
 ) -> anyhow::Result<()> {
     user.call(&worker, contract.id(), "set_greeting")
         .args_json(json!({"message": "Howdy"}))?
+        .deposit(parse_near!("0.01 N"))
         .transact()
         .await?;
 
@@ -65,4 +86,375 @@ async fn test_changes_message(
     assert_eq!(message, "Howdy".to_string());
     println!("      Passed ✅ changes message");
     Ok(())
-}
\ No newline at end of file
+}
+
+async fn test_token_bounty(
+    owner: &Account,
+    assignee: &Account,
+    contract: &Contract,
+    token: &Contract,
+    worker: &Worker<Sandbox>,
+) -> anyhow::Result<()> {
+    let outcome = token
+        .call(&worker, "new")
+        .args_json(json!({ "owner_id": owner.id(), "total_supply": "1000000" }))?
+        .transact()
+        .await?;
+    assert!(outcome.is_success());
+    // the contract and the assignee need token accounts to receive the bounty
+    for account_id in [contract.id(), assignee.id()] {
+        let outcome = owner
+            .call(&worker, token.id(), "storage_deposit")
+            .args_json(json!({ "account_id": account_id }))?
+            .deposit(parse_near!("0.00125 N"))
+            .transact()
+            .await?;
+        assert!(outcome.is_success());
+    }
+
+    let task_id: u64 = owner
+        .call(&worker, contract.id(), "insert_task")
+        .args_json(json!({ "task_name": "paid_task" }))?
+        .deposit(parse_near!("0.01 N"))
+        .transact()
+        .await?
+        .json()?;
+    let outcome = owner
+        .call(&worker, contract.id(), "allow_bounty_token")
+        .args_json(json!({ "token_id": token.id() }))?
+        .transact()
+        .await?;
+    assert!(outcome.is_success());
+    let outcome = owner
+        .call(&worker, contract.id(), "assign_task")
+        .args_json(json!({ "task_id": task_id, "account_id": assignee.id() }))?
+        .transact()
+        .await?;
+    assert!(outcome.is_success());
+    let outcome = assignee
+        .call(&worker, contract.id(), "accept_task")
+        .args_json(json!({ "task_id": task_id }))?
+        .transact()
+        .await?;
+    assert!(outcome.is_success());
+
+    let outcome = owner
+        .call(&worker, token.id(), "ft_transfer_call")
+        .args_json(json!({
+            "receiver_id": contract.id(),
+            "amount": "100",
+            "msg": json!({ "task_id": task_id }).to_string(),
+        }))?
+        .deposit(1)
+        .max_gas()
+        .transact()
+        .await?;
+    assert!(outcome.is_success());
+    let bounties: Vec<Value> = contract
+        .view(
+            &worker,
+            "get_token_bounties",
+            json!({ "task_id": task_id }).to_string().into_bytes(),
+        )
+        .await?
+        .json()?;
+    assert_eq!(bounties.len(), 1);
+    assert_eq!(bounties[0]["token_id"], token.id().as_str());
+    assert_eq!(bounties[0]["amount"], "100");
+
+    // finishing the task pays the bounty to the assignee
+    let outcome = owner
+        .call(&worker, contract.id(), "update_task")
+        .args_json(json!({ "task_id": task_id, "task_status": "DONE" }))?
+        .max_gas()
+        .transact()
+        .await?;
+    assert!(outcome.is_success());
+    let balance: String = token
+        .view(
+            &worker,
+            "ft_balance_of",
+            json!({ "account_id": assignee.id() })
+                .to_string()
+                .into_bytes(),
+        )
+        .await?
+        .json()?;
+    assert_eq!(balance, "100");
+    println!("      Passed ✅ pays token bounties");
+    Ok(())
+}
+
+async fn test_completion_badge(
+    owner: &Account,
+    receiver: &Account,
+    contract: &Contract,
+    worker: &Worker<Sandbox>,
+) -> anyhow::Result<()> {
+    let task_id: u64 = owner
+        .call(&worker, contract.id(), "insert_task")
+        .args_json(json!({ "task_name": "badge_task" }))?
+        .deposit(parse_near!("0.01 N"))
+        .transact()
+        .await?
+        .json()?;
+    let outcome = owner
+        .call(&worker, contract.id(), "update_task")
+        .args_json(json!({ "task_id": task_id, "task_status": "DONE" }))?
+        .transact()
+        .await?;
+    assert!(outcome.is_success());
+    let outcome = owner
+        .call(&worker, contract.id(), "claim_completion_badge")
+        .args_json(json!({ "task_id": task_id }))?
+        .deposit(parse_near!("0.01 N"))
+        .transact()
+        .await?;
+    assert!(outcome.is_success());
+
+    let token: Value = contract
+        .view(
+            &worker,
+            "nft_token",
+            json!({ "token_id": task_id.to_string() })
+                .to_string()
+                .into_bytes(),
+        )
+        .await?
+        .json()?;
+    assert_eq!(token["owner_id"], owner.id().as_str());
+    assert_eq!(token["metadata"]["title"], "Completed: badge_task");
+
+    // badges stay with the owner of the task
+    let outcome = owner
+        .call(&worker, contract.id(), "nft_transfer")
+        .args_json(json!({ "receiver_id": receiver.id(), "token_id": task_id.to_string() }))?
+        .deposit(1)
+        .transact()
+        .await?;
+    assert!(outcome.is_failure());
+    println!("      Passed ✅ mints completion badges");
+    Ok(())
+}
+
+async fn stake_on_new_task(
+    owner: &Account,
+    contract: &Contract,
+    task_name: &str,
+    worker: &Worker<Sandbox>,
+) -> anyhow::Result<u64> {
+    let task_id: u64 = owner
+        .call(&worker, contract.id(), "insert_task")
+        .args_json(json!({ "task_name": task_name }))?
+        .deposit(parse_near!("0.01 N"))
+        .transact()
+        .await?
+        .json()?;
+    let task: Value = contract
+        .view(
+            &worker,
+            "get_task",
+            json!({ "task_id": task_id }).to_string().into_bytes(),
+        )
+        .await?
+        .json()?;
+    // one minute after the task was created
+    let created_at: u64 = task["created_at"].as_str().unwrap().parse()?;
+    let deadline = created_at + 60_000_000_000;
+    let outcome = owner
+        .call(&worker, contract.id(), "stake_on_task")
+        .args_json(json!({ "task_id": task_id, "deadline": deadline.to_string() }))?
+        .deposit(parse_near!("1 N"))
+        .transact()
+        .await?;
+    assert!(outcome.is_success());
+    Ok(task_id)
+}
+
+async fn test_commitment_stake(
+    owner: &Account,
+    beneficiary: &Account,
+    contract: &Contract,
+    worker: &Worker<Sandbox>,
+) -> anyhow::Result<()> {
+    let outcome = contract
+        .call(&worker, "set_stake_beneficiary")
+        .args_json(json!({ "account_id": beneficiary.id() }))?
+        .transact()
+        .await?;
+    assert!(outcome.is_success());
+
+    // finishing in time returns the stake
+    let task_id = stake_on_new_task(owner, contract, "stake_kept", worker).await?;
+    let balance = worker.view_account(owner.id()).await?.balance;
+    let outcome = owner
+        .call(&worker, contract.id(), "update_task")
+        .args_json(json!({ "task_id": task_id, "task_status": "DONE" }))?
+        .transact()
+        .await?;
+    assert!(outcome.is_success());
+    assert!(worker.view_account(owner.id()).await?.balance > balance + parse_near!("0.9 N"));
+
+    // missing the deadline lets anyone slash the stake
+    let task_id = stake_on_new_task(owner, contract, "stake_missed", worker).await?;
+    let outcome = beneficiary
+        .call(&worker, contract.id(), "slash_expired")
+        .args_json(json!({ "task_id": task_id }))?
+        .transact()
+        .await?;
+    assert!(outcome.is_failure());
+    worker.fast_forward(1_000).await?;
+    let balance = worker.view_account(beneficiary.id()).await?.balance;
+    let outcome = beneficiary
+        .call(&worker, contract.id(), "slash_expired")
+        .args_json(json!({ "task_id": task_id }))?
+        .transact()
+        .await?;
+    assert!(outcome.is_success());
+    assert!(worker.view_account(beneficiary.id()).await?.balance > balance + parse_near!("0.9 N"));
+    let stake: Value = contract
+        .view(
+            &worker,
+            "get_stake",
+            json!({ "task_id": task_id }).to_string().into_bytes(),
+        )
+        .await?
+        .json()?;
+    assert!(stake.is_null());
+    println!("      Passed ✅ slashes missed stakes");
+    Ok(())
+}
+
+async fn test_batch_operations(
+    user: &Account,
+    contract: &Contract,
+    worker: &Worker<Sandbox>,
+) -> anyhow::Result<()> {
+    // a full batch fits into the gas of a single call
+    let tasks: Vec<Value> = (0..20)
+        .map(|i| json!({ "task_name": format!("batch_{}", i), "tags": ["batch"] }))
+        .collect();
+    let task_ids: Vec<u64> = user
+        .call(&worker, contract.id(), "insert_tasks")
+        .args_json(json!({ "tasks": tasks }))?
+        .deposit(parse_near!("0.5 N"))
+        .max_gas()
+        .transact()
+        .await?
+        .json()?;
+    assert_eq!(task_ids.len(), 20);
+
+    let updates: Vec<Value> = task_ids
+        .iter()
+        .map(|task_id| json!({ "task_id": task_id, "task_status": "DONE" }))
+        .collect();
+    let outcome = user
+        .call(&worker, contract.id(), "update_tasks")
+        .args_json(json!({ "updates": updates }))?
+        .max_gas()
+        .transact()
+        .await?;
+    assert!(outcome.is_success());
+
+    // deleting a missing task fails the whole batch
+    let mut delete_ids = task_ids.clone();
+    delete_ids.push(u64::MAX);
+    let outcome = user
+        .call(&worker, contract.id(), "delete_tasks")
+        .args_json(json!({ "task_ids": delete_ids }))?
+        .max_gas()
+        .transact()
+        .await?;
+    assert!(outcome.is_failure());
+    let task: Value = contract
+        .view(
+            &worker,
+            "get_task",
+            json!({ "task_id": task_ids[0] }).to_string().into_bytes(),
+        )
+        .await?
+        .json()?;
+    assert_eq!(task["task_status"], "DONE");
+
+    let outcome = user
+        .call(&worker, contract.id(), "delete_tasks")
+        .args_json(json!({ "task_ids": task_ids }))?
+        .max_gas()
+        .transact()
+        .await?;
+    assert!(outcome.is_success());
+    println!("      Passed ✅ applies batches all or nothing");
+    Ok(())
+}
+
+async fn test_upgrade_from_old_wasm(
+    user: &Account,
+    old_wasm: &[u8],
+    wasm: &[u8],
+    worker: &Worker<Sandbox>,
+) -> anyhow::Result<()> {
+    let contract = worker.dev_deploy(old_wasm).await?;
+
+    // write tasks with the string ids of the old layout
+    for task_name in ["task_a", "task_b"] {
+        let outcome = user
+            .call(&worker, contract.id(), "insert_task")
+            .args_json(json!({ "task_name": task_name }))?
+            .transact()
+            .await?;
+        assert!(outcome.is_success());
+    }
+    let outcome = user
+        .call(&worker, contract.id(), "update_task")
+        .args_json(json!({"task_name": "task_b", "task_status": "DONE"}))?
+        .transact()
+        .await?;
+    assert!(outcome.is_success());
+
+    // deploy the new code and migrate the state in a single transaction
+    let outcome = contract
+        .as_account()
+        .batch(&worker, contract.id())
+        .deploy(wasm)
+        .call(Function::new("migrate").args_json(json!({}))?)
+        .transact()
+        .await?;
+    assert!(outcome.is_success());
+
+    let migrated: Vec<u64> = user
+        .call(&worker, contract.id(), "migrate_legacy_tasks")
+        .args_json(json!({ "account_id": user.id() }))?
+        .transact()
+        .await?
+        .json()?;
+    assert_eq!(migrated, vec![0, 1]);
+
+    let task_id: Option<u64> = contract
+        .view(
+            &worker,
+            "get_migrated_task_id",
+            json!({ "legacy_task_id": format!("{}.task_b", user.id()) })
+                .to_string()
+                .into_bytes(),
+        )
+        .await?
+        .json()?;
+    assert_eq!(task_id, Some(1));
+
+    let tasks: Vec<Value> = contract
+        .view(
+            &worker,
+            "get_tasks_for",
+            json!({ "account_id": user.id() }).to_string().into_bytes(),
+        )
+        .await?
+        .json()?;
+    assert_eq!(tasks.len(), 2);
+    assert_eq!(tasks[0]["task_name"], "task_a");
+    assert_eq!(tasks[0]["task_status"], "TODO");
+    assert_eq!(tasks[1]["task_name"], "task_b");
+    assert_eq!(tasks[1]["task_status"], "DONE");
+    assert_eq!(tasks[1]["owner_id"], user.id().as_str());
+    println!("      Passed ✅ upgrades state of the old contract");
+    Ok(())
+}